@@ -0,0 +1,591 @@
+// ANSI 轉義序列相關的字串工具
+// 高亮後的輸出包含 `\x1b[...m` 等控制序列，計算欄位時需要跳過它們
+
+use unicode_width::UnicodeWidthChar;
+
+/// 重設所有樣式
+pub const RESET: &str = "\x1b[0m";
+
+/// OSC 133 shell integration：命令輸出開始 / 結束標記
+pub const OSC133_OUTPUT_START: &str = "\x1b]133;C\x07";
+pub const OSC133_OUTPUT_END: &str = "\x1b]133;D\x07";
+
+/// 結束 OSC 8 超連結
+pub const OSC8_CLOSE: &str = "\x1b]8;;\x1b\\";
+
+/// 以 OSC 133 標記包住輸出：建立時寫入開始標記，離開作用域時寫入結束標記
+///
+/// 即使中途發生錯誤，結束標記也會寫出，終端機不會停在「輸出中」的狀態
+pub struct Osc133<W: std::io::Write> {
+    out: W,
+}
+
+impl<W: std::io::Write> Osc133<W> {
+    pub fn new(mut out: W) -> Self {
+        let _ = out.write_all(OSC133_OUTPUT_START.as_bytes());
+        Osc133 { out }
+    }
+
+    #[cfg(test)]
+    pub fn writer(&mut self) -> &mut W {
+        &mut self.out
+    }
+}
+
+impl<W: std::io::Write> Drop for Osc133<W> {
+    fn drop(&mut self) {
+        let _ = self.out.write_all(OSC133_OUTPUT_END.as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+/// 回傳從 `s[start..]` 開始的 ANSI 轉義序列長度（不是轉義序列則回傳 None）
+pub fn escape_len(s: &str, start: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.get(start) != Some(&0x1b) {
+        return None;
+    }
+
+    match bytes.get(start + 1) {
+        // CSI：ESC [ ... 終止字元（0x40..=0x7e）
+        Some(b'[') => {
+            let mut i = start + 2;
+            while i < bytes.len() {
+                if (0x40..=0x7e).contains(&bytes[i]) {
+                    return Some(i + 1 - start);
+                }
+                i += 1;
+            }
+            Some(bytes.len() - start)
+        }
+        // OSC：ESC ] ... BEL 或 ESC \
+        Some(b']') => {
+            let mut i = start + 2;
+            while i < bytes.len() {
+                if bytes[i] == 0x07 {
+                    return Some(i + 1 - start);
+                }
+                if bytes[i] == 0x1b && bytes.get(i + 1) == Some(&b'\\') {
+                    return Some(i + 2 - start);
+                }
+                i += 1;
+            }
+            Some(bytes.len() - start)
+        }
+        Some(_) => Some(2),
+        None => Some(1),
+    }
+}
+
+/// 字元的顯示寬度（控制字元視為 0，CJK 全形字元為 2）
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// 字串的顯示寬度（跳過 ANSI 轉義序列）
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            i += len;
+            continue;
+        }
+        let c = s[i..].chars().next().unwrap();
+        width += char_width(c);
+        i += c.len_utf8();
+    }
+
+    width
+}
+
+/// 去掉所有 ANSI 轉義序列，只留下文字
+pub fn strip_escapes(s: &str) -> String {
+    let mut plain = String::with_capacity(s.len());
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            i += len;
+            continue;
+        }
+        let c = s[i..].chars().next().unwrap();
+        plain.push(c);
+        i += c.len_utf8();
+    }
+
+    plain
+}
+
+/// 將超過 `max_width` 欄的字串截斷，並預留 `marker_width` 欄給截斷標記
+///
+/// 未超過寬度時回傳 None；截斷處之前的轉義序列會保留，並在結尾重設樣式（與未結束的超連結）
+pub fn truncate(s: &str, max_width: usize, marker_width: usize) -> Option<String> {
+    if visible_width(s) <= max_width {
+        return None;
+    }
+
+    let budget = max_width.saturating_sub(marker_width);
+    let mut output = String::new();
+    let mut width = 0;
+    let mut styled = false;
+    let mut linked = false;
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            let sequence = &s[i..i + len];
+            if sequence.starts_with("\x1b]8;") {
+                linked = sequence != OSC8_CLOSE && sequence != "\x1b]8;;\x07";
+            }
+            output.push_str(sequence);
+            styled = true;
+            i += len;
+            continue;
+        }
+
+        let c = s[i..].chars().next().unwrap();
+        let w = char_width(c);
+        // 不把全形字元切成一半
+        if width + w > budget {
+            break;
+        }
+        output.push(c);
+        width += w;
+        i += c.len_utf8();
+    }
+
+    if styled {
+        output.push_str(RESET);
+    }
+    if linked {
+        output.push_str(OSC8_CLOSE);
+    }
+
+    Some(output)
+}
+
+/// 開始指向 `target` 的 OSC 8 超連結
+pub fn osc8_open(target: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", target)
+}
+
+/// 將已上色的字串依顯示寬度切成多列，每列不超過 `width` 欄
+///
+/// 零寬字元（例如組合字元）留在前一個字元的同一列；全形字元不會被切半。
+/// 切斷處結束目前的樣式，下一列開頭重新套用自上次重設以來的 SGR 序列
+pub fn wrap(s: &str, width: usize) -> Vec<String> {
+    if width == 0 || visible_width(s) <= width {
+        return vec![s.to_string()];
+    }
+
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut row_width = 0;
+    let mut active: Vec<&str> = Vec::new();
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            let sequence = &s[i..i + len];
+            if sequence == RESET || sequence == "\x1b[m" {
+                active.clear();
+            } else if sequence.starts_with("\x1b[") && sequence.ends_with('m') {
+                active.push(sequence);
+            }
+            row.push_str(sequence);
+            i += len;
+            continue;
+        }
+
+        let c = s[i..].chars().next().unwrap();
+        let w = char_width(c);
+        if w > 0 && row_width > 0 && row_width + w > width {
+            if !active.is_empty() {
+                row.push_str(RESET);
+            }
+            rows.push(std::mem::replace(&mut row, active.concat()));
+            row_width = 0;
+        }
+        row.push(c);
+        row_width += w;
+        i += c.len_utf8();
+    }
+    rows.push(row);
+    rows
+}
+
+/// 將顏色名稱（或原始 SGR 參數，例如 `1;31`）轉為 SGR 參數
+pub fn color_sgr(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    let sgr = match name.as_str() {
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        "gray" | "grey" => "90",
+        other if !other.is_empty() && other.chars().all(|c| c.is_ascii_digit() || c == ';') => {
+            other
+        }
+        _ => return None,
+    };
+    Some(sgr.to_string())
+}
+
+/// 以 SGR 參數為文字上色
+pub fn paint(text: &str, sgr: &str) -> String {
+    format!("\x1b[{}m{}{}", sgr, text, RESET)
+}
+
+/// 為已上色的字串疊加樣式（例如反白）：每個 SGR 序列之後重新套用，避免被中途的重設清掉
+pub fn overlay(s: &str, sgr: &str) -> String {
+    let style = format!("\x1b[{}m", sgr);
+    let mut output = String::with_capacity(s.len() + style.len() * 2);
+    output.push_str(&style);
+    let mut i = 0;
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            let sequence = &s[i..i + len];
+            output.push_str(sequence);
+            if sequence.starts_with("\x1b[") && sequence.ends_with('m') {
+                output.push_str(&style);
+            }
+            i += len;
+            continue;
+        }
+        let c = s[i..].chars().next().unwrap();
+        output.push(c);
+        i += c.len_utf8();
+    }
+    output.push_str(RESET);
+    output
+}
+
+/// 在已上色的字串中展開 tab（跳過 ANSI 轉義序列計算欄位）
+pub fn expand_tabs(s: &str, tab_width: usize) -> String {
+    if tab_width == 0 || !s.contains('\t') {
+        return s.to_string();
+    }
+
+    let mut output = String::with_capacity(s.len());
+    let mut column = 0;
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            output.push_str(&s[i..i + len]);
+            i += len;
+            continue;
+        }
+
+        let c = s[i..].chars().next().unwrap();
+        if c == '\t' {
+            let spaces = tab_width - column % tab_width;
+            output.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            output.push(c);
+            column += char_width(c);
+        }
+        i += c.len_utf8();
+    }
+
+    output
+}
+
+/// 將字串中 SGR 序列的真彩色（`38;2;r;g;b`）與 256 色（`38;5;n`）前景/背景
+/// 降為最接近的 16 色（`30`–`37`、`90`–`97`，背景為 `40`–`47`、`100`–`107`）
+///
+/// 只處理字串，已上色的直通內容也適用；其他序列與 SGR 參數原樣保留
+pub fn flatten_to_16(s: &str) -> String {
+    if !s.contains('\x1b') {
+        return s.to_string();
+    }
+
+    let mut output = String::with_capacity(s.len());
+    let mut i = 0;
+
+    while i < s.len() {
+        if let Some(len) = escape_len(s, i) {
+            let seq = &s[i..i + len];
+            match seq
+                .strip_prefix("\x1b[")
+                .and_then(|rest| rest.strip_suffix('m'))
+            {
+                Some(params) => {
+                    output.push_str("\x1b[");
+                    output.push_str(&flatten_sgr_params(params));
+                    output.push('m');
+                }
+                None => output.push_str(seq),
+            }
+            i += len;
+            continue;
+        }
+
+        let c = s[i..].chars().next().unwrap();
+        output.push(c);
+        i += c.len_utf8();
+    }
+
+    output
+}
+
+/// 改寫一組 SGR 參數（不含 `ESC [` 與 `m`）中的延伸顏色
+fn flatten_sgr_params(params: &str) -> String {
+    let parts: Vec<&str> = params.split(';').collect();
+    let mut output: Vec<String> = Vec::with_capacity(parts.len());
+    let mut i = 0;
+
+    while i < parts.len() {
+        let base = match parts[i] {
+            "38" => Some(30),
+            "48" => Some(40),
+            _ => None,
+        };
+        let color = base.and_then(|_| match parts.get(i + 1).copied() {
+            Some("5") => {
+                let n = parts.get(i + 2)?.parse::<u8>().ok()?;
+                Some((n, 3))
+            }
+            Some("2") => {
+                let rgb = parts.get(i + 2..i + 5)?;
+                let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| c.parse::<u8>().ok());
+                Some((ansi_colours::ansi256_from_rgb((r?, g?, b?)), 5))
+            }
+            _ => None,
+        });
+
+        match (base, color) {
+            (Some(base), Some((n, consumed))) => {
+                output.push(sgr_16(base, nearest_16(n)).to_string());
+                i += consumed;
+            }
+            _ => {
+                output.push(parts[i].to_string());
+                i += 1;
+            }
+        }
+    }
+
+    output.join(";")
+}
+
+/// 16 色中的黑、灰、白
+const GREYS_16: [u8; 4] = [0, 8, 7, 15];
+
+/// 16 色索引的 SGR 參數：`base` 為 30（前景）或 40（背景），亮色另加 60
+pub fn sgr_16(base: u8, index: u8) -> u8 {
+    if index < 8 {
+        base + index
+    } else {
+        base + 60 + index - 8
+    }
+}
+
+/// 256 色索引對應到最接近的 16 色索引（0–15 原樣回傳）
+fn nearest_16(n: u8) -> u8 {
+    if n < 16 {
+        return n;
+    }
+    ansi16_from_rgb(ansi_colours::rgb_from_ansi256(n))
+}
+
+/// RGB 對應到最接近的 16 色索引
+///
+/// 主題常用的淡色在 RGB 距離上往往最接近灰色或別的色相，因此有明顯彩度的顏色
+/// 先依色相選出六種彩色之一，再依亮度決定一般色或亮色；其餘的取最接近的灰階
+pub fn ansi16_from_rgb((r, g, b): (u8, u8, u8)) -> u8 {
+    let d = |a: i32, b: i32| (a - b).pow(2);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+
+    if max - min < 40 {
+        return GREYS_16
+            .into_iter()
+            .min_by_key(|&i| {
+                let (pr, pg, pb) = ansi_colours::rgb_from_ansi256(i);
+                let [r, g, b, pr, pg, pb] = [r, g, b, pr, pg, pb].map(i32::from);
+                d(r, pr) + d(g, pg) + d(b, pb)
+            })
+            .unwrap();
+    }
+
+    // 去掉灰階成分後放大到 0–255，與紅、綠、黃、藍、洋紅、青（索引 1–6）比較
+    let chroma = [r, g, b].map(|c| i32::from(c - min) * 255 / i32::from(max - min));
+    let hue = (1..=6u8)
+        .min_by_key(|&i| {
+            let bits = [i & 1, i & 2, i & 4].map(|bit| if bit != 0 { 255 } else { 0 });
+            (0..3).map(|c| d(chroma[c], bits[c])).sum::<i32>()
+        })
+        .unwrap();
+    let lightness = (u16::from(max) + u16::from(min)) / 2;
+    if lightness >= 120 {
+        hue + 8
+    } else {
+        hue
+    }
+}
+
+/// 依顯示寬度在空白處換行（純文字，不含轉義序列）；比寬度長的單字獨佔一行，不會被切開
+pub fn word_wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for word in text.split_whitespace() {
+        let word_width = visible_width(word);
+        if !line.is_empty() && line_width + 1 + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(word);
+        line_width += word_width;
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc133_marks_surround_content() {
+        use std::io::Write;
+
+        let mut buffer = Vec::new();
+        {
+            let mut marks = Osc133::new(&mut buffer);
+            marks.writer().write_all(b"hello\n").unwrap();
+        }
+
+        let output = String::from_utf8(buffer).unwrap();
+        assert_eq!(
+            output,
+            format!("{}hello\n{}", OSC133_OUTPUT_START, OSC133_OUTPUT_END)
+        );
+        assert_eq!(visible_width(&output), visible_width("hello\n"));
+    }
+
+    #[test]
+    fn test_overlay_survives_resets() {
+        let colored = "\x1b[38;5;1mab\x1b[0m c";
+        assert_eq!(
+            overlay(colored, "7"),
+            "\x1b[7m\x1b[38;5;1m\x1b[7mab\x1b[0m\x1b[7m c\x1b[0m"
+        );
+        assert_eq!(strip_escapes(&overlay(colored, "7")), "ab c");
+    }
+
+    #[test]
+    fn test_expand_tabs_skips_escapes() {
+        let colored = "\x1b[38;5;1mab\x1b[0m\tc";
+        assert_eq!(expand_tabs(colored, 4), "\x1b[38;5;1mab\x1b[0m  c");
+    }
+
+    #[test]
+    fn test_truncate_makes_room_for_marker() {
+        // 寬度 8，標記 "..." 佔 3 欄，只保留 5 欄內容
+        assert_eq!(truncate("abcdefghijkl", 8, 3).unwrap(), "abcde");
+        assert_eq!(truncate("short", 8, 3), None);
+
+        // 轉義序列不計寬度，截斷後重設樣式
+        let colored = "\x1b[31mabcdefghij\x1b[0m";
+        assert_eq!(truncate(colored, 6, 2).unwrap(), "\x1b[31mabcd\x1b[0m");
+
+        // 全形字元不會被切半
+        assert_eq!(truncate("中文字元測試", 6, 1).unwrap(), "中文");
+
+        // 在超連結中截斷時一併結束超連結
+        let linked = format!(
+            "{}https://example.com{}",
+            osc8_open("https://example.com"),
+            OSC8_CLOSE
+        );
+        assert_eq!(
+            truncate(&linked, 5, 0).unwrap(),
+            format!(
+                "{}https{}{}",
+                osc8_open("https://example.com"),
+                RESET,
+                OSC8_CLOSE
+            )
+        );
+    }
+
+    #[test]
+    fn test_wrap_keeps_styles_across_rows() {
+        assert_eq!(wrap("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(wrap("short", 10), vec!["short"]);
+
+        // 切斷處重設樣式，下一列重新套用
+        let colored = "\x1b[1m\x1b[31mabcd\x1b[0mef";
+        assert_eq!(
+            wrap(colored, 3),
+            vec!["\x1b[1m\x1b[31mabc\x1b[0m", "\x1b[1m\x1b[31md\x1b[0mef"]
+        );
+
+        // 全形字元不會被切半，組合字元留在同一列
+        assert_eq!(wrap("中文字", 5), vec!["中文", "字"]);
+        assert_eq!(wrap("abe\u{301}f", 3), vec!["abe\u{301}", "f"]);
+    }
+
+    #[test]
+    fn test_word_wrap() {
+        assert_eq!(
+            word_wrap("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            word_wrap("a verylongword b", 4),
+            vec!["a", "verylongword", "b"]
+        );
+        // 全形字元佔兩欄
+        assert_eq!(word_wrap("中文 字元", 4), vec!["中文", "字元"]);
+    }
+
+    #[test]
+    fn test_visible_width() {
+        assert_eq!(visible_width("\x1b[1mab\x1b[0m中"), 4);
+        assert_eq!(strip_escapes("\x1b[1mab\x1b[0m中"), "ab中");
+    }
+
+    #[test]
+    fn test_expand_tabs_stops() {
+        assert_eq!(expand_tabs("\tx\ty", 4), "    x   y");
+        assert_eq!(expand_tabs("abcd\te", 4), "abcd    e");
+        assert_eq!(expand_tabs("a\tb", 0), "a\tb");
+    }
+
+    #[test]
+    fn test_flatten_to_16() {
+        // 真彩色紅色前景 → 亮紅（91），並保留其他參數
+        assert_eq!(
+            flatten_to_16("\x1b[1;38;2;255;0;0mred\x1b[0m"),
+            "\x1b[1;91mred\x1b[0m"
+        );
+        // 256 色與背景色
+        assert_eq!(flatten_to_16("\x1b[38;5;2mx"), "\x1b[32mx");
+        assert_eq!(flatten_to_16("\x1b[48;2;0;0;0mx"), "\x1b[40mx");
+        assert_eq!(flatten_to_16("\x1b[38;5;231mx"), "\x1b[97mx");
+        // 淡紫色不會被歸到灰色
+        assert_eq!(flatten_to_16("\x1b[38;2;204;153;204mx"), "\x1b[95mx");
+        assert_eq!(flatten_to_16("\x1b[38;2;128;0;0mx"), "\x1b[31mx");
+        assert_eq!(flatten_to_16("\x1b[38;2;116;115;105mx"), "\x1b[90mx");
+        // 其他序列與純文字不變
+        assert_eq!(flatten_to_16("\x1b]133;C\x07a\tb"), "\x1b]133;C\x07a\tb");
+        assert_eq!(flatten_to_16("\x1b[38;2;1mx"), "\x1b[38;2;1mx");
+    }
+}
@@ -0,0 +1,73 @@
+// 內容雜湊（--checksum）：比對轉碼前後的內容是否一致
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// 支援的雜湊演算法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "md5" => Ok(ChecksumAlgorithm::Md5),
+            "sha1" => Ok(ChecksumAlgorithm::Sha1),
+            "sha256" => Ok(ChecksumAlgorithm::Sha256),
+            _ => Err(format!(
+                "Invalid value '{}' for --checksum (expected md5, sha1 or sha256)",
+                s
+            )),
+        }
+    }
+}
+
+impl ChecksumAlgorithm {
+    /// 計算資料的雜湊值（小寫十六進位）
+    pub fn hex_digest(self, data: &[u8]) -> String {
+        match self {
+            ChecksumAlgorithm::Md5 => to_hex(&Md5::digest(data)),
+            ChecksumAlgorithm::Sha1 => to_hex(&Sha1::digest(data)),
+            ChecksumAlgorithm::Sha256 => to_hex(&Sha256::digest(data)),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let data = b"hello world";
+        assert_eq!(
+            ChecksumAlgorithm::Md5.hex_digest(data),
+            "5eb63bbbe01eeed093cb22bb8f5acdc3"
+        );
+        assert_eq!(
+            ChecksumAlgorithm::Sha1.hex_digest(data),
+            "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"
+        );
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.hex_digest(data),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!("SHA256".parse(), Ok(ChecksumAlgorithm::Sha256));
+        assert!("crc32".parse::<ChecksumAlgorithm>().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+// 與參考檔案逐行比較（--against），找出新內容中有變更的行
+
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::collections::BTreeMap;
+
+/// 相對於參考檔案的變更種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineChange {
+    Added,    // 參考檔案中沒有對應的行
+    Modified, // 取代了參考檔案中的行
+}
+
+impl LineChange {
+    /// 行首的變更標記
+    pub fn marker(self) -> char {
+        match self {
+            LineChange::Added => '+',
+            LineChange::Modified => '~',
+        }
+    }
+}
+
+/// 比較兩份內容，回傳新內容中有變更的行（行號從 1 開始）
+///
+/// 行尾（LF / CRLF）不影響比較；只存在於參考檔案中的行（刪除）沒有對應的行號，不會列出
+pub fn changed_lines(reference: &str, content: &str) -> BTreeMap<usize, LineChange> {
+    let old: Vec<&str> = reference.lines().collect();
+    let new: Vec<&str> = content.lines().collect();
+
+    let mut changes = BTreeMap::new();
+    for op in capture_diff_slices(Algorithm::Myers, &old, &new) {
+        let (tag, _, new_range) = op.as_tag_tuple();
+        let change = match tag {
+            DiffTag::Insert => LineChange::Added,
+            DiffTag::Replace => LineChange::Modified,
+            DiffTag::Equal | DiffTag::Delete => continue,
+        };
+        changes.extend(new_range.map(|index| (index + 1, change)));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_lines() {
+        let reference = "a\nb\nc\nd\n";
+        let content = "a\nB\nc\nd\ne\n";
+
+        let changes = changed_lines(reference, content);
+        assert_eq!(
+            changes.into_iter().collect::<Vec<_>>(),
+            vec![(2, LineChange::Modified), (5, LineChange::Added)]
+        );
+    }
+
+    #[test]
+    fn test_line_endings_are_ignored() {
+        assert!(changed_lines("a\r\nb", "a\nb\n").is_empty());
+    }
+}
@@ -1,58 +1,677 @@
 use anyhow::{Context, Result};
-use encoding_rs::Encoding;
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, DecoderResult, EncoderResult, Encoding};
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::path::Path;
+use std::str::FromStr;
 
 /// 編碼檢測結果
 #[derive(Debug)]
 pub struct DetectedEncoding {
     pub encoding: &'static Encoding,
     pub confidence: EncodingConfidence,
+    pub had_errors: bool,          // 解碼時是否有無法轉換的字元
+    pub error_offsets: Vec<usize>, // 前幾個無法轉換的位元組偏移（最多 MAX_ERROR_OFFSETS 個）
 }
 
-#[derive(Debug, PartialEq)]
+/// DetectedEncoding 最多記錄的錯誤位置數
+pub const MAX_ERROR_OFFSETS: usize = 5;
+
+/// 記錄解碼結果：有錯誤時找出前幾個錯誤位置
+fn with_errors(detected: DetectedEncoding, bytes: &[u8], had_errors: bool) -> DetectedEncoding {
+    let error_offsets = if had_errors {
+        invalid_offsets(bytes, detected.encoding, MAX_ERROR_OFFSETS)
+    } else {
+        Vec::new()
+    };
+    DetectedEncoding {
+        had_errors,
+        error_offsets,
+        ..detected
+    }
+}
+
+/// 編碼偵測的信心，由低到高排序（--min-confidence 依此比較）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum EncodingConfidence {
-    Certain, // BOM 或用戶指定
+    Low,     // 統計偵測的證據不足，或回退到系統編碼
+    Medium,  // 統計偵測（chardetng）有把握，且看到足夠的非 ASCII 位元組
     High,    // UTF-8 檢測成功
-    Low,     // 回退到系統編碼
+    Certain, // BOM 或用戶指定
+}
+
+impl FromStr for EncodingConfidence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(EncodingConfidence::Low),
+            "medium" => Ok(EncodingConfidence::Medium),
+            "high" => Ok(EncodingConfidence::High),
+            _ => Err(format!(
+                "Invalid value '{}' for --min-confidence (expected low, medium or high)",
+                s
+            )),
+        }
+    }
+}
+
+/// --min-confidence：統計偵測的信心低於 `min` 時不採用猜測的編碼，改用系統編碼；
+/// `strict`（--strict-confidence）時改為回報錯誤
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfidenceThreshold {
+    pub min: EncodingConfidence,
+    pub strict: bool,
+}
+
+impl Default for ConfidenceThreshold {
+    /// 預設採用所有猜測（短檔案的 Latin-1 等傳統編碼也不會被當成系統編碼）
+    fn default() -> Self {
+        ConfidenceThreshold {
+            min: EncodingConfidence::Low,
+            strict: false,
+        }
+    }
+}
+
+/// 遇到無法解碼的位元組時的處理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InvalidMode {
+    #[default]
+    Replace, // 以 U+FFFD 取代（預設）
+    Skip,  // 直接丟棄
+    Error, // 回報錯誤位置並中止
+}
+
+impl FromStr for InvalidMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "replace" => Ok(InvalidMode::Replace),
+            "skip" => Ok(InvalidMode::Skip),
+            "error" => Ok(InvalidMode::Error),
+            _ => Err(format!(
+                "Invalid value '{}' for --invalid (expected replace, skip or error)",
+                s
+            )),
+        }
+    }
+}
+
+/// 解碼後開頭 U+FEFF（BOM）的處理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BomMode {
+    #[default]
+    Default, // 偵測到的 BOM 由解碼器移除，其餘的 U+FEFF 保留
+    Strip, // 連同解碼後殘留的 U+FEFF 一併移除（--strip-bom）
+    Keep,  // 保留檔案開頭的 BOM，輸出為 U+FEFF（--keep-bom）
+}
+
+/// 解碼選項
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub invalid: InvalidMode,
+    pub replacement: Option<char>, // 以此字元取代 U+FFFD（只在有解碼錯誤時套用）
+    pub detection_bytes: Option<usize>, // 編碼偵測最多檢查的位元組數（None 表示整份資料）
+    pub max_bytes: Option<u64>,    // 每個檔案最多讀取的位元組數（裝置檔必須指定）
+    pub bom: BomMode,
+    pub transport: Option<TransportEncoding>, // 偵測編碼前先還原 base64 / hex（--decode）
+    pub confidence: ConfidenceThreshold,      // 統計偵測結果的最低信心（--min-confidence）
+}
+
+/// --decode：輸入是以 base64 或十六進位表示的原始位元組
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportEncoding {
+    Base64,
+    Hex,
+}
+
+impl FromStr for TransportEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "base64" => Ok(TransportEncoding::Base64),
+            "hex" => Ok(TransportEncoding::Hex),
+            _ => Err(format!(
+                "Invalid value '{}' for --decode (expected base64 or hex)",
+                s
+            )),
+        }
+    }
+}
+
+impl TransportEncoding {
+    /// 還原為原始位元組；空白與換行（例如每 76 字元換行的 base64）會被忽略
+    pub fn decode(self, input: &[u8]) -> Result<Vec<u8>> {
+        let compact: Vec<u8> = input
+            .iter()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+
+        match self {
+            TransportEncoding::Base64 => {
+                use base64::engine::{general_purpose, DecodePaddingMode, GeneralPurpose};
+                use base64::Engine;
+
+                // 接受省略結尾 `=` 的輸入
+                const ENGINE: GeneralPurpose = GeneralPurpose::new(
+                    &base64::alphabet::STANDARD,
+                    general_purpose::PAD.with_decode_padding_mode(DecodePaddingMode::Indifferent),
+                );
+                ENGINE.decode(&compact).context("Invalid base64 input")
+            }
+            TransportEncoding::Hex => {
+                if !compact.len().is_multiple_of(2) {
+                    anyhow::bail!("Invalid hex input: odd number of digits");
+                }
+                compact
+                    .chunks(2)
+                    .enumerate()
+                    .map(|(i, pair)| {
+                        std::str::from_utf8(pair)
+                            .ok()
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                            .ok_or_else(|| {
+                                anyhow::anyhow!("Invalid hex input: bad digit pair at {}", i * 2)
+                            })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// 原始位元組範圍 `START:END`（不含 END，兩端皆可省略）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    /// 將範圍套用到長度為 `len` 的資料，超出結尾的部分會被截掉
+    fn clamp(&self, len: usize) -> std::ops::Range<usize> {
+        let start = (self.start as usize).min(len);
+        let end = self.end.map_or(len, |end| (end as usize).min(len));
+        start..end.max(start)
+    }
+}
+
+impl FromStr for ByteRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid byte range '{}' (expected START:END)", s);
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+
+        let start = if start.is_empty() {
+            0
+        } else {
+            start.parse().map_err(|_| invalid())?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse().map_err(|_| invalid())?)
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid());
+        }
+
+        Ok(ByteRange { start, end })
+    }
+}
+
+/// UTF-16 代理對錯誤（位置為原始資料的位元組偏移）
+#[derive(Debug, PartialEq)]
+pub enum SurrogateError {
+    LoneHigh(usize), // 高代理後沒有接低代理
+    LoneLow(usize),  // 低代理前沒有高代理
+}
+
+impl SurrogateError {
+    pub fn offset(&self) -> usize {
+        match self {
+            SurrogateError::LoneHigh(offset) | SurrogateError::LoneLow(offset) => *offset,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            SurrogateError::LoneHigh(_) => "lone high surrogate",
+            SurrogateError::LoneLow(_) => "lone low surrogate",
+        }
+    }
 }
 
 /// 讀取文件內容並轉換為 UTF-8 字符串
 pub fn read_file_with_encoding(
     path: &Path,
     user_encoding: Option<&'static Encoding>,
-    debug: bool,
+    options: &DecodeOptions,
 ) -> Result<(String, DetectedEncoding)> {
     // 讀取文件的原始字節
-    let bytes = fs::read(path).context("Failed to read file")?;
+    let bytes = read_file_bytes(path, options.max_bytes)?;
 
-    // 編碼優先級：UTF-8/BOM > 用戶指定 > 系統編碼
-    let detected = detect_encoding(&bytes, user_encoding, debug);
+    decode_with_encoding(&bytes, user_encoding, options)
+}
 
-    if debug {
-        eprintln!(
-            "[DEBUG] Detected encoding: {} (confidence: {:?})",
-            detected.encoding.name(),
-            detected.confidence
-        );
+/// 以偵測到的編碼解碼整段資料；檔案、stdin 與位元組範圍共用，解碼選項一致地套用
+///
+/// 無法解碼的位元組依 `options.invalid` 取代（可指定取代字元）、丟棄或回報錯誤，
+/// 開頭的 BOM 依 `options.bom` 處理
+pub fn decode_bytes(
+    bytes: &[u8],
+    detected: DetectedEncoding,
+    options: &DecodeOptions,
+) -> Result<(String, DetectedEncoding)> {
+    let mut decoder = match options.bom {
+        BomMode::Keep => detected.encoding.new_decoder_without_bom_handling(),
+        _ => detected.encoding.new_decoder_with_bom_removal(),
+    };
+
+    let mut decoded = String::new();
+    let mut src = bytes;
+    let mut had_errors = false;
+    loop {
+        let needed = decoder
+            .max_utf8_buffer_length_without_replacement(src.len())
+            .unwrap_or(src.len());
+        decoded.reserve(needed);
+
+        let (result, read) = decoder.decode_to_string_without_replacement(src, &mut decoded, true);
+        src = &src[read..];
+
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(..) => {
+                had_errors = true;
+                if options.invalid == InvalidMode::Replace {
+                    decoded.push(options.replacement.unwrap_or('\u{FFFD}'));
+                }
+            }
+        }
     }
 
-    // 解碼為 UTF-8 字符串
-    let (cow, _encoding_used, had_errors) = detected.encoding.decode(&bytes);
+    let detected = with_errors(detected, bytes, had_errors);
+    if had_errors {
+        check_invalid(bytes, detected.encoding, options)?;
+    }
+
+    let decoded = apply_bom_mode(decoded.into(), bytes, options.bom);
+    Ok((decoded.into_owned(), detected))
+}
+
+/// 串流解碼時每次從檔案讀取的位元組數
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
-    if had_errors && debug {
-        eprintln!("[DEBUG] Warning: Some characters could not be decoded properly");
+/// 逐塊解碼的 reader：讀取原始位元組並輸出 UTF-8，不需要把整個檔案讀進記憶體
+///
+/// 無法解碼的位元組依 `DecodeOptions` 處理：取代為 U+FFFD（或 `replacement`），
+/// `--invalid error` 時在讀到該處回傳 `InvalidData` 錯誤
+pub struct DecodingReader<R> {
+    inner: R,
+    decoder: Decoder,
+    encoding: &'static Encoding,
+    invalid: InvalidMode,
+    replacement: char,
+    strip_bom: bool,   // 還沒有輸出任何內容，且要移除開頭的 U+FEFF
+    input: Vec<u8>,    // 從 inner 讀取的原始位元組
+    output: String,    // 已解碼、尚未被讀走的內容
+    output_pos: usize, // output 中已被讀走的位元組數
+    consumed: usize,   // 已解碼的原始位元組數（用於錯誤位置）
+    eof: bool,
+    had_errors: bool,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, encoding: &'static Encoding, options: &DecodeOptions) -> Self {
+        DecodingReader {
+            inner,
+            decoder: match options.bom {
+                BomMode::Keep => encoding.new_decoder_without_bom_handling(),
+                _ => encoding.new_decoder_with_bom_removal(),
+            },
+            strip_bom: options.bom == BomMode::Strip,
+            encoding,
+            invalid: options.invalid,
+            replacement: options.replacement.unwrap_or('\u{FFFD}'),
+            input: vec![0; STREAM_CHUNK_SIZE],
+            output: String::new(),
+            output_pos: 0,
+            consumed: 0,
+            eof: false,
+            had_errors: false,
+        }
     }
 
-    Ok((cow.into_owned(), detected))
+    /// 解碼 input 的前 `len` 個位元組，結果附加到 output
+    fn decode_chunk(&mut self, len: usize) -> io::Result<()> {
+        let mut src = &self.input[..len];
+        loop {
+            let needed = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(src.len())
+                .unwrap_or(STREAM_CHUNK_SIZE);
+            self.output.reserve(needed);
+
+            let (result, read) =
+                self.decoder
+                    .decode_to_string_without_replacement(src, &mut self.output, self.eof);
+            src = &src[read..];
+            self.consumed += read;
+
+            match result {
+                DecoderResult::InputEmpty => return Ok(()),
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(bad, extra) => {
+                    self.had_errors = true;
+                    if self.invalid == InvalidMode::Error {
+                        let offset = self.consumed - bad as usize - extra as usize;
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Invalid {} data at byte offset {}",
+                                self.encoding.name(),
+                                offset
+                            ),
+                        ));
+                    }
+                    if self.invalid == InvalidMode::Replace {
+                        self.output.push(self.replacement);
+                    }
+                }
+            }
+        }
+    }
 }
 
-/// 從 stdin 讀取並轉換為 UTF-8 字符串
-pub fn read_stdin_with_encoding(
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_pos == self.output.len() && !self.eof {
+            self.output.clear();
+            self.output_pos = 0;
+
+            let len = self.inner.read(&mut self.input)?;
+            self.eof = len == 0;
+            self.decode_chunk(len)?;
+
+            if self.strip_bom && !self.output.is_empty() {
+                self.strip_bom = false;
+                self.output_pos =
+                    self.output.len() - self.output.trim_start_matches('\u{FEFF}').len();
+            }
+
+            // 讀完才知道是否有無法解碼的位元組
+            if self.eof && self.had_errors {
+                warn_undecodable(self.encoding, self.invalid);
+            }
+        }
+
+        let available = &self.output.as_bytes()[self.output_pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.output_pos += n;
+        Ok(n)
+    }
+}
+
+/// 以串流方式開啟檔案並偵測編碼（不把整個檔案讀進記憶體）
+///
+/// 沒有指定 `detection_bytes` 時，開頭像 UTF-8 的檔案會再逐塊檢查到結尾，
+/// 與一次讀入時檢查整個檔案的結果相同（多讀一次檔案，但記憶體用量不變）
+pub fn open_file_stream(
+    path: &Path,
     user_encoding: Option<&'static Encoding>,
-    debug: bool,
-) -> Result<(String, DetectedEncoding)> {
+    options: &DecodeOptions,
+) -> Result<(DecodingReader<fs::File>, DetectedEncoding)> {
+    let mut detected = detect_file_encoding(
+        path,
+        user_encoding,
+        options.detection_bytes,
+        options.confidence,
+    )?;
+
+    let file = fs::File::open(path).context("Failed to read file")?;
+    let len = file.metadata().context("Failed to read file")?.len();
+    if options.detection_bytes.is_none()
+        && detected.confidence == EncodingConfidence::High
+        && len > DETECTION_PREFIX_LEN
+        && !is_valid_utf8_reader(&file).context("Failed to read file")?
+    {
+        detected = fallback_encoding(
+            user_encoding,
+            || {
+                fs::File::open(path)
+                    .and_then(guess_encoding_from_reader)
+                    .ok()
+            },
+            options.confidence,
+        )?;
+    }
+
+    let file = fs::File::open(path).context("Failed to read file")?;
+    Ok((
+        DecodingReader::new(file, detected.encoding, options),
+        detected,
+    ))
+}
+
+/// 開頭已讀取一段、其餘仍在原本 reader 中的輸入
+pub type PrefixedReader<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+/// 以串流方式解碼無法重新讀取的輸入（例如 --fd 的管線）：只以已讀取的開頭 `prefix` 偵測編碼，
+/// 其餘內容從 `rest` 逐塊解碼
+///
+/// `prefix` 應以 `DETECTION_PREFIX_LEN` 為上限讀取；較短時代表已讀到結尾
+pub fn open_prefixed_stream<R: Read>(
+    prefix: Vec<u8>,
+    rest: R,
+    user_encoding: Option<&'static Encoding>,
+    options: &DecodeOptions,
+) -> Result<(DecodingReader<PrefixedReader<R>>, DetectedEncoding)> {
+    let len = options
+        .detection_bytes
+        .map_or(prefix.len(), |limit| limit.min(prefix.len()));
+    let complete = len == prefix.len() && (prefix.len() as u64) < DETECTION_PREFIX_LEN;
+    let detected =
+        detect_encoding_prefix(&prefix[..len], complete, user_encoding, options.confidence)?;
+    Ok((
+        DecodingReader::new(
+            io::Cursor::new(prefix).chain(rest),
+            detected.encoding,
+            options,
+        ),
+        detected,
+    ))
+}
+
+/// 逐塊檢查 reader 的內容是否為有效的 UTF-8（跨塊的多位元組字元保留到下一塊）
+fn is_valid_utf8_reader<R: Read>(mut reader: R) -> io::Result<bool> {
+    let mut buf = vec![0; STREAM_CHUNK_SIZE];
+    let mut carry = 0;
+    loop {
+        let len = reader.read(&mut buf[carry..])?;
+        if len == 0 {
+            return Ok(carry == 0);
+        }
+
+        let filled = carry + len;
+        match std::str::from_utf8(&buf[..filled]) {
+            Ok(_) => carry = 0,
+            Err(e) if e.error_len().is_none() => {
+                buf.copy_within(e.valid_up_to()..filled, 0);
+                carry = filled - e.valid_up_to();
+            }
+            Err(_) => return Ok(false),
+        }
+    }
+}
+
+/// 讀取檔案的原始位元組，最多 `max_bytes` 個
+///
+/// 字元/區塊裝置（例如 `/dev/zero`）可能永遠讀不完，沒有指定上限時只接受空的裝置（`/dev/null`）
+pub fn read_file_bytes(path: &Path, max_bytes: Option<u64>) -> Result<Vec<u8>> {
+    let mut file = fs::File::open(path).context("Failed to read file")?;
+    let mut bytes = Vec::new();
+
+    match max_bytes {
+        Some(limit) => {
+            file.take(limit)
+                .read_to_end(&mut bytes)
+                .context("Failed to read file")?;
+        }
+        None if is_device(&file.metadata().context("Failed to read file")?) => {
+            // 先試讀一個位元組，有資料就拒絕
+            file.by_ref()
+                .take(1)
+                .read_to_end(&mut bytes)
+                .context("Failed to read file")?;
+            if !bytes.is_empty() {
+                anyhow::bail!(
+                    "Refusing to read device file {} without a limit (use --bytes N)",
+                    path.display()
+                );
+            }
+        }
+        None => {
+            file.read_to_end(&mut bytes)
+                .context("Failed to read file")?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// 判斷是否為二進位資料時檢查的開頭位元組數（與 git 相同）
+pub const BINARY_SNIFF_LEN: usize = 8000;
+
+/// 控制字元超過開頭位元組的 1/BINARY_CONTROL_RATIO 時視為二進位
+const BINARY_CONTROL_RATIO: usize = 10;
+
+/// 開頭有 NUL，或控制字元比例過高時視為二進位資料（例如圖片、壓縮檔）
+///
+/// 有 BOM 的資料（包含大量 NUL 的 UTF-16）不算；tab、換行、換頁與 ESC（有顏色的紀錄檔）不算控制字元
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    if Encoding::for_bom(bytes).is_some() {
+        return false;
+    }
+
+    let head = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    if head.contains(&0) {
+        return true;
+    }
+    let control = head
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f)
+        .count();
+    control * BINARY_CONTROL_RATIO > head.len()
+}
+
+/// 讀取一般檔案的開頭判斷是否為二進位；FIFO 與裝置檔不預先讀取（讀了就無法重來），一律視為文字
+pub fn file_looks_binary(path: &Path) -> Result<bool> {
+    if !fs::metadata(path).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(false);
+    }
+
+    let mut head = Vec::with_capacity(BINARY_SNIFF_LEN);
+    fs::File::open(path)
+        .and_then(|file| file.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut head))
+        .context("Failed to read file")?;
+    Ok(looks_binary(&head))
+}
+
+#[cfg(unix)]
+fn is_device(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = metadata.file_type();
+    file_type.is_char_device() || file_type.is_block_device()
+}
+
+#[cfg(not(unix))]
+fn is_device(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// 解碼後依 `mode` 處理開頭的 U+FEFF；`bytes` 是解碼前的原始資料
+fn apply_bom_mode<'a>(
+    decoded: std::borrow::Cow<'a, str>,
+    bytes: &[u8],
+    mode: BomMode,
+) -> std::borrow::Cow<'a, str> {
+    match mode {
+        BomMode::Strip if decoded.starts_with('\u{FEFF}') => {
+            decoded.trim_start_matches('\u{FEFF}').to_string().into()
+        }
+        // 解碼器已移除 BOM，補回一個
+        BomMode::Keep if Encoding::for_bom(bytes).is_some() && !decoded.starts_with('\u{FEFF}') => {
+            format!("\u{FEFF}{}", decoded).into()
+        }
+        _ => decoded,
+    }
+}
+
+/// 警告有無法解碼的位元組（取代或丟棄；`--invalid error` 時改為回傳錯誤）
+fn warn_undecodable(encoding: &'static Encoding, mode: InvalidMode) {
+    warn!(
+        "some bytes could not be decoded as {} and were {}",
+        encoding.name(),
+        if mode == InvalidMode::Skip {
+            "skipped"
+        } else {
+            "replaced"
+        }
+    );
+}
+
+/// 將輸出轉碼為指定的編碼（--output-encoding）
+///
+/// 目標編碼無法表示的字元不會被取代，而是計數後回報錯誤，避免產生悄悄遺失內容的檔案
+pub fn encode_output(text: &str, encoding: &'static Encoding) -> Result<Vec<u8>> {
+    // encoding_rs 的 UTF-16 編碼器輸出 UTF-8，需自行轉換
+    if encoding == encoding_rs::UTF_16LE {
+        return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if encoding == encoding_rs::UTF_16BE {
+        return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+
+    let unmappable = count_unmappable(text, encoding);
+    if unmappable > 0 {
+        anyhow::bail!(
+            "{} character(s) cannot be represented in {}",
+            unmappable,
+            encoding.name()
+        );
+    }
+    Ok(encoding.encode(text).0.into_owned())
+}
+
+/// 計算目標編碼無法表示的字元數
+fn count_unmappable(text: &str, encoding: &'static Encoding) -> usize {
+    let mut encoder = encoding.new_encoder();
+    let mut buf = [0; 1024];
+    let mut rest = text;
+    let mut count = 0;
+    loop {
+        let (result, read, _) = encoder.encode_from_utf8_without_replacement(rest, &mut buf, true);
+        rest = &rest[read..];
+        match result {
+            EncoderResult::InputEmpty => return count,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(_) => count += 1,
+        }
+    }
+}
+
+/// 讀取 stdin 的全部原始位元組
+pub fn read_stdin_bytes() -> Result<Vec<u8>> {
     let mut bytes = Vec::new();
 
     // 讀取 stdin，處理 Ctrl+C 中斷
@@ -65,83 +684,597 @@ pub fn read_stdin_with_encoding(
         Err(e) => return Err(e).context("Failed to read from stdin"),
     }
 
-    let detected = detect_encoding(&bytes, user_encoding, debug);
+    Ok(bytes)
+}
 
-    if debug {
-        eprintln!(
-            "[DEBUG] Detected encoding: {} (confidence: {:?})",
-            detected.encoding.name(),
-            detected.confidence
+/// 偵測編碼並將原始位元組解碼為 UTF-8 字符串
+pub fn decode_with_encoding(
+    bytes: &[u8],
+    user_encoding: Option<&'static Encoding>,
+    options: &DecodeOptions,
+) -> Result<(String, DetectedEncoding)> {
+    // 編碼優先級：UTF-8/BOM > 用戶指定 > 系統編碼
+    let transported;
+    let bytes = match options.transport {
+        Some(transport) => {
+            transported = transport.decode(bytes)?;
+            debug!("Decoded {:?} input: {} bytes", transport, transported.len());
+            &transported[..]
+        }
+        None => bytes,
+    };
+
+    let detected = detect_encoding(bytes, user_encoding, options)?;
+
+    debug!(
+        "Detected encoding: {} (confidence: {:?})",
+        detected.encoding.name(),
+        detected.confidence
+    );
+
+    decode_bytes(bytes, detected, options)
+}
+
+/// 只讀取檔案中指定的位元組範圍並解碼（編碼由檔案開頭偵測）
+pub fn read_file_range_with_encoding(
+    path: &Path,
+    range: ByteRange,
+    user_encoding: Option<&'static Encoding>,
+    options: &DecodeOptions,
+) -> Result<(String, DetectedEncoding)> {
+    let detected = detect_file_encoding(
+        path,
+        user_encoding,
+        options.detection_bytes,
+        options.confidence,
+    )?;
+
+    let mut file = fs::File::open(path).context("Failed to read file")?;
+    let len = file.metadata().context("Failed to read file")?.len();
+
+    // 起點超出檔案結尾時輸出空內容
+    let mut bytes = Vec::new();
+    if range.start < len {
+        file.seek(SeekFrom::Start(range.start))
+            .context("Failed to read file")?;
+        let limit = range.end.map_or(u64::MAX, |end| end - range.start);
+        file.take(limit)
+            .read_to_end(&mut bytes)
+            .context("Failed to read file")?;
+    }
+
+    decode_slice(&bytes, detected, options)
+}
+
+/// 對已讀入的資料（例如 stdin）套用位元組範圍並解碼
+pub fn decode_range_with_encoding(
+    bytes: &[u8],
+    range: ByteRange,
+    user_encoding: Option<&'static Encoding>,
+    options: &DecodeOptions,
+) -> Result<(String, DetectedEncoding)> {
+    let detected = detect_encoding(bytes, user_encoding, options)?;
+    decode_slice(&bytes[range.clamp(bytes.len())], detected, options)
+}
+
+/// 解碼檔案中的一段資料：先對齊到字元邊界，避免頭尾出現取代字元
+fn decode_slice(
+    bytes: &[u8],
+    detected: DetectedEncoding,
+    options: &DecodeOptions,
+) -> Result<(String, DetectedEncoding)> {
+    let bytes = snap_to_char_boundaries(bytes, detected.encoding);
+    decode_bytes(bytes, detected, options)
+}
+
+/// 去掉範圍頭尾被切斷的字元（支援 UTF-8 與 UTF-16）
+fn snap_to_char_boundaries<'a>(bytes: &'a [u8], encoding: &'static Encoding) -> &'a [u8] {
+    if encoding == encoding_rs::UTF_8 {
+        // 開頭最多略過 3 個接續位元組（10xxxxxx）
+        let skip = bytes
+            .iter()
+            .take(3)
+            .take_while(|&&b| b & 0xC0 == 0x80)
+            .count();
+        let bytes = &bytes[skip..];
+
+        // 結尾不完整的字元
+        match std::str::from_utf8(bytes) {
+            Err(e) if e.error_len().is_none() => &bytes[..e.valid_up_to()],
+            _ => bytes,
+        }
+    } else if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+        &bytes[..bytes.len() - bytes.len() % 2]
+    } else {
+        bytes
+    }
+}
+
+/// 一行在原始資料中的位置（長度包含換行符號）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineSpan {
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// 在原始位元組上計算每一行的位置，換行符號依偵測到的編碼比對（BOM 不算在第一行內）
+pub fn line_index(bytes: &[u8], user_encoding: Option<&'static Encoding>) -> Vec<LineSpan> {
+    // 預設的門檻不是 strict，不會失敗；位置只取決於換行的編碼
+    let encoding = detect_encoding(bytes, user_encoding, &DecodeOptions::default())
+        .map_or_else(|_| get_system_encoding(), |detected| detected.encoding);
+
+    let start = match Encoding::for_bom(bytes) {
+        Some((bom_encoding, bom_length)) if bom_encoding == encoding => bom_length,
+        _ => 0,
+    };
+
+    // UTF-16 的換行是兩個位元組，且必須落在偶數位置
+    let newline: &[u8] = if encoding == encoding_rs::UTF_16LE {
+        b"\n\0"
+    } else if encoding == encoding_rs::UTF_16BE {
+        b"\0\n"
+    } else {
+        b"\n"
+    };
+
+    let mut spans = Vec::new();
+    let mut offset = start;
+    let mut pos = start;
+    while pos + newline.len() <= bytes.len() {
+        if &bytes[pos..pos + newline.len()] == newline {
+            pos += newline.len();
+            spans.push(LineSpan {
+                offset,
+                length: pos - offset,
+            });
+            offset = pos;
+        } else {
+            pos += newline.len();
+        }
+    }
+
+    // 最後一行沒有換行符號
+    if offset < bytes.len() {
+        spans.push(LineSpan {
+            offset,
+            length: bytes.len() - offset,
+        });
+    }
+
+    spans
+}
+
+/// 處理解碼錯誤：除錯模式下記錄錯誤位置，`--invalid error` 時回傳錯誤
+fn check_invalid(bytes: &[u8], encoding: &'static Encoding, options: &DecodeOptions) -> Result<()> {
+    // UTF-16 的代理對錯誤另外檢查，提供更精確的說明
+    let surrogate_errors = if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+    {
+        find_surrogate_errors(bytes, encoding == encoding_rs::UTF_16BE)
+    } else {
+        Vec::new()
+    };
+
+    for error in &surrogate_errors {
+        debug!(
+            "UTF-16 {} at byte offset {}",
+            error.description(),
+            error.offset()
         );
     }
 
-    let (cow, _encoding_used, had_errors) = detected.encoding.decode(&bytes);
+    if options.invalid != InvalidMode::Error {
+        warn_undecodable(encoding, options.invalid);
+        return Ok(());
+    }
 
-    if had_errors && debug {
-        eprintln!("[DEBUG] Warning: Some characters could not be decoded properly");
+    if let Some(error) = surrogate_errors.first() {
+        anyhow::bail!(
+            "Invalid {} data: {} at byte offset {}",
+            encoding.name(),
+            error.description(),
+            error.offset()
+        );
+    }
+    match first_invalid_offset(bytes, encoding) {
+        Some(offset) => anyhow::bail!("Invalid {} data at byte offset {}", encoding.name(), offset),
+        None => anyhow::bail!("Invalid {} data", encoding.name()),
     }
+}
 
-    Ok((cow.into_owned(), detected))
+/// 找出第一個無法解碼的位元組偏移
+fn first_invalid_offset(bytes: &[u8], encoding: &'static Encoding) -> Option<usize> {
+    invalid_offsets(bytes, encoding, 1).first().copied()
 }
 
-/// 檢測文件編碼（優先級：UTF-8/BOM > 用戶指定 > 系統編碼）
+/// 找出前 `limit` 個無法解碼的位元組偏移
+fn invalid_offsets(bytes: &[u8], encoding: &'static Encoding, limit: usize) -> Vec<usize> {
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let Some(capacity) = decoder.max_utf8_buffer_length_without_replacement(bytes.len()) else {
+        return Vec::new();
+    };
+    let mut output = String::with_capacity(capacity);
+
+    let mut offsets = Vec::new();
+    let mut consumed = 0;
+    while offsets.len() < limit {
+        let (result, read) =
+            decoder.decode_to_string_without_replacement(&bytes[consumed..], &mut output, true);
+        consumed += read;
+        match result {
+            DecoderResult::Malformed(bad, after) => {
+                offsets.push(consumed - bad as usize - after as usize)
+            }
+            _ => break,
+        }
+    }
+    offsets
+}
+
+/// 掃描 UTF-16 資料中未配對的代理字元
+pub fn find_surrogate_errors(bytes: &[u8], big_endian: bool) -> Vec<SurrogateError> {
+    let mut errors = Vec::new();
+    let mut pending_high: Option<usize> = None;
+
+    for (index, pair) in bytes.chunks_exact(2).enumerate() {
+        let offset = index * 2;
+        let unit = if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        };
+
+        match unit {
+            0xD800..=0xDBFF => {
+                if let Some(high) = pending_high.replace(offset) {
+                    errors.push(SurrogateError::LoneHigh(high));
+                }
+            }
+            0xDC00..=0xDFFF => {
+                if pending_high.take().is_none() {
+                    errors.push(SurrogateError::LoneLow(offset));
+                }
+            }
+            _ => {
+                if let Some(high) = pending_high.take() {
+                    errors.push(SurrogateError::LoneHigh(high));
+                }
+            }
+        }
+    }
+
+    if let Some(high) = pending_high {
+        errors.push(SurrogateError::LoneHigh(high));
+    }
+
+    errors
+}
+
+/// 檢測文件編碼（優先級：UTF-8/BOM > 用戶指定 > 統計偵測 > 系統編碼）
+///
+/// `detection_bytes` 限制只檢查開頭的位元組數：較快，但之後才出現的非 UTF-8 位元組不會被發現，
+/// 解碼時會以 U+FFFD 取代；統計偵測的結果依 `confidence` 門檻決定是否採用
 fn detect_encoding(
     bytes: &[u8],
     user_encoding: Option<&'static Encoding>,
-    debug: bool,
-) -> DetectedEncoding {
+    options: &DecodeOptions,
+) -> Result<DetectedEncoding> {
+    let len = options
+        .detection_bytes
+        .map_or(bytes.len(), |limit| limit.min(bytes.len()));
+    detect_encoding_prefix(
+        &bytes[..len],
+        len == bytes.len(),
+        user_encoding,
+        options.confidence,
+    )
+}
+
+/// 只用於偵測編碼時讀取的前綴長度
+pub const DETECTION_PREFIX_LEN: u64 = 64 * 1024;
+
+/// 只讀取檔案開頭的一段資料來偵測編碼（不解碼整個檔案）
+pub fn detect_file_encoding(
+    path: &Path,
+    user_encoding: Option<&'static Encoding>,
+    limit: Option<usize>,
+    threshold: ConfidenceThreshold,
+) -> Result<DetectedEncoding> {
+    let file = fs::File::open(path).context("Failed to read file")?;
+    detect_encoding_from_reader(file, user_encoding, limit, threshold)
+}
+
+/// 從 reader 讀取最多 `limit`（預設 DETECTION_PREFIX_LEN）位元組並偵測編碼
+pub fn detect_encoding_from_reader<R: Read>(
+    reader: R,
+    user_encoding: Option<&'static Encoding>,
+    limit: Option<usize>,
+    threshold: ConfidenceThreshold,
+) -> Result<DetectedEncoding> {
+    let limit = limit.map_or(DETECTION_PREFIX_LEN, |limit| limit as u64);
+    let mut prefix = Vec::new();
+    reader
+        .take(limit)
+        .read_to_end(&mut prefix)
+        .context("Failed to read file")?;
+
+    // 讀滿前綴時，資料可能在中途被截斷
+    let complete = (prefix.len() as u64) < limit;
+    detect_encoding_prefix(&prefix, complete, user_encoding, threshold)
+}
+
+/// 讀取檔案開頭並輸出編碼偵測的每個步驟（--dump-detection）
+pub fn dump_file_detection(
+    path: &Path,
+    user_encoding: Option<&'static Encoding>,
+) -> Result<String> {
+    let mut prefix = Vec::new();
+    fs::File::open(path)
+        .context("Failed to read file")?
+        .take(DETECTION_PREFIX_LEN)
+        .read_to_end(&mut prefix)
+        .context("Failed to read file")?;
+
+    let complete = (prefix.len() as u64) < DETECTION_PREFIX_LEN;
+    Ok(dump_detection(&prefix, complete, user_encoding))
+}
+
+/// 將偵測所依據的資料與結果整理成多行文字，方便回報編碼問題
+pub fn dump_detection(
+    bytes: &[u8],
+    complete: bool,
+    user_encoding: Option<&'static Encoding>,
+) -> String {
+    const PREVIEW_LEN: usize = 64;
+    let mut dump = String::new();
+
+    dump.push_str(&format!(
+        "bytes: {}{}\n",
+        bytes.len(),
+        if complete { "" } else { " (prefix)" }
+    ));
+    for (row, chunk) in bytes[..bytes.len().min(PREVIEW_LEN)].chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "  {:08x}  {:<47}  |{}|\n",
+            row * 16,
+            hex.join(" "),
+            text
+        ));
+    }
+
+    dump.push_str(&match Encoding::for_bom(bytes) {
+        Some((encoding, length)) => format!("bom: {} ({} bytes)\n", encoding.name(), length),
+        None => "bom: none\n".to_string(),
+    });
+    dump.push_str(&format!(
+        "valid utf-8: {}\n",
+        if is_valid_utf8(bytes, complete) {
+            "yes"
+        } else {
+            "no"
+        }
+    ));
+    if let Some(offset) = first_invalid_offset(bytes, encoding_rs::UTF_8).filter(|_| complete) {
+        dump.push_str(&format!("first invalid utf-8 byte: {}\n", offset));
+    }
+    dump.push_str(&format!(
+        "user encoding: {}\n",
+        user_encoding.map_or("none", |encoding| encoding.name())
+    ));
+    dump.push_str(&format!("statistical guess: {}\n", {
+        let (encoding, confidence) = guess_encoding(bytes, complete);
+        format!("{} ({:?})", encoding.name(), confidence)
+    }));
+    dump.push_str(&format!(
+        "system encoding: {}\n",
+        get_system_encoding().name()
+    ));
+
+    // 以預設門檻選擇（不是 strict，不會失敗）
+    if let Ok(detected) = detect_encoding_prefix(bytes, complete, user_encoding, Default::default())
+    {
+        dump.push_str(&format!(
+            "chosen: {} ({:?})\n",
+            detected.encoding.name(),
+            detected.confidence
+        ));
+    }
+
+    dump
+}
+
+/// 檢測編碼；`complete` 為 false 時表示資料只是前綴，結尾可能切斷多位元組字元
+fn detect_encoding_prefix(
+    bytes: &[u8],
+    complete: bool,
+    user_encoding: Option<&'static Encoding>,
+    threshold: ConfidenceThreshold,
+) -> Result<DetectedEncoding> {
     // 1. 檢查 BOM
     if let Some((encoding, _bom_length)) = Encoding::for_bom(bytes) {
-        if debug {
-            eprintln!("[DEBUG] BOM detected: {}", encoding.name());
-        }
-        return DetectedEncoding {
+        debug!("BOM detected: {}", encoding.name());
+        return Ok(DetectedEncoding {
             encoding,
             confidence: EncodingConfidence::Certain,
-        };
+            had_errors: false,
+            error_offsets: Vec::new(),
+        });
     }
 
     // 2. 檢查是否為有效的 UTF-8
-    if std::str::from_utf8(bytes).is_ok() {
-        if debug {
-            eprintln!("[DEBUG] Valid UTF-8 detected");
-        }
-        return DetectedEncoding {
+    if is_valid_utf8(bytes, complete) {
+        debug!("Valid UTF-8 detected");
+        return Ok(DetectedEncoding {
             encoding: encoding_rs::UTF_8,
             confidence: EncodingConfidence::High,
-        };
+            had_errors: false,
+            error_offsets: Vec::new(),
+        });
     }
 
+    fallback_encoding(
+        user_encoding,
+        || Some(guess_encoding(bytes, complete)),
+        threshold,
+    )
+}
+
+/// 不是 UTF-8 時使用的編碼：用戶指定 > 統計偵測（`guess`，只在沒有指定編碼時執行）> 系統編碼
+///
+/// 統計偵測的信心低於門檻時改用系統編碼，`strict` 時回報錯誤
+fn fallback_encoding(
+    user_encoding: Option<&'static Encoding>,
+    guess: impl FnOnce() -> Option<(&'static Encoding, EncodingConfidence)>,
+    threshold: ConfidenceThreshold,
+) -> Result<DetectedEncoding> {
     // 3. 使用用戶指定的編碼
     if let Some(encoding) = user_encoding {
-        if debug {
-            eprintln!("[DEBUG] Using user-specified encoding: {}", encoding.name());
-        }
-        return DetectedEncoding {
+        debug!("Using user-specified encoding: {}", encoding.name());
+        return Ok(DetectedEncoding {
             encoding,
             confidence: EncodingConfidence::Certain,
-        };
+            had_errors: false,
+            error_offsets: Vec::new(),
+        });
     }
 
-    // 4. 回退到系統編碼
-    let system_encoding = get_system_encoding();
-    if debug {
-        eprintln!(
-            "[DEBUG] Falling back to system encoding: {}",
-            system_encoding.name()
+    // 3.5 統計偵測的信心達到門檻時採用，避免傳統編碼的檔案被當成系統編碼
+    if let Some((encoding, confidence)) = guess() {
+        if confidence >= threshold.min {
+            debug!(
+                "Statistical detection: {} ({:?})",
+                encoding.name(),
+                confidence
+            );
+            return Ok(DetectedEncoding {
+                encoding,
+                confidence,
+                had_errors: false,
+                error_offsets: Vec::new(),
+            });
+        }
+        if threshold.strict {
+            anyhow::bail!(
+                "Encoding guess {} has {:?} confidence, below --min-confidence {:?}",
+                encoding.name(),
+                confidence,
+                threshold.min
+            );
+        }
+        debug!(
+            "Statistical guess {} ({:?}) is below the threshold {:?}",
+            encoding.name(),
+            confidence,
+            threshold.min
         );
     }
-    DetectedEncoding {
+
+    // 4. 回退到系統編碼
+    let system_encoding = get_system_encoding();
+    debug!(
+        "Falling back to system encoding: {}",
+        system_encoding.name()
+    );
+    Ok(DetectedEncoding {
         encoding: system_encoding,
         confidence: EncodingConfidence::Low,
+        had_errors: false,
+        error_offsets: Vec::new(),
+    })
+}
+
+/// 統計偵測的猜測至少要依據這麼多非 ASCII 位元組才算有把握（Medium）
+const GUESS_EVIDENCE_BYTES: usize = 16;
+
+/// 以 chardetng 猜測非 UTF-8 資料的編碼，並評估猜測的信心
+fn guess_encoding(bytes: &[u8], complete: bool) -> (&'static Encoding, EncodingConfidence) {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, complete);
+    let non_ascii = bytes.iter().filter(|b| !b.is_ascii()).count();
+    assess_guess(detector.guess_assess(None, false), non_ascii)
+}
+
+/// chardetng 的判斷對應到 EncodingConfidence：偵測器有把握（猜測的分數高於其他候選），
+/// 且看過至少 GUESS_EVIDENCE_BYTES 個非 ASCII 位元組時為 Medium，否則為 Low
+fn assess_guess(
+    (encoding, confident): (&'static Encoding, bool),
+    non_ascii: usize,
+) -> (&'static Encoding, EncodingConfidence) {
+    let confidence = if confident && non_ascii >= GUESS_EVIDENCE_BYTES {
+        EncodingConfidence::Medium
+    } else {
+        EncodingConfidence::Low
+    };
+    (encoding, confidence)
+}
+
+/// 同 `guess_encoding`，但逐塊讀取整個 reader
+fn guess_encoding_from_reader<R: Read>(
+    mut reader: R,
+) -> io::Result<(&'static Encoding, EncodingConfidence)> {
+    let mut detector = EncodingDetector::new();
+    let mut buf = vec![0; STREAM_CHUNK_SIZE];
+    let mut non_ascii = 0;
+    loop {
+        let len = reader.read(&mut buf)?;
+        detector.feed(&buf[..len], len == 0);
+        if len == 0 {
+            break;
+        }
+        non_ascii += buf[..len].iter().filter(|b| !b.is_ascii()).count();
+    }
+    Ok(assess_guess(detector.guess_assess(None, false), non_ascii))
+}
+
+/// 檢查是否為有效的 UTF-8（前綴結尾被截斷的字元視為有效）
+fn is_valid_utf8(bytes: &[u8], complete: bool) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        // error_len() 為 None 表示錯誤來自結尾不完整的字元
+        Err(e) => !complete && e.error_len().is_none(),
     }
 }
 
+/// 以 CATE_SYSTEM_ENCODING 固定回退用的系統編碼，讓測試與使用者不受 locale / 代碼頁影響
+fn system_encoding_override() -> Option<&'static Encoding> {
+    parse_system_encoding_override(&std::env::var("CATE_SYSTEM_ENCODING").ok()?)
+}
+
+fn parse_system_encoding_override(value: &str) -> Option<&'static Encoding> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let encoding = parse_encoding(value).ok();
+    if encoding.is_none() {
+        warn!(
+            "CATE_SYSTEM_ENCODING: unknown encoding '{}', ignored",
+            value
+        );
+    }
+    encoding
+}
+
 /// 獲取系統預設編碼
 #[cfg(target_os = "windows")]
 fn get_system_encoding() -> &'static Encoding {
     use winapi::um::winnls::GetACP;
 
+    if let Some(encoding) = system_encoding_override() {
+        return encoding;
+    }
+
     let code_page = unsafe { GetACP() };
 
     match code_page {
@@ -159,6 +1292,10 @@ fn get_system_encoding() -> &'static Encoding {
 
 #[cfg(not(target_os = "windows"))]
 fn get_system_encoding() -> &'static Encoding {
+    if let Some(encoding) = system_encoding_override() {
+        return encoding;
+    }
+
     // Unix 系統通常使用 UTF-8
     // 可以通過環境變量 LANG 來檢測，但大多數現代系統都是 UTF-8
     if let Ok(lang) = std::env::var("LANG") {
@@ -212,3 +1349,627 @@ pub fn list_encodings() {
     println!("  Use any encoding name supported by encoding_rs");
     println!("  (e.g., euc-jp, iso-8859-2, koi8-r, etc.)");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{self, Level};
+
+    #[test]
+    fn test_lone_high_surrogate_utf16le() {
+        // "A", 高代理 0xD800（沒有低代理）, "B"
+        let bytes = [0x41, 0x00, 0x00, 0xD8, 0x42, 0x00];
+
+        assert_eq!(
+            find_surrogate_errors(&bytes, false),
+            vec![SurrogateError::LoneHigh(2)]
+        );
+
+        let options = DecodeOptions {
+            invalid: InvalidMode::Error,
+            ..Default::default()
+        };
+        let err = check_invalid(&bytes, encoding_rs::UTF_16LE, &options).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("lone high surrogate at byte offset 2"));
+
+        // 預設模式只取代，不回報錯誤
+        let (result, _) = logging::capture(Level::Quiet, || {
+            check_invalid(&bytes, encoding_rs::UTF_16LE, &DecodeOptions::default())
+        });
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_valid_surrogate_pair() {
+        // U+1F600 = D83D DE00
+        let bytes = [0x3D, 0xD8, 0x00, 0xDE];
+        assert!(find_surrogate_errors(&bytes, false).is_empty());
+        assert_eq!(
+            find_surrogate_errors(&[0xDE, 0x00], true),
+            vec![SurrogateError::LoneLow(0)]
+        );
+    }
+
+    #[test]
+    fn test_decode_warning_suppressed_when_quiet() {
+        let path = std::env::temp_dir().join(format!("cate-quiet-{}.txt", std::process::id()));
+        fs::write(&path, b"hello \xff world\n").unwrap();
+        let user_encoding = Some(encoding_rs::UTF_8);
+
+        let (result, messages) = logging::capture(Level::Normal, || {
+            read_file_with_encoding(&path, user_encoding, &DecodeOptions::default())
+        });
+        assert!(result.unwrap().0.contains("world"));
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("could not be decoded"));
+
+        let (result, messages) = logging::capture(Level::Quiet, || {
+            read_file_with_encoding(&path, user_encoding, &DecodeOptions::default())
+        });
+        fs::remove_file(&path).unwrap();
+
+        // 內容照常輸出，只是沒有警告
+        assert!(result.unwrap().0.contains("hello"));
+        assert!(messages.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_device_files_need_byte_limit() {
+        let options = DecodeOptions::default();
+
+        // 空的裝置可以直接讀
+        let (content, _) = read_file_with_encoding(Path::new("/dev/null"), None, &options).unwrap();
+        assert_eq!(content, "");
+
+        // 讀不完的裝置沒有上限時拒絕
+        let err = read_file_with_encoding(Path::new("/dev/zero"), None, &options).unwrap_err();
+        assert!(err.to_string().contains("--bytes"));
+
+        let options = DecodeOptions {
+            max_bytes: Some(4),
+            ..Default::default()
+        };
+        let (content, _) = read_file_with_encoding(Path::new("/dev/zero"), None, &options).unwrap();
+        assert_eq!(content, "\0\0\0\0");
+    }
+
+    /// 每次只回傳少量位元組的 reader，模擬跨塊的多位元組字元
+    struct TrickleReader<'a>(&'a [u8], usize);
+
+    impl Read for TrickleReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(self.1).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_decoding_reader_matches_full_decode() {
+        let (bytes, _, _) = encoding_rs::GBK.encode("第一行\n第二行 abc\n");
+        let mut decoded = String::new();
+        DecodingReader::new(
+            TrickleReader(&bytes, 3),
+            encoding_rs::GBK,
+            &DecodeOptions::default(),
+        )
+        .read_to_string(&mut decoded)
+        .unwrap();
+        assert_eq!(decoded, "第一行\n第二行 abc\n");
+
+        // UTF-8 BOM 被移除
+        let mut decoded = String::new();
+        DecodingReader::new(
+            TrickleReader(b"\xEF\xBB\xBFhi", 1),
+            encoding_rs::UTF_8,
+            &DecodeOptions::default(),
+        )
+        .read_to_string(&mut decoded)
+        .unwrap();
+        assert_eq!(decoded, "hi");
+    }
+
+    #[test]
+    fn test_decoding_reader_invalid_bytes() {
+        let options = DecodeOptions {
+            replacement: Some('?'),
+            ..Default::default()
+        };
+        let mut decoded = String::new();
+        let mut reader = DecodingReader::new(
+            TrickleReader(b"ok \xff \xe4\xb8\xad", 2),
+            encoding_rs::UTF_8,
+            &options,
+        );
+        logging::capture(Level::Quiet, || reader.read_to_string(&mut decoded))
+            .0
+            .unwrap();
+        assert_eq!(decoded, "ok ? 中");
+
+        let options = DecodeOptions {
+            invalid: InvalidMode::Error,
+            ..Default::default()
+        };
+        let err = DecodingReader::new(TrickleReader(b"ok \xff", 2), encoding_rs::UTF_8, &options)
+            .read_to_string(&mut String::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid UTF-8 data at byte offset 3");
+    }
+
+    #[test]
+    fn test_open_prefixed_stream() {
+        // 開頭已被讀走（偵測用），剩下的內容仍從原本的 reader 讀取
+        let bytes: Vec<u8> = "\u{FEFF}第一行\n第二行\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let (mut reader, detected) = open_prefixed_stream(
+            bytes[..6].to_vec(),
+            TrickleReader(&bytes[6..], 3),
+            None,
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(detected.encoding, encoding_rs::UTF_16LE);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "第一行\n第二行\n");
+    }
+
+    #[test]
+    fn test_is_valid_utf8_reader_across_chunks() {
+        let text = "中文字".as_bytes();
+        assert!(is_valid_utf8_reader(TrickleReader(text, 2)).unwrap());
+        assert!(!is_valid_utf8_reader(TrickleReader(&text[..4], 2)).unwrap());
+        assert!(!is_valid_utf8_reader(TrickleReader(b"a\xffb", 1)).unwrap());
+    }
+
+    /// 記錄實際被讀取的位元組數
+    struct CountingReader<R> {
+        inner: R,
+        read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_detection_reads_only_prefix() {
+        // 4 MB 的 UTF-8 內容，前綴的結尾剛好切在一個三位元組字元中間
+        let mut data = vec![b'a'; DETECTION_PREFIX_LEN as usize - 1];
+        data.extend("中".repeat(1024 * 1024).as_bytes());
+
+        let mut reader = CountingReader {
+            inner: std::io::Cursor::new(data),
+            read: 0,
+        };
+        let detected =
+            detect_encoding_from_reader(&mut reader, None, None, Default::default()).unwrap();
+
+        assert_eq!(reader.read, DETECTION_PREFIX_LEN as usize);
+        assert_eq!(detected.encoding, encoding_rs::UTF_8);
+        assert_eq!(detected.confidence, EncodingConfidence::High);
+    }
+
+    #[test]
+    fn test_detection_bytes_limits_examined_prefix() {
+        let mut data = b"plain ascii".to_vec();
+        data.extend(std::iter::repeat_n(b'x', 4096));
+        data.push(0xff);
+
+        let mut reader = CountingReader {
+            inner: std::io::Cursor::new(data.clone()),
+            read: 0,
+        };
+        let detected =
+            detect_encoding_from_reader(&mut reader, None, Some(1024), Default::default()).unwrap();
+        assert_eq!(reader.read, 1024);
+        assert_eq!(detected.confidence, EncodingConfidence::High);
+
+        // 無效位元組在檢查範圍之外：仍視為 UTF-8，解碼時以 U+FFFD 取代
+        let options = DecodeOptions {
+            detection_bytes: Some(1024),
+            ..Default::default()
+        };
+        assert_eq!(
+            detect_encoding(&data, None, &options).unwrap().confidence,
+            EncodingConfidence::High
+        );
+        assert_ne!(
+            detect_encoding(&data, None, &DecodeOptions::default())
+                .unwrap()
+                .encoding,
+            encoding_rs::UTF_8
+        );
+        let (text, detected) = decode_with_encoding(&data, None, &options).unwrap();
+        assert!(detected.had_errors);
+        assert!(text.ends_with('\u{FFFD}'));
+    }
+
+    #[test]
+    fn test_bom_modes() {
+        let decode = |bytes: &[u8], bom| {
+            let options = DecodeOptions {
+                bom,
+                ..Default::default()
+            };
+            decode_with_encoding(bytes, None, &options).unwrap().0
+        };
+        let utf8_bom = b"\xEF\xBB\xBFhi";
+        assert_eq!(decode(utf8_bom, BomMode::Default), "hi");
+        assert_eq!(decode(utf8_bom, BomMode::Strip), "hi");
+        assert_eq!(decode(utf8_bom, BomMode::Keep), "\u{FEFF}hi");
+
+        // UTF-16 檔案在 BOM 後又多一個 U+FEFF：預設保留，--strip-bom 移除
+        let utf16_double_bom = b"\xFF\xFE\xFF\xFEh\x00";
+        assert_eq!(decode(utf16_double_bom, BomMode::Default), "\u{FEFF}h");
+        assert_eq!(decode(utf16_double_bom, BomMode::Strip), "h");
+
+        // 串流解碼的結果相同
+        let stream = |bytes: &[u8], bom| {
+            let options = DecodeOptions {
+                bom,
+                ..Default::default()
+            };
+            let mut decoded = String::new();
+            DecodingReader::new(TrickleReader(bytes, 1), encoding_rs::UTF_16LE, &options)
+                .read_to_string(&mut decoded)
+                .unwrap();
+            decoded
+        };
+        assert_eq!(stream(utf16_double_bom, BomMode::Strip), "h");
+        assert_eq!(stream(utf16_double_bom, BomMode::Keep), "\u{FEFF}\u{FEFF}h");
+    }
+
+    #[test]
+    fn test_invalid_offsets() {
+        let bytes = b"a\xffb\xfe\xfdc";
+        assert_eq!(invalid_offsets(bytes, encoding_rs::UTF_8, 5), vec![1, 3, 4]);
+        assert_eq!(invalid_offsets(bytes, encoding_rs::UTF_8, 2), vec![1, 3]);
+        assert_eq!(first_invalid_offset(b"ok", encoding_rs::UTF_8), None);
+    }
+
+    #[test]
+    fn test_encode_output() {
+        assert_eq!(
+            encode_output("你好", encoding_rs::GBK).unwrap(),
+            b"\xc4\xe3\xba\xc3"
+        );
+        assert_eq!(encode_output("a", encoding_rs::UTF_16BE).unwrap(), b"\x00a");
+
+        let err = encode_output("a → b ✓", encoding_rs::WINDOWS_1252).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 character(s) cannot be represented in windows-1252"
+        );
+    }
+
+    #[test]
+    fn test_statistical_detection() {
+        let (bytes, _, _) =
+            encoding_rs::SHIFT_JIS.encode("日本語のテキストです。これはテストの文章です。");
+        let options = DecodeOptions::default();
+        let detected = detect_encoding(&bytes, None, &options).unwrap();
+        assert_eq!(detected.encoding, encoding_rs::SHIFT_JIS);
+        assert_eq!(detected.confidence, EncodingConfidence::Medium);
+
+        // 用戶指定的編碼優先於統計偵測
+        let detected = detect_encoding(&bytes, Some(encoding_rs::GBK), &options).unwrap();
+        assert_eq!(detected.encoding, encoding_rs::GBK);
+        assert_eq!(detected.confidence, EncodingConfidence::Certain);
+    }
+
+    #[test]
+    fn test_min_confidence_threshold() {
+        // 非 ASCII 位元組剛好少於 / 達到 GUESS_EVIDENCE_BYTES 的兩個樣本
+        let weak = [b'a', 0xE9].repeat(GUESS_EVIDENCE_BYTES - 1);
+        let strong = [b'a', 0xE9].repeat(GUESS_EVIDENCE_BYTES);
+        assert_eq!(guess_encoding(&weak, true).1, EncodingConfidence::Low);
+        assert_eq!(guess_encoding(&strong, true).1, EncodingConfidence::Medium);
+
+        // 預設（low）採用所有猜測
+        let options = DecodeOptions::default();
+        let detected = detect_encoding(&weak, None, &options).unwrap();
+        assert_eq!(detected.encoding, guess_encoding(&weak, true).0);
+        assert_eq!(detected.confidence, EncodingConfidence::Low);
+
+        // medium：信心剛好達到門檻時採用猜測，低於門檻時回退到系統編碼
+        let mut options = DecodeOptions::default();
+        options.confidence.min = EncodingConfidence::Medium;
+        let detected = detect_encoding(&strong, None, &options).unwrap();
+        assert_eq!(detected.encoding, guess_encoding(&strong, true).0);
+        assert_eq!(detected.confidence, EncodingConfidence::Medium);
+        let detected = detect_encoding(&weak, None, &options).unwrap();
+        assert_eq!(detected.encoding, get_system_encoding());
+        assert_eq!(detected.confidence, EncodingConfidence::Low);
+
+        // high：不採用任何猜測，但用戶指定的編碼不受影響
+        options.confidence.min = EncodingConfidence::High;
+        let detected = detect_encoding(&strong, None, &options).unwrap();
+        assert_eq!(detected.encoding, get_system_encoding());
+        let detected = detect_encoding(&strong, Some(encoding_rs::GBK), &options).unwrap();
+        assert_eq!(detected.confidence, EncodingConfidence::Certain);
+
+        // --strict-confidence：低於門檻時回報錯誤
+        options.confidence.strict = true;
+        let err = detect_encoding(&strong, None, &options).unwrap_err();
+        assert!(err.to_string().contains("--min-confidence"));
+        options.confidence.min = EncodingConfidence::Medium;
+        assert!(detect_encoding(&strong, None, &options).is_ok());
+        assert!(detect_encoding(&weak, None, &options).is_err());
+        // 有效的 UTF-8 不經過統計偵測，不受門檻影響
+        assert!(detect_encoding("純文字".as_bytes(), None, &options).is_ok());
+
+        assert_eq!(
+            "low".parse::<EncodingConfidence>().unwrap(),
+            EncodingConfidence::Low
+        );
+        assert_eq!(
+            "HIGH".parse::<EncodingConfidence>().unwrap(),
+            EncodingConfidence::High
+        );
+        assert!("certain".parse::<EncodingConfidence>().is_err());
+        assert!(EncodingConfidence::Low < EncodingConfidence::Medium);
+        assert!(EncodingConfidence::High < EncodingConfidence::Certain);
+    }
+
+    #[test]
+    fn test_system_encoding_override() {
+        // 不修改行程的環境變數（其他測試會平行地使用系統編碼）
+        assert_eq!(
+            parse_system_encoding_override("gbk"),
+            Some(encoding_rs::GBK)
+        );
+        assert_eq!(
+            parse_system_encoding_override(" Shift_JIS "),
+            Some(encoding_rs::SHIFT_JIS)
+        );
+        assert_eq!(parse_system_encoding_override(""), None);
+
+        let (result, messages) = logging::capture(Level::Normal, || {
+            parse_system_encoding_override("no-such-encoding")
+        });
+        assert_eq!(result, None);
+        assert!(messages[0].contains("CATE_SYSTEM_ENCODING"));
+    }
+
+    #[test]
+    fn test_truncated_utf8_only_valid_for_prefix() {
+        let truncated = &"中".as_bytes()[..2];
+        assert!(is_valid_utf8(truncated, false));
+        assert!(!is_valid_utf8(truncated, true));
+        assert!(!is_valid_utf8(b"\xff", false));
+    }
+
+    #[test]
+    fn test_byte_range_from_ascii_file() {
+        let path = std::env::temp_dir().join(format!("cate-range-{}.txt", std::process::id()));
+        fs::write(&path, "0123456789abcdef").unwrap();
+        let read = |range: &str| {
+            read_file_range_with_encoding(
+                &path,
+                range.parse().unwrap(),
+                None,
+                &DecodeOptions::default(),
+            )
+            .unwrap()
+            .0
+        };
+
+        assert_eq!(read("4:10"), "456789");
+        assert_eq!(read(":3"), "012");
+        assert_eq!(read("12:"), "cdef");
+        // 超出檔案結尾
+        assert_eq!(read("14:100"), "ef");
+        assert_eq!(read("100:200"), "");
+        fs::remove_file(&path).unwrap();
+
+        assert!("5:2".parse::<ByteRange>().is_err());
+        assert!("abc".parse::<ByteRange>().is_err());
+    }
+
+    #[test]
+    fn test_byte_range_snaps_to_utf8_boundaries() {
+        // "中文" = E4 B8 AD E6 96 87，範圍從第一個字的中間開始
+        let range = "1:6".parse().unwrap();
+        let (content, detected) =
+            decode_range_with_encoding("中文".as_bytes(), range, None, &DecodeOptions::default())
+                .unwrap();
+        assert_eq!(content, "文");
+        assert!(!detected.had_errors);
+
+        let range = "0:5".parse().unwrap();
+        let (content, _) =
+            decode_range_with_encoding("中文".as_bytes(), range, None, &DecodeOptions::default())
+                .unwrap();
+        assert_eq!(content, "中");
+    }
+
+    #[test]
+    fn test_line_index_reconstructs_lines() {
+        let text = "first\r\n第二行\n\nlast";
+        let bytes = text.as_bytes();
+        let spans = line_index(bytes, None);
+
+        let lines: Vec<&[u8]> = spans
+            .iter()
+            .map(|span| &bytes[span.offset..span.offset + span.length])
+            .collect();
+        let expected: Vec<&[u8]> = text.split_inclusive('\n').map(str::as_bytes).collect();
+        assert_eq!(lines, expected);
+
+        // UTF-16LE：跳過 BOM，換行佔兩個位元組
+        let mut utf16 = vec![0xFF, 0xFE];
+        for unit in "a\nbc\n".encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(
+            line_index(&utf16, None),
+            vec![
+                LineSpan {
+                    offset: 2,
+                    length: 4
+                },
+                LineSpan {
+                    offset: 6,
+                    length: 6
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dump_detection_with_bom() {
+        let dump = dump_detection(b"\xef\xbb\xbfhello\n", true, None);
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines[0], "bytes: 9");
+        assert!(lines[1].starts_with("  00000000  ef bb bf 68 65 6c 6c 6f 0a"));
+        assert!(lines[1].ends_with("|...hello.|"));
+        assert!(lines.contains(&"bom: UTF-8 (3 bytes)"));
+        assert!(lines.contains(&"valid utf-8: yes"));
+        assert_eq!(lines.last(), Some(&"chosen: UTF-8 (Certain)"));
+
+        let dump = dump_detection(b"\xff\xfeh\x00i\x00", true, None);
+        assert!(dump.contains("bom: UTF-16LE (2 bytes)"));
+        assert!(dump.ends_with("chosen: UTF-16LE (Certain)\n"));
+    }
+
+    #[test]
+    fn test_replacement_char() {
+        let options = DecodeOptions {
+            replacement: Some('?'),
+            ..Default::default()
+        };
+        let (content, detected) =
+            decode_with_encoding(b"ok \xff\xfe end", Some(encoding_rs::UTF_8), &options).unwrap();
+        assert!(detected.had_errors);
+        assert_eq!(content, "ok ?? end");
+
+        // 沒有解碼錯誤時，原本就存在的 U+FFFD 不受影響
+        let (content, _) =
+            decode_with_encoding("keep \u{FFFD}".as_bytes(), None, &options).unwrap();
+        assert_eq!(content, "keep \u{FFFD}");
+    }
+
+    #[test]
+    fn test_decode_bytes_options() {
+        let decode = |bytes: &[u8], encoding, options: DecodeOptions| {
+            let detected = DetectedEncoding {
+                encoding,
+                confidence: EncodingConfidence::Certain,
+                had_errors: false,
+                error_offsets: Vec::new(),
+            };
+            decode_bytes(bytes, detected, &options)
+        };
+        let invalid = b"a\xffb \xef\xbf\xbd";
+
+        // 預設取代為 U+FFFD；原本就存在的 U+FFFD 不受取代字元影響
+        let (content, detected) = decode(invalid, encoding_rs::UTF_8, Default::default()).unwrap();
+        assert_eq!(content, "a\u{FFFD}b \u{FFFD}");
+        assert!(detected.had_errors);
+        assert_eq!(detected.error_offsets, vec![1]);
+
+        let replaced = DecodeOptions {
+            replacement: Some('?'),
+            ..Default::default()
+        };
+        let (content, _) = decode(invalid, encoding_rs::UTF_8, replaced).unwrap();
+        assert_eq!(content, "a?b \u{FFFD}");
+
+        let skip = DecodeOptions {
+            invalid: InvalidMode::Skip,
+            ..Default::default()
+        };
+        let (content, _) = decode(invalid, encoding_rs::UTF_8, skip.clone()).unwrap();
+        assert_eq!(content, "ab \u{FFFD}");
+
+        let strict = DecodeOptions {
+            invalid: InvalidMode::Error,
+            ..Default::default()
+        };
+        let err = decode(invalid, encoding_rs::UTF_8, strict).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid UTF-8 data at byte offset 1");
+
+        // BOM 處理與無法解碼的位元組可以同時套用
+        let utf16 = b"\xFF\xFEh\x00\x00\xD8i\x00";
+        let (content, _) = decode(utf16, encoding_rs::UTF_16LE, skip).unwrap();
+        assert_eq!(content, "hi");
+        let keep = DecodeOptions {
+            bom: BomMode::Keep,
+            replacement: Some('?'),
+            ..Default::default()
+        };
+        let (content, _) = decode(utf16, encoding_rs::UTF_16LE, keep).unwrap();
+        assert_eq!(content, "\u{FEFF}h?i");
+    }
+
+    #[test]
+    fn test_transport_decoding() {
+        let options = DecodeOptions {
+            transport: Some(TransportEncoding::Base64),
+            ..Default::default()
+        };
+        // 換行與省略的 `=` 都可以接受；還原後照常偵測編碼
+        let (content, detected) =
+            decode_with_encoding(b"5L2g5aW9\nLCBjYXRl\nIQo\n", None, &options).unwrap();
+        assert_eq!(content, "你好, cate!\n");
+        assert_eq!(detected.encoding, encoding_rs::UTF_8);
+        assert!(decode_with_encoding(b"not*base64", None, &options).is_err());
+
+        let hex = DecodeOptions {
+            transport: Some(TransportEncoding::Hex),
+            ..Default::default()
+        };
+        let (content, _) = decode_with_encoding(b"68 69 0A", None, &hex).unwrap();
+        assert_eq!(content, "hi\n");
+        assert_eq!(
+            TransportEncoding::Hex.decode(b"6").unwrap_err().to_string(),
+            "Invalid hex input: odd number of digits"
+        );
+        assert!(TransportEncoding::Hex.decode(b"zz").is_err());
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        assert!(looks_binary(png));
+        assert!(!looks_binary(b"plain text\twith tabs\r\n"));
+        assert!(!looks_binary("中文 \x1b[31mred\x1b[0m\n".as_bytes()));
+        assert!(!looks_binary(b""));
+
+        // 有 BOM 的 UTF-16 雖然有 NUL 也是文字
+        assert!(!looks_binary(b"\xFF\xFEh\x00i\x00"));
+
+        // 控制字元超過 10% 才算二進位
+        let mut bytes = vec![b'a'; 90];
+        bytes.extend([0x01; 10]);
+        assert!(!looks_binary(&bytes));
+        bytes.push(0x02);
+        assert!(looks_binary(&bytes));
+
+        // 只看開頭：NUL 在 BINARY_SNIFF_LEN 之後不算
+        let mut late_nul = vec![b'a'; BINARY_SNIFF_LEN];
+        late_nul.push(0);
+        assert!(!looks_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_first_invalid_offset() {
+        assert_eq!(
+            first_invalid_offset(b"abc\xffdef", encoding_rs::UTF_8),
+            Some(3)
+        );
+        assert_eq!(first_invalid_offset(b"abcdef", encoding_rs::UTF_8), None);
+    }
+}
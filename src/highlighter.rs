@@ -1,9 +1,14 @@
 use anyhow::{Context, Result};
-use once_cell::sync::Lazy;
-use std::path::Path;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, Theme, ThemeSet};
-use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::easy::ScopeRangeIterator;
+use syntect::highlighting::{
+    Color, HighlightState, Highlighter as SyntectHighlighter, RangedHighlightIterator,
+    ScopeSelectors, Style, Theme, ThemeSet,
+};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
 use syntect::util::as_24_bit_terminal_escaped;
 
 /// 嵌入的語法集（來自 bat 專案）
@@ -17,14 +22,111 @@ const SERIALIZED_SYNTAX_SET: &[u8] = include_bytes!("../assets/syntaxes.bin");
 /// 語法集是否壓縮（與 bat 保持一致）
 const COMPRESS_SYNTAXES: bool = false;
 
-/// 全域語法集（延遲載入，使用 bat 的載入方式）
+/// 全域語法集（延遲載入，使用 bat 的載入方式；有 --syntax-dir 時再連結使用者語法）
 static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
-    load_from_binary(SERIALIZED_SYNTAX_SET, COMPRESS_SYNTAXES)
-        .expect("Failed to load embedded syntax set")
+    let syntaxes = load_from_binary(SERIALIZED_SYNTAX_SET, COMPRESS_SYNTAXES)
+        .expect("Failed to load embedded syntax set");
+    match SYNTAX_DIR.get() {
+        Some(dir) => add_user_syntaxes(syntaxes, dir),
+        None => syntaxes,
+    }
+});
+
+/// --syntax-dir 指定的語法目錄（必須在第一次使用語法集前設定）
+static SYNTAX_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// 設定使用者語法目錄（.sublime-syntax）
+pub fn set_syntax_dir(dir: PathBuf) {
+    let _ = SYNTAX_DIR.set(dir);
+}
+
+/// 將目錄中的 .sublime-syntax 加入內嵌語法集並重新連結；
+/// 後加入的語法在依副檔名/名稱查找時優先。載入失敗只發出警告
+fn add_user_syntaxes(syntaxes: SyntaxSet, dir: &Path) -> SyntaxSet {
+    if !dir.is_dir() {
+        warn!("Syntax directory not found: {}", dir.display());
+        return syntaxes;
+    }
+    let mut builder = syntaxes.into_builder();
+    if let Err(e) = builder.add_from_folder(dir, true) {
+        warn!("{}: failed to load syntaxes: {}", dir.display(), e);
+    }
+    builder.build()
+}
+
+/// 全域主題集（syntect 內建主題，加上使用者主題目錄中的 .tmTheme）
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
+    let mut themes = ThemeSet::load_defaults();
+    if let Some(dir) = user_theme_dir() {
+        load_user_themes(&mut themes, &dir);
+    }
+    themes
 });
 
-/// 全域主題集（使用 syntect 內建主題）
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+/// --theme-dir 指定的主題目錄（必須在第一次使用主題前設定）
+static THEME_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// 設定使用者主題目錄，取代預設的 `$CATE_CONFIG_DIR/themes`
+pub fn set_theme_dir(dir: PathBuf) {
+    let _ = THEME_DIR.set(dir);
+}
+
+/// 使用者主題目錄：--theme-dir，否則為 `$CATE_CONFIG_DIR/themes`
+fn user_theme_dir() -> Option<PathBuf> {
+    THEME_DIR.get().cloned().or_else(|| {
+        std::env::var_os("CATE_CONFIG_DIR").map(|dir| PathBuf::from(dir).join("themes"))
+    })
+}
+
+/// 載入目錄中的 .tmTheme 主題（同名時取代內建主題）；目錄不存在時略過，載入失敗只發出警告
+fn load_user_themes(themes: &mut ThemeSet, dir: &Path) {
+    if !dir.is_dir() {
+        debug!("Theme directory not found: {}", dir.display());
+        return;
+    }
+    if let Err(e) = themes.add_from_folder(dir) {
+        warn!("{}: failed to load themes: {}", dir.display(), e);
+    }
+}
+
+/// 預設主題（深色背景，或無法判斷背景時）
+pub const DEFAULT_THEME: &str = "base16-eighties.dark";
+/// 偵測到淺色背景時的預設主題
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+
+/// 內建的主題別名（別名 → 完整主題名稱）
+const THEME_ALIASES: &[(&str, &str)] = &[("dark", DEFAULT_THEME), ("light", DEFAULT_LIGHT_THEME)];
+
+/// 所有主題別名：CATE_THEME_ALIASES（`別名=主題,…`）定義的在前，優先於內建別名
+pub fn theme_aliases() -> Vec<(String, String)> {
+    parse_theme_aliases(std::env::var("CATE_THEME_ALIASES").ok().as_deref())
+}
+
+fn parse_theme_aliases(user_aliases: Option<&str>) -> Vec<(String, String)> {
+    let user = user_aliases
+        .into_iter()
+        .flat_map(|list| list.split(','))
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(alias, theme)| (alias.trim().to_string(), theme.trim().to_string()))
+        .filter(|(alias, theme)| !alias.is_empty() && !theme.is_empty());
+    let builtin = THEME_ALIASES
+        .iter()
+        .map(|(alias, theme)| (alias.to_string(), theme.to_string()));
+    user.chain(builtin).collect()
+}
+
+/// 將主題別名解析為完整名稱；不是別名時原樣回傳
+fn resolve_theme_alias(name: &str, aliases: &[(String, String)]) -> String {
+    aliases
+        .iter()
+        .find(|(alias, _)| alias == name)
+        .map_or_else(|| name.to_string(), |(_, theme)| theme.clone())
+}
+
+/// 所有註解 scope 的共同前綴（comment.line、comment.block…）
+static COMMENT_SCOPE: Lazy<Scope> = Lazy::new(|| Scope::new("comment").unwrap());
+/// 所有字串 scope 的共同前綴（string.quoted.double…）
+static STRING_SCOPE: Lazy<Scope> = Lazy::new(|| Scope::new("string").unwrap());
 
 /// 從二進位資料載入（與 bat 的 from_binary 相同邏輯）
 fn load_from_binary<T>(data: &[u8], compressed: bool) -> Result<T>
@@ -43,19 +145,55 @@ where
 pub struct Highlighter {
     theme: Theme,
     true_color: bool,
+    basic_colors: bool,                 // 只使用 16 色（優先於 true_color）
+    dim_scopes: Option<ScopeSelectors>, // 符合這些 scope 的片段以低亮度（SGR 2）輸出
 }
 
 impl Highlighter {
     /// 建立新的高亮器
     pub fn new(theme_name: Option<&str>, true_color: bool) -> Result<Self> {
-        let theme_name = theme_name.unwrap_or("base16-eighties.dark");
+        let theme_name = resolve_theme_alias(theme_name.unwrap_or(DEFAULT_THEME), &theme_aliases());
         let theme = THEME_SET
             .themes
-            .get(theme_name)
+            .get(&theme_name)
             .context(format!("Theme '{}' not found", theme_name))?
             .clone();
 
-        Ok(Self { theme, true_color })
+        Ok(Self {
+            theme,
+            true_color,
+            basic_colors: false,
+            dim_scopes: None,
+        })
+    }
+
+    /// 只輸出 16 色（`\x1b[3Xm` / `\x1b[9Xm`），給不支援 256 色的終端機使用
+    pub fn with_basic_colors(mut self) -> Self {
+        self.basic_colors = true;
+        self.true_color = false;
+        self
+    }
+
+    /// 以低亮度輸出符合 `scopes` 的片段（例如註解、import、字串），讓其餘程式碼更顯眼
+    pub fn with_dimmed_scopes(mut self, scopes: ScopeSelectors) -> Self {
+        self.dim_scopes = Some(scopes);
+        self
+    }
+
+    /// 反轉主題所有顏色的亮度（深色主題用在淺色終端機，或反之）
+    ///
+    /// 只翻轉亮度、保留色相與飽和度；中等亮度的顏色幾乎不變，
+    /// 效果不如專為淺色背景設計的主題
+    pub fn with_inverted_colors(mut self) -> Self {
+        let settings = &mut self.theme.settings;
+        for color in [&mut settings.foreground, &mut settings.background] {
+            *color = color.map(invert_lightness);
+        }
+        for item in &mut self.theme.scopes {
+            item.style.foreground = item.style.foreground.map(invert_lightness);
+            item.style.background = item.style.background.map(invert_lightness);
+        }
+        self
     }
 
     /// 準備一個逐行高亮器
@@ -65,19 +203,105 @@ impl Highlighter {
         first_line: Option<&str>,
         language: Option<&str>,
     ) -> LineHighlighter<'a> {
-        // 檢測語法（優先使用手動指定的語言）
-        let syntax = if let Some(lang) = language {
-            self.find_syntax_by_name(lang)
-                .unwrap_or_else(|| self.detect_syntax(first_line, file_path))
-        } else {
-            self.detect_syntax(first_line, file_path)
-        };
+        let syntax = self.select_syntax(file_path, first_line, language);
         let is_plain_text = syntax.name == "Plain Text";
 
+        let highlighter = SyntectHighlighter::new(&self.theme);
+        let highlight_state = HighlightState::new(&highlighter, ScopeStack::new());
+
         LineHighlighter {
-            highlighter: HighlightLines::new(syntax, &self.theme),
+            highlighter,
+            parse_state: ParseState::new(syntax),
+            highlight_state,
+            dim_scopes: self.dim_scopes.as_ref(),
             true_color: self.true_color,
+            basic_colors: self.basic_colors,
             is_plain_text,
+            style_cache: Some(HashMap::new()),
+        }
+    }
+
+    /// 逐行回傳帶樣式的片段（不含行尾換行符），供 SVG 等非終端機輸出使用
+    pub fn styled_lines(
+        &self,
+        content: &str,
+        file_path: Option<&Path>,
+        language: Option<&str>,
+    ) -> Result<Vec<Vec<(Style, String)>>> {
+        let syntax = self.select_syntax(file_path, content.lines().next(), language);
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+
+        content
+            .split_inclusive('\n')
+            .map(|line| {
+                let body = strip_line_ending(line);
+                let ranges = highlighter
+                    .highlight_line(line, &SYNTAX_SET)
+                    .context("Failed to highlight line")?;
+
+                let mut remaining = body.len();
+                Ok(ranges
+                    .into_iter()
+                    .filter_map(|(style, text)| {
+                        let take = text.len().min(remaining);
+                        remaining -= take;
+                        (take > 0).then(|| (style, text[..take].to_string()))
+                    })
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// 主題的預設前景色與背景色
+    pub fn theme_colors(&self) -> (Color, Color) {
+        let settings = &self.theme.settings;
+        (
+            settings.foreground.unwrap_or(Color::WHITE),
+            settings.background.unwrap_or(Color::BLACK),
+        )
+    }
+
+    /// 回傳檔案會使用的語法名稱
+    pub fn syntax_name(
+        &self,
+        file_path: Option<&Path>,
+        first_line: Option<&str>,
+        language: Option<&str>,
+    ) -> &str {
+        &self.select_syntax(file_path, first_line, language).name
+    }
+
+    /// 回傳檔案會使用的語法名稱，以及是哪一條規則選中的（--debug 用來說明高亮結果）
+    pub fn explain_syntax(
+        &self,
+        file_path: Option<&Path>,
+        first_line: Option<&str>,
+        language: Option<&str>,
+    ) -> (&str, SyntaxSource) {
+        let (syntax, source) = self.select_syntax_with_source(file_path, first_line, language);
+        (&syntax.name, source)
+    }
+
+    /// 選擇語法（優先使用手動指定的語言）
+    fn select_syntax(
+        &self,
+        file_path: Option<&Path>,
+        first_line: Option<&str>,
+        language: Option<&str>,
+    ) -> &SyntaxReference {
+        self.select_syntax_with_source(file_path, first_line, language)
+            .0
+    }
+
+    fn select_syntax_with_source(
+        &self,
+        file_path: Option<&Path>,
+        first_line: Option<&str>,
+        language: Option<&str>,
+    ) -> (&SyntaxReference, SyntaxSource) {
+        match language.and_then(|lang| self.find_syntax_by_name(lang)) {
+            Some(syntax) => (syntax, SyntaxSource::Language),
+            None => self.detect_syntax(first_line, file_path),
         }
     }
 
@@ -103,20 +327,36 @@ impl Highlighter {
         &self,
         first_line: Option<&str>,
         file_path: Option<&Path>,
-    ) -> &SyntaxReference {
+    ) -> (&SyntaxReference, SyntaxSource) {
+        let filetype = std::env::var("CATE_FILETYPE").ok();
+        self.detect_syntax_with_filetype(first_line, file_path, filetype.as_deref())
+    }
+
+    /// 檢測語法；都無法判斷時使用 `filetype`（來自 CATE_FILETYPE）作為預設語言
+    fn detect_syntax_with_filetype(
+        &self,
+        first_line: Option<&str>,
+        file_path: Option<&Path>,
+        filetype: Option<&str>,
+    ) -> (&SyntaxReference, SyntaxSource) {
         // 1. 嘗試從檔案路徑檢測
         if let Some(path) = file_path {
-            // 從副檔名檢測
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if let Some(syntax) = SYNTAX_SET.find_syntax_by_extension(ext) {
-                    return syntax;
+            // 從副檔名檢測（內嵌多種語言的檔案優先使用容器語法）
+            // 非 UTF-8 的路徑以 lossy 方式轉換後比對（副檔名本身通常是 ASCII）
+            if let Some(ext) = path.extension().map(|e| e.to_string_lossy()) {
+                if let Some(syntax) = container_syntax(&ext) {
+                    return (syntax, SyntaxSource::Container(ext.into_owned()));
+                }
+                if let Some(syntax) = SYNTAX_SET.find_syntax_by_extension(&ext) {
+                    return (syntax, SyntaxSource::Extension(ext.into_owned()));
                 }
             }
 
             // 從檔名檢測（例如 Makefile, Dockerfile）
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if let Some(syntax) = SYNTAX_SET.find_syntax_by_name(name) {
-                    return syntax;
+            if let Some(name) = path.file_name().map(|n| n.to_string_lossy()) {
+                let by_name = |syntax| (syntax, SyntaxSource::FileName(name.to_string()));
+                if let Some(syntax) = SYNTAX_SET.find_syntax_by_name(&name) {
+                    return by_name(syntax);
                 }
 
                 // 特殊檔名處理
@@ -144,7 +384,7 @@ impl Highlighter {
                         .or_else(|| SYNTAX_SET.find_syntax_by_name("Shell Script (Bash)"))
                         .or_else(|| SYNTAX_SET.find_syntax_by_extension("sh"))
                     {
-                        return syntax;
+                        return by_name(syntax);
                     }
                 }
 
@@ -152,12 +392,12 @@ impl Highlighter {
                 match name_lower.as_str() {
                     "makefile" | "gnumakefile" => {
                         if let Some(syntax) = SYNTAX_SET.find_syntax_by_name("Makefile") {
-                            return syntax;
+                            return by_name(syntax);
                         }
                     }
                     "dockerfile" => {
                         if let Some(syntax) = SYNTAX_SET.find_syntax_by_name("Dockerfile") {
-                            return syntax;
+                            return by_name(syntax);
                         }
                     }
                     _ => {}
@@ -169,13 +409,18 @@ impl Highlighter {
         if let Some(first_line) = first_line {
             if first_line.starts_with("#!") {
                 if let Some(syntax) = SYNTAX_SET.find_syntax_by_first_line(first_line) {
-                    return syntax;
+                    return (syntax, SyntaxSource::Shebang);
                 }
             }
         }
 
-        // 3. 回退到純文字
-        SYNTAX_SET.find_syntax_plain_text()
+        // 3. 包裝腳本透過 CATE_FILETYPE 提供的預設語言
+        if let Some(syntax) = filetype.and_then(|name| self.find_syntax_by_name(name.trim())) {
+            return (syntax, SyntaxSource::Filetype);
+        }
+
+        // 4. 回退到純文字
+        (SYNTAX_SET.find_syntax_plain_text(), SyntaxSource::Fallback)
     }
 
     /// 列出可用主題
@@ -191,49 +436,246 @@ impl Highlighter {
             .map(|s| s.name.clone())
             .collect()
     }
+
+    /// 列出可用語法與其最上層 scope（例如 `Rust` → `source.rust`）
+    pub fn available_syntax_scopes() -> Vec<(String, String)> {
+        SYNTAX_SET
+            .syntaxes()
+            .iter()
+            .map(|s| (s.name.clone(), s.scope.build_string()))
+            .collect()
+    }
+}
+
+/// --dim-uninteresting 預設變暗的 scope：註解、字串與 import（依各語法的 scope 命名）
+pub const DEFAULT_DIM_SCOPES: &str = "comment, string, meta.import, meta.statement.import, \
+     keyword.control.import, keyword.other.import, keyword.operator.module";
+
+/// 解析以逗號分隔的 scope selector（例如 `comment, string.quoted`）
+///
+/// 空的項目會比對到所有 scope，因此視為錯誤
+pub fn parse_scope_selectors(list: &str) -> Result<ScopeSelectors> {
+    if list.split(',').any(|scope| scope.trim().is_empty()) {
+        anyhow::bail!("Invalid scope list '{}': empty scope", list);
+    }
+    list.parse()
+        .with_context(|| format!("Invalid scope list '{}'", list))
+}
+
+/// 語法是由哪一條規則選中的
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxSource {
+    Language,          // -l 指定
+    Container(String), // 內嵌多種語言的副檔名（例如 .vue）
+    Extension(String), // 副檔名
+    FileName(String),  // 檔名（例如 Makefile、.bashrc）
+    Shebang,           // 第一行的 #!
+    Filetype,          // CATE_FILETYPE 環境變數
+    Fallback,          // 都不符合，使用純文字
+}
+
+impl std::fmt::Display for SyntaxSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxSource::Language => write!(f, "-l/--language"),
+            SyntaxSource::Container(ext) => write!(f, "container extension .{}", ext),
+            SyntaxSource::Extension(ext) => write!(f, "extension .{}", ext),
+            SyntaxSource::FileName(name) => write!(f, "file name {}", name),
+            SyntaxSource::Shebang => write!(f, "shebang line"),
+            SyntaxSource::Filetype => write!(f, "CATE_FILETYPE"),
+            SyntaxSource::Fallback => write!(f, "fallback (nothing matched)"),
+        }
+    }
 }
 
 /// 逐行高亮器（有狀態）
 pub struct LineHighlighter<'a> {
-    highlighter: HighlightLines<'a>,
+    // 與 syntect 的 HighlightLines 相同，但需要 scope 資訊來決定哪些片段要變暗
+    highlighter: SyntectHighlighter<'a>,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+    dim_scopes: Option<&'a ScopeSelectors>,
     true_color: bool,
+    basic_colors: bool,
     is_plain_text: bool,
+    // 每種樣式的跳脫序列只計算一次（同一檔案中的樣式種類很少）
+    style_cache: Option<HashMap<Style, String>>,
 }
 
 impl<'a> LineHighlighter<'a> {
+    /// 除錯用：純文字也經過完整的高亮流程（不跳過跳脫序列的產生）
+    pub fn force_syntax_path(&mut self) {
+        self.is_plain_text = false;
+    }
+
+    /// 除錯用：目前（最後一行結束時）的 scope 堆疊深度與內容，
+    /// 例如 `depth 2 [source.rust string.quoted.double.rust]`；未結束的字串會一直留在堆疊中
+    pub fn state_summary(&self) -> String {
+        let scopes = self.highlight_state.path.as_slice();
+        let names: Vec<String> = scopes.iter().map(|scope| scope.build_string()).collect();
+        format!("depth {} [{}]", scopes.len(), names.join(" "))
+    }
+
+    /// 這一行中位於字串 scope 內的位元組範圍；只向前看，不改變語法狀態
+    pub fn string_ranges(&self, line: &str) -> Vec<std::ops::Range<usize>> {
+        if self.is_plain_text {
+            return Vec::new();
+        }
+
+        let mut parse_state = self.parse_state.clone();
+        let Ok(ops) = parse_state.parse_line(line, &SYNTAX_SET) else {
+            return Vec::new();
+        };
+        let mut stack = self.highlight_state.path.clone();
+        let mut ranges = Vec::new();
+        for (range, op) in ScopeRangeIterator::new(&ops, line) {
+            if stack.apply(op).is_err() {
+                break;
+            }
+            let in_string = stack
+                .as_slice()
+                .iter()
+                .any(|scope| STRING_SCOPE.is_prefix_of(*scope));
+            if in_string && !range.is_empty() {
+                ranges.push(range);
+            }
+        }
+        ranges
+    }
+
+    /// 這一行是否整行都是註解（空白除外）；只向前看，不改變語法狀態
+    pub fn is_comment_line(&self, line: &str) -> bool {
+        if self.is_plain_text {
+            return false;
+        }
+
+        let mut parse_state = self.parse_state.clone();
+        let Ok(ops) = parse_state.parse_line(line, &SYNTAX_SET) else {
+            return false;
+        };
+        let mut stack = self.highlight_state.path.clone();
+        ScopeRangeIterator::new(&ops, line).all(|(range, op)| {
+            stack.apply(op).is_ok()
+                && (line[range].trim().is_empty()
+                    || stack
+                        .as_slice()
+                        .iter()
+                        .any(|scope| COMMENT_SCOPE.is_prefix_of(*scope)))
+        })
+    }
+
     /// 高亮單行（保持語法狀態）
+    ///
+    /// 輸入需包含行尾換行符（語法狀態依賴它），回傳的字串不含行尾換行符，
+    /// 並在有顏色輸出時以重設序列結尾
     pub fn highlight_line(&mut self, line: &str) -> Result<String> {
+        let body = strip_line_ending(line);
+
         // 如果是純文字，直接返回
         if self.is_plain_text {
-            return Ok(line.to_string());
+            return Ok(body.to_string());
         }
 
         // 長行保護：超過 16KB 的行跳過語法高亮（與 bat 一致）
         const MAX_LINE_LENGTH: usize = 16 * 1024;
         if line.len() > MAX_LINE_LENGTH {
             // 仍然需要高亮一個換行符來更新狀態
-            let _ = self
-                .highlighter
-                .highlight_line("\n", &SYNTAX_SET)
-                .context("Failed to highlight line")?;
-            return Ok(line.to_string());
+            self.highlight_ranges("\n")?;
+            return Ok(body.to_string());
         }
 
         // 逐行高亮
-        let ranges: Vec<(Style, &str)> = self
-            .highlighter
-            .highlight_line(line, &SYNTAX_SET)
-            .context("Failed to highlight line")?;
+        let ranges = self.highlight_ranges(line)?;
 
-        let escaped = if self.true_color {
-            as_24_bit_terminal_escaped(&ranges[..], false)
-        } else {
-            self.as_8bit_terminal_escaped(&ranges[..])
+        // 去掉行尾換行符，讓呼叫端自行決定如何輸出行尾
+        let mut remaining = body.len();
+        let ranges: Vec<(Style, &str, bool)> = ranges
+            .into_iter()
+            .filter_map(|(style, text, dim)| {
+                let take = text.len().min(remaining);
+                remaining -= take;
+                (take > 0).then(|| (style, &text[..take], dim))
+            })
+            .collect();
+
+        if ranges.is_empty() {
+            return Ok(String::new());
+        }
+
+        let mut escaped = match self.style_cache {
+            Some(ref mut cache) => {
+                let mut output = String::with_capacity(body.len() * 2);
+                for (style, text, dim) in &ranges {
+                    let prefix = cache
+                        .entry(*style)
+                        .or_insert_with(|| style_prefix(style, self.true_color, self.basic_colors));
+                    if *dim {
+                        output.push_str("\x1b[2m");
+                    }
+                    output.push_str(prefix);
+                    output.push_str(text);
+                    if !self.true_color {
+                        output.push_str("\x1b[0m");
+                    } else if *dim {
+                        output.push_str("\x1b[22m");
+                    }
+                }
+                output
+            }
+            // 不使用快取的參考實作（只用於比對輸出，不處理變暗）
+            None => {
+                let ranges: Vec<(Style, &str)> = ranges
+                    .iter()
+                    .map(|&(style, text, _)| (style, text))
+                    .collect();
+                if self.basic_colors {
+                    ranges
+                        .iter()
+                        .map(|(style, text)| {
+                            format!("{}{}\x1b[0m", style_prefix(style, false, true), text)
+                        })
+                        .collect()
+                } else if self.true_color {
+                    as_24_bit_terminal_escaped(&ranges[..], false)
+                } else {
+                    self.as_8bit_terminal_escaped(&ranges[..])
+                }
+            }
         };
+        escaped.push_str("\x1b[0m");
 
         Ok(escaped)
     }
 
+    /// 解析並高亮一行，回傳每個片段的樣式，以及是否落在要變暗的 scope 中
+    fn highlight_ranges<'b>(&mut self, line: &'b str) -> Result<Vec<(Style, &'b str, bool)>> {
+        let ops = self
+            .parse_state
+            .parse_line(line, &SYNTAX_SET)
+            .context("Failed to highlight line")?;
+
+        // 從這一行開頭的 scope 堆疊逐一套用操作，記下要變暗的區段
+        let mut dim_regions = Vec::new();
+        if let Some(selectors) = self.dim_scopes {
+            let mut stack = self.highlight_state.path.clone();
+            for (range, op) in ScopeRangeIterator::new(&ops, line) {
+                stack.apply(op).context("Failed to highlight line")?;
+                if selectors.does_match(stack.as_slice()).is_some() {
+                    dim_regions.push(range);
+                }
+            }
+        }
+
+        let iter =
+            RangedHighlightIterator::new(&mut self.highlight_state, &ops, line, &self.highlighter);
+        Ok(iter
+            .map(|(style, text, range)| {
+                let dim = dim_regions.iter().any(|r| r.contains(&range.start));
+                (style, text, dim)
+            })
+            .collect())
+    }
+
     /// 將 syntect 顏色轉為 8-bit ANSI 色碼（相容模式）
     fn as_8bit_terminal_escaped(&self, ranges: &[(Style, &str)]) -> String {
         let mut output = String::new();
@@ -249,23 +691,376 @@ impl<'a> LineHighlighter<'a> {
     }
 }
 
+/// 內嵌 HTML / JS / CSS 的容器格式：副檔名對應的頂層語法
+///
+/// 選中容器語法後，`<script>` / `<style>` 等區塊會由 syntect 切換到內嵌語法，
+/// 語法狀態由 LineHighlighter 跨行保留
+const CONTAINER_SYNTAXES: &[(&str, &str)] = &[
+    ("vue", "Vue Component"),
+    ("svelte", "Svelte"),
+    ("svlt", "Svelte"),
+    ("php", "PHP"),
+    ("phtml", "PHP"),
+    ("php8", "PHP"),
+    ("ctp", "PHP"),
+];
+
+fn container_syntax(ext: &str) -> Option<&'static SyntaxReference> {
+    let ext = ext.to_lowercase();
+    CONTAINER_SYNTAXES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .and_then(|(_, name)| SYNTAX_SET.find_syntax_by_name(name))
+}
+
+/// 樣式對應的前景色跳脫序列（與 as_24_bit_terminal_escaped / as_8bit_terminal_escaped 相同；
+/// 16 色模式取最接近的標準色）
+fn style_prefix(style: &Style, true_color: bool, basic_colors: bool) -> String {
+    let fg = style.foreground;
+    if basic_colors {
+        let index = crate::ansi::ansi16_from_rgb((fg.r, fg.g, fg.b));
+        format!("\x1b[{}m", crate::ansi::sgr_16(30, index))
+    } else if true_color {
+        // 半透明前景色與背景混色（與 syntect 的 blend_fg_color 相同）
+        let blend = |f: u8, b: u8| {
+            let ratio = fg.a as u32;
+            ((f as u32 * ratio + b as u32 * (255 - ratio)) / 255) as u8
+        };
+        let bg = style.background;
+        format!(
+            "\x1b[38;2;{};{};{}m",
+            blend(fg.r, bg.r),
+            blend(fg.g, bg.g),
+            blend(fg.b, bg.b)
+        )
+    } else {
+        let color_code = ansi_colours::ansi256_from_rgb((fg.r, fg.g, fg.b));
+        format!("\x1b[38;5;{}m", color_code)
+    }
+}
+
+/// 反轉顏色的 HSL 亮度（L → 1 - L），色相與飽和度不變
+///
+/// 等同於每個通道加上 `255 - max - min`，結果必定在 0..=255 之間
+fn invert_lightness(color: Color) -> Color {
+    let max = color.r.max(color.g).max(color.b) as i32;
+    let min = color.r.min(color.g).min(color.b) as i32;
+    let shift = |c: u8| (c as i32 + 255 - max - min) as u8;
+    Color {
+        r: shift(color.r),
+        g: shift(color.g),
+        b: shift(color.b),
+        a: color.a,
+    }
+}
+
+/// 依內容特徵猜測語言（只看前 50 行），回傳語法名稱與對應的 `-l` 參數
+///
+/// 只用於除錯提示，不影響實際使用的語法
+fn sniff_syntax(content: &str) -> Option<(&'static str, &'static str)> {
+    let head = content.trim_start();
+    if head.starts_with("<?xml") {
+        return Some(("XML", "xml"));
+    }
+    let lower = head.get(..15).unwrap_or(head).to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some(("HTML", "html"));
+    }
+    if (head.starts_with('{') || head.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+    {
+        return Some(("JSON", "json"));
+    }
+
+    let (mut python, mut rust) = (0, 0);
+    for line in content.lines().take(50) {
+        let line = line.trim();
+        if (line.starts_with("def ") || line.starts_with("class ")) && line.ends_with(':')
+            || line.starts_with("from ") && line.contains(" import ")
+            || line.starts_with("if __name__ ==")
+            || line.starts_with("import ") && !line.ends_with(';')
+        {
+            python += 1;
+        }
+        if line.starts_with("fn ")
+            || line.starts_with("pub fn ")
+            || line.starts_with("impl ")
+            || line.starts_with("let mut ")
+            || line.starts_with("use ") && line.ends_with(';')
+        {
+            rust += 1;
+        }
+    }
+
+    match (python, rust) {
+        (p, r) if p >= 2 && p > r => Some(("Python", "python")),
+        (p, r) if r >= 2 && r > p => Some(("Rust", "rust")),
+        _ => None,
+    }
+}
+
+/// 內容看起來像另一種語言時，回傳該語法名稱與建議的 `-l` 參數
+///
+/// `limit` 限制只檢查內容開頭的位元組數（例如大型 JSON 不必整份解析）
+pub fn language_suggestion(
+    syntax_name: &str,
+    content: &str,
+    limit: Option<usize>,
+) -> Option<(&'static str, &'static str)> {
+    let content = match limit {
+        Some(limit) if limit < content.len() => {
+            let end = (0..=limit)
+                .rev()
+                .find(|&i| content.is_char_boundary(i))
+                .unwrap_or(0);
+            &content[..end]
+        }
+        _ => content,
+    };
+    sniff_syntax(content).filter(|(name, _)| *name != syntax_name)
+}
+
+/// 去掉行尾的 `\n` 或 `\r\n`
+pub fn strip_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// --color-depth：輸出的色彩深度（位元數）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    Mono,      // 1：不上色
+    Basic,     // 4：16 色
+    Ansi256,   // 8：256 色
+    TrueColor, // 24：24-bit 真彩色
+}
+
+impl std::str::FromStr for ColorDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(ColorDepth::Mono),
+            "4" => Ok(ColorDepth::Basic),
+            "8" => Ok(ColorDepth::Ansi256),
+            "24" => Ok(ColorDepth::TrueColor),
+            _ => Err(format!(
+                "Invalid value '{}' for --color-depth (expected 1, 4, 8 or 24)",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorDepth {
+    /// 依 TERM 偵測只支援 16 色的終端機；其他情況回傳 None，沿用真彩色 / 256 色的判斷
+    pub fn detect() -> Option<Self> {
+        color_depth_from_term(std::env::var("TERM").ok().as_deref())
+    }
+}
+
+fn color_depth_from_term(term: Option<&str>) -> Option<ColorDepth> {
+    let term = term?;
+    let basic = matches!(term, "linux" | "ansi" | "cons25" | "xterm-color")
+        || term.ends_with("-16color")
+        || term.ends_with("-8color");
+    basic.then_some(ColorDepth::Basic)
+}
+
+/// --true-color：真彩色的判斷方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TrueColorMode {
+    #[default]
+    Auto, // 依環境變數（CATE_FORCE_TRUECOLOR / COLORTERM）
+    Probe,  // 環境變數無法確定時，向終端機查詢
+    Always, // 一律使用 24-bit 色彩
+    Never,  // 一律使用 256 色
+}
+
+impl std::str::FromStr for TrueColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(TrueColorMode::Auto),
+            "probe" => Ok(TrueColorMode::Probe),
+            "always" => Ok(TrueColorMode::Always),
+            "never" => Ok(TrueColorMode::Never),
+            _ => Err(format!(
+                "Invalid value '{}' for --true-color (expected auto, probe, always or never)",
+                s
+            )),
+        }
+    }
+}
+
+impl TrueColorMode {
+    /// 決定是否使用真彩色；Auto 回傳 None，交由 supports_true_color 在使用時判斷
+    pub fn resolve(self) -> Option<bool> {
+        match self {
+            TrueColorMode::Auto => None,
+            TrueColorMode::Always => Some(true),
+            TrueColorMode::Never => Some(false),
+            TrueColorMode::Probe => {
+                let force = std::env::var("CATE_FORCE_TRUECOLOR").ok();
+                if force.is_some() || supports_true_color() {
+                    Some(supports_true_color())
+                } else {
+                    Some(crate::terminal::probe_true_color().unwrap_or(false))
+                }
+            }
+        }
+    }
+}
+
 /// 檢測終端是否支援 24-bit 真彩色
+///
+/// `CATE_FORCE_TRUECOLOR`（1/0）可固定結果，讓測試與 CI 的輸出穩定
 pub fn supports_true_color() -> bool {
-    std::env::var("COLORTERM")
-        .map(|v| v == "truecolor" || v == "24bit")
-        .unwrap_or(false)
+    true_color_from_env(
+        std::env::var("CATE_FORCE_TRUECOLOR").ok().as_deref(),
+        std::env::var("COLORTERM").ok().as_deref(),
+    )
+}
+
+fn true_color_from_env(force: Option<&str>, colorterm: Option<&str>) -> bool {
+    match force {
+        Some("1") | Some("true") => return true,
+        Some("0") | Some("false") => return false,
+        _ => {}
+    }
+
+    matches!(colorterm, Some("truecolor") | Some("24bit"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_available_syntax_scopes() {
+        let scopes = Highlighter::available_syntax_scopes();
+        assert!(scopes.contains(&("Rust".to_string(), "source.rust".to_string())));
+        assert_eq!(scopes.len(), Highlighter::available_syntaxes().len());
+    }
+
+    #[test]
+    fn test_basic_colors() {
+        let content = "fn main() {\n    let s = \"hi\";\n}\n";
+        let highlighter = Highlighter::new(None, true).unwrap().with_basic_colors();
+        let mut lh = highlighter.prepare_for_file(Some(Path::new("main.rs")), None, None);
+        for line in content.split_inclusive('\n') {
+            let output = lh.highlight_line(line).unwrap();
+            assert!(!output.contains("38;2;") && !output.contains("38;5;"));
+            assert_eq!(crate::ansi::strip_escapes(&output), line.trim_end());
+            // 每個 SGR 都是 30–37 / 90–97 或重設
+            for sgr in output.split("\x1b[").skip(1) {
+                let code: u8 = sgr[..sgr.find('m').unwrap()].parse().unwrap();
+                assert!(matches!(code, 0 | 30..=37 | 90..=97), "{}", code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_depth() {
+        assert_eq!("4".parse(), Ok(ColorDepth::Basic));
+        assert_eq!("24".parse(), Ok(ColorDepth::TrueColor));
+        assert!("16".parse::<ColorDepth>().is_err());
+        assert_eq!(
+            color_depth_from_term(Some("linux")),
+            Some(ColorDepth::Basic)
+        );
+        assert_eq!(
+            color_depth_from_term(Some("rxvt-16color")),
+            Some(ColorDepth::Basic)
+        );
+        assert_eq!(color_depth_from_term(Some("xterm-256color")), None);
+        assert_eq!(color_depth_from_term(None), None);
+    }
+
+    #[test]
+    fn test_add_user_syntaxes() {
+        let dir = std::env::temp_dir().join(format!("cate-syntaxes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("MyDsl.sublime-syntax"),
+            "%YAML 1.2\n---\nname: MyDsl\nfile_extensions: [mydsl]\nscope: source.mydsl\ncontexts:\n  main:\n    - match: '#.*$'\n      scope: comment.line.mydsl\n",
+        )
+        .unwrap();
+
+        let embedded: SyntaxSet =
+            load_from_binary(SERIALIZED_SYNTAX_SET, COMPRESS_SYNTAXES).unwrap();
+        let count = embedded.syntaxes().len();
+        let syntaxes = add_user_syntaxes(embedded, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(syntaxes.syntaxes().len(), count + 1);
+        let syntax = syntaxes.find_syntax_by_extension("mydsl").unwrap();
+        assert_eq!(syntax.name, "MyDsl");
+        assert!(syntaxes.find_syntax_by_name("MyDsl").is_some());
+        assert!(syntaxes.find_syntax_by_name("Rust").is_some());
+
+        // 不存在的目錄不是錯誤
+        let syntaxes = add_user_syntaxes(syntaxes, &dir);
+        assert_eq!(syntaxes.syntaxes().len(), count + 1);
+    }
+
+    #[test]
+    fn test_load_user_themes() {
+        let dir = std::env::temp_dir().join(format!("cate-themes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Mine.tmTheme"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>name</key><string>Mine</string>
+<key>settings</key><array><dict><key>settings</key><dict>
+<key>background</key><string>#101010</string>
+<key>foreground</key><string>#e0e0e0</string>
+</dict></dict></array>
+</dict></plist>"#,
+        )
+        .unwrap();
+
+        let mut themes = ThemeSet::load_defaults();
+        load_user_themes(&mut themes, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(themes.themes.contains_key("Mine"));
+
+        // 不存在的目錄不是錯誤
+        let count = themes.themes.len();
+        load_user_themes(&mut themes, &dir);
+        assert_eq!(themes.themes.len(), count);
+    }
+
+    #[test]
+    fn test_theme_aliases() {
+        let aliases = parse_theme_aliases(None);
+        assert_eq!(
+            resolve_theme_alias("dark", &aliases),
+            "base16-eighties.dark"
+        );
+        assert_eq!(
+            resolve_theme_alias("Solarized (dark)", &aliases),
+            "Solarized (dark)"
+        );
+        assert!(Highlighter::new(Some("dark"), true).is_ok());
+        assert!(Highlighter::new(Some("light"), true).is_ok());
+
+        // 使用者定義的別名優先，格式錯誤的項目略過
+        let aliases =
+            parse_theme_aliases(Some("dark = Solarized (dark), bad, sol=Solarized (light)"));
+        assert_eq!(resolve_theme_alias("dark", &aliases), "Solarized (dark)");
+        assert_eq!(resolve_theme_alias("sol", &aliases), "Solarized (light)");
+        assert_eq!(resolve_theme_alias("light", &aliases), "InspiredGitHub");
+    }
+
     #[test]
     fn test_rust_syntax_detection() {
         let highlighter = Highlighter::new(None, true).unwrap();
         let path = Path::new("test.rs");
 
-        let syntax = highlighter.detect_syntax(None, Some(path));
+        let syntax = highlighter.detect_syntax(None, Some(path)).0;
         assert_eq!(syntax.name, "Rust");
     }
 
@@ -274,10 +1069,183 @@ mod tests {
         let highlighter = Highlighter::new(None, true).unwrap();
         let first_line = "#!/bin/bash";
 
-        let syntax = highlighter.detect_syntax(Some(first_line), None);
+        let syntax = highlighter.detect_syntax(Some(first_line), None).0;
         assert!(syntax.name.contains("Bash") || syntax.name.contains("Shell"));
     }
 
+    #[test]
+    fn test_explain_syntax_names_extension() {
+        let highlighter = Highlighter::new(None, true).unwrap();
+        let (name, source) =
+            highlighter.explain_syntax(Some(Path::new("tool.py")), Some("import os"), None);
+        assert_eq!(name, "Python");
+        assert_eq!(source, SyntaxSource::Extension("py".to_string()));
+        assert_eq!(source.to_string(), "extension .py");
+
+        let (name, source) = highlighter.explain_syntax(None, Some("#!/bin/bash"), None);
+        assert!(name.contains("Bash") || name.contains("Shell"));
+        assert_eq!(source, SyntaxSource::Shebang);
+
+        let (_, source) = highlighter.explain_syntax(None, None, Some("rust"));
+        assert_eq!(source, SyntaxSource::Language);
+    }
+
+    #[test]
+    fn test_filetype_env_fallback() {
+        let highlighter = Highlighter::new(None, true).unwrap();
+        let detect = |path: &str, filetype| {
+            highlighter
+                .detect_syntax_with_filetype(None, Some(Path::new(path)), filetype)
+                .0
+                .name
+                .clone()
+        };
+
+        // CATE_FILETYPE=python：沒有副檔名的檔案使用 Python
+        assert_eq!(detect("script", Some("python")), "Python");
+        assert_eq!(detect("script", None), "Plain Text");
+        // 副檔名仍然優先
+        assert_eq!(detect("main.rs", Some("python")), "Rust");
+    }
+
+    #[test]
+    fn test_language_suggestion_for_misdetected_python() {
+        let content = "import os\nfrom sys import argv\n\ndef main():\n    print(argv)\n";
+        let highlighter = Highlighter::new(None, true).unwrap();
+        let syntax = highlighter.syntax_name(Some(Path::new("notes.txt")), Some("import os"), None);
+        assert_eq!(syntax, "Plain Text");
+
+        assert_eq!(
+            language_suggestion(syntax, content, None),
+            Some(("Python", "python"))
+        );
+        // 已經偵測為 Python 時不提示
+        assert_eq!(language_suggestion("Python", content, None), None);
+        assert_eq!(language_suggestion(syntax, "just some notes\n", None), None);
+    }
+
+    #[test]
+    fn test_language_suggestion_respects_limit() {
+        let content = "{\"name\": \"中文\", \"items\": [1, 2, 3]}";
+        assert_eq!(
+            language_suggestion("Plain Text", content, None),
+            Some(("JSON", "json"))
+        );
+        // 只檢查開頭（切在多位元組字元中間也不會 panic），不完整的 JSON 不算
+        assert_eq!(language_suggestion("Plain Text", content, Some(11)), None);
+    }
+
+    #[test]
+    fn test_inverted_theme_flips_lightness() {
+        let rgb = |r, g, b| Color { r, g, b, a: 0xff };
+        assert_eq!(invert_lightness(rgb(0, 0, 0)), rgb(255, 255, 255));
+        assert_eq!(invert_lightness(rgb(255, 255, 255)), rgb(0, 0, 0));
+        // base16-eighties 的關鍵字紫色 #cc99cc 變成深紫 #663366
+        assert_eq!(
+            invert_lightness(rgb(0xcc, 0x99, 0xcc)),
+            rgb(0x66, 0x33, 0x66)
+        );
+
+        let highlighter = Highlighter::new(None, true).unwrap().with_inverted_colors();
+        let mut lh = highlighter.prepare_for_file(Some(Path::new("main.rs")), None, None);
+        let line = lh.highlight_line("fn main() {}\n").unwrap();
+        assert!(line.starts_with("\x1b[38;2;102;51;102mfn"), "{:?}", line);
+    }
+
+    #[test]
+    fn test_dim_comment_scopes() {
+        let highlighter = Highlighter::new(None, true)
+            .unwrap()
+            .with_dimmed_scopes(parse_scope_selectors("comment").unwrap());
+        let mut lh = highlighter.prepare_for_file(Some(Path::new("main.rs")), None, None);
+        let line = lh.highlight_line("let x = 1; // note\n").unwrap();
+
+        // 程式碼維持原本亮度，註解（包含 `//`）的片段以 SGR 2 開頭、SGR 22 結束
+        let comment = line.find("//").unwrap();
+        let dim = line[..comment].rfind("\x1b[2m").unwrap();
+        assert!(!line[..dim].contains("\x1b[2m"), "{:?}", line);
+        assert!(!line[dim..comment].contains("\x1b[22m"));
+        assert!(line[comment..].contains(" note\x1b[22m"));
+
+        assert!(parse_scope_selectors("comment,,string").is_err());
+        assert!(parse_scope_selectors(DEFAULT_DIM_SCOPES).is_ok());
+
+        // 沒有指定時輸出不變
+        let plain = Highlighter::new(None, true).unwrap();
+        let mut lh = plain.prepare_for_file(Some(Path::new("main.rs")), None, None);
+        assert!(!lh.highlight_line("// note\n").unwrap().contains("\x1b[2m"));
+    }
+
+    #[test]
+    fn test_style_cache_output_is_identical() {
+        let source = include_str!("highlighter.rs");
+        let path = Path::new("highlighter.rs");
+
+        for true_color in [true, false] {
+            let highlighter = Highlighter::new(None, true_color).unwrap();
+            let mut cached = highlighter.prepare_for_file(Some(path), None, None);
+            let mut uncached = highlighter.prepare_for_file(Some(path), None, None);
+            uncached.style_cache = None;
+
+            for line in source.split_inclusive('\n') {
+                assert_eq!(
+                    cached.highlight_line(line).unwrap(),
+                    uncached.highlight_line(line).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vue_embedded_regions() {
+        let source = "<template>\n  <div class=\"x\">{{ msg }}</div>\n</template>\n\
+                      <script>\nexport default { data() { return 1 } }\n</script>\n\
+                      <style>\n.x { color: red; }\n</style>\n";
+        let highlighter = Highlighter::new(None, false).unwrap();
+        let path = Path::new("App.vue");
+        assert_eq!(
+            highlighter.syntax_name(Some(path), None, None),
+            "Vue Component"
+        );
+        assert_eq!(
+            highlighter.syntax_name(Some(Path::new("page.php8")), None, None),
+            "PHP"
+        );
+
+        let mut lh = highlighter.prepare_for_file(Some(path), None, None);
+        let lines: Vec<String> = source
+            .split_inclusive('\n')
+            .map(|line| lh.highlight_line(line).unwrap())
+            .collect();
+
+        // 內嵌區塊有被語法高亮：同一行出現不只一種顏色
+        let colors = |line: &str| {
+            let mut colors: Vec<&str> = line
+                .split("\x1b[38;5;")
+                .skip(1)
+                .map(|s| &s[..s.find('m').unwrap()])
+                .collect();
+            colors.sort();
+            colors.dedup();
+            colors.len()
+        };
+        assert!(colors(&lines[4]) > 1, "script: {:?}", lines[4]);
+        assert!(colors(&lines[7]) > 1, "style: {:?}", lines[7]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_detection() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let highlighter = Highlighter::new(None, true).unwrap();
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9.rs"));
+        assert!(path.to_str().is_none());
+
+        assert_eq!(highlighter.detect_syntax(None, Some(path)).0.name, "Rust");
+    }
+
     #[test]
     fn test_line_highlighter() {
         let highlighter = Highlighter::new(None, true).unwrap();
@@ -288,6 +1256,14 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_force_truecolor_override() {
+        assert!(true_color_from_env(Some("1"), None));
+        assert!(!true_color_from_env(Some("0"), Some("truecolor")));
+        assert!(true_color_from_env(None, Some("24bit")));
+        assert!(!true_color_from_env(None, None));
+    }
+
     #[test]
     fn test_language_specification() {
         let highlighter = Highlighter::new(None, true).unwrap();
@@ -0,0 +1,132 @@
+// 縮排分析：統計以 tab / 空白縮排的行數，找出混用的情況
+
+use serde::Serialize;
+use std::fmt;
+
+/// 檔案主要的縮排方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+    None, // 沒有縮排的行
+}
+
+impl fmt::Display for IndentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            IndentStyle::Tabs => "tabs",
+            IndentStyle::Spaces => "spaces",
+            IndentStyle::None => "none",
+        })
+    }
+}
+
+/// 單一檔案的縮排統計
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndentReport {
+    pub path: String,
+    pub tab_lines: usize,   // 只以 tab 縮排
+    pub space_lines: usize, // 只以空白縮排
+    pub mixed_lines: usize, // 縮排中同時有 tab 與空白
+    pub dominant: IndentStyle,
+    pub consistent: bool,
+}
+
+impl IndentReport {
+    /// 分析內容的縮排（只看行首的空白，空白行不計）
+    pub fn analyze(path: &str, content: &str) -> Self {
+        let (mut tab_lines, mut space_lines, mut mixed_lines) = (0, 0, 0);
+
+        for line in content.lines() {
+            let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+            if indent_len == line.len() {
+                continue;
+            }
+
+            let indent = &line[..indent_len];
+            match (indent.contains('\t'), indent.contains(' ')) {
+                (true, true) => mixed_lines += 1,
+                (true, false) => tab_lines += 1,
+                (false, true) => space_lines += 1,
+                (false, false) => {}
+            }
+        }
+
+        let dominant = if tab_lines == 0 && space_lines == 0 {
+            IndentStyle::None
+        } else if tab_lines > space_lines {
+            IndentStyle::Tabs
+        } else {
+            IndentStyle::Spaces
+        };
+
+        Self {
+            path: path.to_string(),
+            tab_lines,
+            space_lines,
+            mixed_lines,
+            dominant,
+            consistent: mixed_lines == 0 && (tab_lines == 0 || space_lines == 0),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("indent report is always serializable")
+    }
+}
+
+impl fmt::Display for IndentReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: tabs={} spaces={} mixed={} dominant={} ({})",
+            self.path,
+            self.tab_lines,
+            self.space_lines,
+            self.mixed_lines,
+            self.dominant,
+            if self.consistent {
+                "consistent"
+            } else {
+                "inconsistent"
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consistent_indentation() {
+        let report = IndentReport::analyze(
+            "a.rs",
+            "fn main() {\n    let x = 1;\n\n    if x {\n        x;\n    }\n}\n",
+        );
+        assert_eq!(report.space_lines, 4);
+        assert_eq!(report.tab_lines, 0);
+        assert_eq!(report.dominant, IndentStyle::Spaces);
+        assert!(report.consistent);
+        assert_eq!(
+            report.to_string(),
+            "a.rs: tabs=0 spaces=4 mixed=0 dominant=spaces (consistent)"
+        );
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        let report = IndentReport::analyze("b.c", "int x;\n\ta();\n\tb();\n  c();\n\t  d();\n");
+        assert_eq!(
+            (report.tab_lines, report.space_lines, report.mixed_lines),
+            (2, 1, 1)
+        );
+        assert_eq!(report.dominant, IndentStyle::Tabs);
+        assert!(!report.consistent);
+        assert_eq!(
+            report.to_json(),
+            r#"{"path":"b.c","tab_lines":2,"space_lines":1,"mixed_lines":1,"dominant":"tabs","consistent":false}"#
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// 診斷訊息的輸出層級（數值越大輸出越多）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Quiet = 0,  // 只輸出致命錯誤
+    Normal = 1, // 輸出警告（預設）
+    Info = 2,   // -V：輸出偵測到的編碼等摘要
+    Debug = 3,  // -VV / --debug：輸出完整除錯資訊
+}
+
+impl Level {
+    /// 將 -V 的次數轉為輸出層級
+    pub fn from_verbosity(verbosity: u8) -> Self {
+        match verbosity {
+            0 => Level::Normal,
+            1 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+/// 全域輸出層級
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+#[cfg(test)]
+thread_local! {
+    /// 測試用：覆寫當前執行緒的輸出層級，避免測試之間互相影響
+    static TEST_LEVEL: std::cell::Cell<Option<Level>> = const { std::cell::Cell::new(None) };
+
+    /// 測試用：捕捉輸出的訊息
+    static CAPTURED: std::cell::RefCell<Option<Vec<String>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// 設定全域輸出層級
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// 取得目前的輸出層級
+pub fn level() -> Level {
+    #[cfg(test)]
+    if let Some(level) = TEST_LEVEL.with(|l| l.get()) {
+        return level;
+    }
+
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        2 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+/// 指定層級的訊息是否會被輸出
+pub fn enabled(level: Level) -> bool {
+    self::level() >= level
+}
+
+/// 輸出一行訊息到 stderr
+pub fn emit(args: fmt::Arguments) {
+    #[cfg(test)]
+    {
+        let captured = CAPTURED.with(|c| match c.borrow_mut().as_mut() {
+            Some(buffer) => {
+                buffer.push(args.to_string());
+                true
+            }
+            None => false,
+        });
+        if captured {
+            return;
+        }
+    }
+
+    eprintln!("{}", args);
+}
+
+/// 輸出警告（--quiet 時不輸出）
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Normal) {
+            $crate::logging::emit(format_args!("cate: warning: {}", format_args!($($arg)*)));
+        }
+    };
+}
+
+/// 輸出摘要資訊（-V 時輸出）
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Info) {
+            $crate::logging::emit(format_args!("[INFO] {}", format_args!($($arg)*)));
+        }
+    };
+}
+
+/// 輸出除錯資訊（-VV / --debug 時輸出）
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Debug) {
+            $crate::logging::emit(format_args!("[DEBUG] {}", format_args!($($arg)*)));
+        }
+    };
+}
+
+/// 測試用：以指定層級執行並回傳所有輸出的訊息
+#[cfg(test)]
+pub fn capture<R>(level: Level, f: impl FnOnce() -> R) -> (R, Vec<String>) {
+    TEST_LEVEL.with(|l| l.set(Some(level)));
+    CAPTURED.with(|c| *c.borrow_mut() = Some(Vec::new()));
+
+    let result = f();
+
+    TEST_LEVEL.with(|l| l.set(None));
+    let messages = CAPTURED.with(|c| c.borrow_mut().take()).unwrap_or_default();
+    (result, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let (_, messages) = capture(Level::Normal, || {
+            warn!("shown");
+            debug!("hidden");
+        });
+        assert_eq!(messages, vec!["cate: warning: shown"]);
+
+        let (_, messages) = capture(Level::Quiet, || warn!("hidden"));
+        assert!(messages.is_empty());
+
+        let (_, messages) = capture(Level::Info, || {
+            info!("summary");
+            debug!("hidden");
+        });
+        assert_eq!(messages, vec!["[INFO] summary"]);
+
+        let (_, messages) = capture(Level::Debug, || debug!("value = {}", 1));
+        assert_eq!(messages, vec!["[DEBUG] value = 1"]);
+    }
+}
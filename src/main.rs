@@ -1,34 +1,164 @@
+#[macro_use]
+mod logging;
+
+mod ansi;
+mod checksum;
+mod diff;
 mod encoder;
 mod highlighter;
+mod indent;
+mod newline;
+mod pager;
 mod printer;
+mod report;
+mod svg;
+mod terminal;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
 use pico_args::Arguments;
-use std::io::Write;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
 
 struct Args {
     files: Vec<PathBuf>,
     encoding: Option<String>,
+    output_encoding: Option<String>, // --output-encoding: 將輸出轉碼為此編碼
+    invalid: encoder::InvalidMode,   // --invalid: 無法解碼時的處理方式
+    replacement_char: Option<char>,  // --replacement-char: 取代 U+FFFD 的字元
+    bom: encoder::BomMode,           // --strip-bom / --keep-bom: 開頭 U+FEFF 的處理方式
+    fd: Option<i32>,                 // --fd: 從指定的檔案描述符讀取（Unix）
+    file_name: Option<PathBuf>,      // --file-name: stdin / --fd 內容的名稱（用於語法偵測）
+    byte_range: Option<encoder::ByteRange>, // --byte-range: 只輸出指定的位元組範圍
+    transport: Option<encoder::TransportEncoding>, // --decode: 先還原 base64 / hex 輸入
+    binary: bool,                    // --binary: 二進位檔也照常輸出
+    against: Option<PathBuf>,        // --against: 只輸出與此檔案不同的行
     show_line_numbers: bool,
-    debug: bool,
+    number_nonblank: bool, // -b, --number-nonblank: 只為非空行編號（優先於 -n）
+    show_ends: bool,       // -E, --show-ends: 在每行結尾加上 `$`
+    verbosity: u8,         // -V, --verbose（可重複）：1 = 顯示編碼，2 = 完整除錯（同 --debug）
+    quiet: bool,           // -q, --quiet: 不輸出警告
+    fail_on_replacement: bool, // --fail-on-replacement / --strict: 有無法解碼的位元組時不輸出並以錯誤結束
+    prefetch: usize,           // --prefetch: 預先讀取的檔案數（0 表示停用）
+    input_buffer_size: usize,  // --input-buffer-size: 逐行讀取時的緩衝區大小
+    detection_bytes: Option<usize>, // --detection-bytes: 編碼與內容語法偵測最多檢查的位元組數
+    min_confidence: Option<encoder::EncodingConfidence>, // --min-confidence: 統計偵測結果的最低信心
+    strict_confidence: bool, // --strict-confidence: 低於 --min-confidence 時回報錯誤而非改用系統編碼
+    max_bytes: Option<u64>,  // --bytes: 每個檔案最多讀取的位元組數
+    summary_json: bool,      // --summary-json: 只輸出所有檔案的 JSON 統計
+    max_files: Option<usize>, // --max-files: 最多處理的檔案數
+    detect_only: bool,       // --detect-only: 只偵測並輸出編碼
+    checksum: Option<checksum::ChecksumAlgorithm>, // --checksum: 只輸出內容的雜湊值
+    checksum_raw: bool,      // --checksum-raw: 雜湊原始位元組而非解碼後的內容
+    dump_detection: bool,    // --dump-detection: 輸出編碼偵測的詳細過程
+    osc133: bool,            // --osc133: 以 shell integration 標記包住輸出（實驗性）
+    server: bool,            // --server: 從 stdin 逐行讀取檔名並輸出，直到空行或 EOF
+    header: bool,            // --header: 輸出到終端機時在每個檔案前加上標題列
+    header_always: bool,     // --header=always: 即使輸出被導向也加上標題列
+    header_color: Option<String>, // --header-color: 標題列的顏色
+    paging: pager::PagingMode, // --paging: auto / always / never
+    print_width: bool,       // --print-width: 輸出偵測到的終端機寬度與來源
+    retab_detect: bool,      // --retab-detect: 輸出縮排統計，不一致時以非零結束
+    retab_json: bool,        // --retab-detect=json: 以 JSON 輸出縮排統計
+    force_syntax_path: bool, // --force-syntax-path（隱藏）：純文字也經過高亮流程，僅供除錯
+    debug_highlight: bool,   // --debug-highlight: 每行的 scope 堆疊輸出到 stderr
+    line_index: bool,        // --line-index: 輸出每一行在原始檔案中的位元組位置
+    hex: bool,               // --hex: 以 hexdump 格式輸出原始位元組
+    line_length_histogram: bool, // --line-length-histogram: 只輸出行長度的直方圖
+    histogram_unit: printer::LengthUnit, // --histogram-unit: chars / bytes
+    detect_newline: bool,    // --detect-newline: 輸出 LF / CRLF / CR 行尾統計
+
+    // 匯出
+    export: bool,            // --export: 將高亮後的內容匯出為 SVG
+    output: Option<PathBuf>, // -o, --output: 匯出的目標檔案（預設為 stdout）
 
     // 語法高亮選項
-    no_highlight: bool,       // --no-highlight: 停用語法高亮
-    theme: Option<String>,    // --theme: 指定主題
-    language: Option<String>, // -l, --language: 指定語法語言
+    no_highlight: bool,                           // --no-highlight: 停用語法高亮
+    theme: Option<String>,                        // --theme: 指定主題
+    theme_light: Option<String>,                  // --theme-light: 淺色背景時的主題
+    theme_dark: Option<String>,                   // --theme-dark: 深色背景時的主題
+    true_color: highlighter::TrueColorMode,       // --true-color: auto / probe / always / never
+    color_depth: Option<highlighter::ColorDepth>, // --color-depth: 1 / 4 / 8 / 24
+    invert_theme: bool,                           // --invert-theme: 反轉主題顏色的亮度
+    flatten_ansi: bool,                           // --flatten-ansi-to-16: 輸出降為 16 色
+    dim_scopes: Option<String>, // --dim-uninteresting / --dim-scopes: 以低亮度輸出的 scope
+    language: Option<String>,   // -l, --language: 指定語法語言
+
+    // Tab 選項
+    replace_tabs_in_output_only: bool, // --replace-tabs-in-output-only: 高亮後才展開 tab
+    tabs: Option<usize>,               // --tabs: 將 tab 展開到 N 的倍數欄（0 表示保留 tab）
+
+    reveal_bidi: bool,                 // --reveal-bidi: 顯示雙向控制字元與零寬字元
+    reveal_in_strings: bool,           // --reveal-in-strings: 只顯示字串中的隱藏字元
+    show_nonprinting: bool,            // --show-nonprinting: 以 ^X / M-^X 顯示控制字元
+    escapes: Option<printer::Escapes>, // --interpret-escapes / --escapes: 轉換字面上的跳脫序列
+    normalize: Option<printer::Normalization>, // --normalize-unicode: nfc / nfd / nfkc / nfkd
+
+    strip_shebang: bool, // --strip-shebang: 不輸出 shebang 行
+
+    compact_blank: Option<usize>, // --compact-blank / --squeeze-blank: 連續空行最多輸出 N 行
+    collapse_repeats: bool,       // --collapse-repeats: 連續相同的行只輸出一次並加上次數
+
+    locate: bool,          // --locate: 每行前加上 `路徑:行號:`
+    no_run_on_empty: bool, // --no-run-on-empty: 完全略過空檔案（包含檔案間的分隔）
+
+    // 超長行
+    warn_long_lines: Option<usize>, // --warn-long-lines: 超過 N 位元組的行在 stderr 提示
+    truncate_long_lines: bool,      // --truncate-long-lines: 在高亮前截斷超長行
+    max_output_lines: Option<usize>, // --max-output-lines: 合計最多輸出 N 行（0 為不限制）
+
+    // 合併成一行
+    join: bool,                     // --join / --flatten-newlines: 將所有行合併成一行
+    join_separator: Option<String>, // --join-separator: 分隔字串（預設為空白）
+    join_trim: bool,                // --join-trim: 去掉每行頭尾空白並略過空行
+
+    // 行首/行尾裝飾
+    line_prefix: Option<String>, // --line-prefix: 每行開頭的字串
+    line_suffix: Option<String>, // --line-suffix: 每行結尾的字串
+    prefix_position: printer::PrefixPosition, // --line-prefix-position: before / after（相對於行號）
+
+    reflow_long_comments: bool, // --reflow-long-comments: 依終端機寬度重新換行過長的註解
+    wrap: printer::WrapMode,    // --wrap: auto / never / 欄數
+
+    preserve_ansi: bool, // --preserve-ansi-and-highlight: 已含 ANSI 的行原樣輸出
+    timestamps: Option<regex::Regex>, // --highlight-timestamps / --timestamp-pattern
+    matches: Option<regex::Regex>, // --match（-i / --match-case 決定是否區分大小寫）
+    highlight_urls: bool, // --highlight-urls: 為網址加上底線
+    hyperlinks: bool,    // --hyperlinks: 網址輸出為可點擊的 OSC 8 超連結（隱含 --highlight-urls）
+    levels: Option<printer::LevelColors>, // --highlight-levels[=line] / --level-colors
+
+    // 取樣選項
+    sample: Option<usize>,                    // --sample: 每 N 行輸出一行
+    sample_ends: bool,                        // --sample-ends: 也輸出第一行與最後一行
+    line_ranges: Vec<printer::LineRange>,     // --line-range（可重複）: 只輸出這些行
+    highlight_lines: Vec<printer::LineRange>, // -H, --highlight-line（可重複）: 標出這些行
+    highlight_line_color: Option<String>,     // --highlight-line-color: 標出行的樣式
+    zebra: bool,                              // --zebra: 偶數行加上淡背景色
+
+    // 截斷選項
+    chop: Option<usize>,                   // --chop: 截斷超過此欄數的行
+    truncate_marker: Option<String>,       // --truncate-marker: 截斷標記
+    truncate_marker_color: Option<String>, // --truncate-marker-color: 截斷標記顏色
+    pad_to_width: Option<usize>,           // --pad-to-width: 以空白補齊到此欄數
 }
 
 impl Args {
     fn parse() -> Result<Self> {
-        let mut args = Arguments::from_env();
+        Self::from_arguments(Arguments::from_env())
+    }
 
+    fn from_arguments(mut args: Arguments) -> Result<Self> {
         // 處理幫助和版本
         if args.contains(["-h", "--help"]) {
             print_help();
             std::process::exit(0);
         }
 
+        // -v 維持為 --version 的短旗標（不破壞既有用法），詳細程度改用 -V
         if args.contains(["-v", "--version"]) {
             print_version();
             std::process::exit(0);
@@ -40,35 +170,358 @@ impl Args {
             std::process::exit(0);
         }
 
+        // 使用者主題目錄需在載入主題集之前設定
+        if let Some(dir) = args.opt_value_from_str::<_, PathBuf>("--theme-dir")? {
+            highlighter::set_theme_dir(dir);
+        }
+
         // 列出主題
         if args.contains("--list-themes") {
             list_themes();
             std::process::exit(0);
         }
 
-        // 列出語法
+        // 使用者語法目錄需在載入語法集之前設定
+        if let Some(dir) = args.opt_value_from_str::<_, PathBuf>("--syntax-dir")? {
+            highlighter::set_syntax_dir(dir);
+        }
+
+        // 列出語法（=scopes 時附上最上層 scope）
+        if args.contains("--list-syntaxes=scopes") {
+            list_syntax_scopes();
+            std::process::exit(0);
+        }
         if args.contains("--list-syntaxes") {
             list_syntaxes();
             std::process::exit(0);
         }
 
+        // --files-from：從檔案或 stdin 讀取檔案清單（--null 改用 NUL 分隔）
+        let files_from: Option<PathBuf> = args.opt_value_from_os_str("--files-from", |s| {
+            Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+        })?;
+        let null_separated = args.contains("--null");
+
         Ok(Args {
             encoding: args.opt_value_from_str(["-e", "--encoding"])?,
+            output_encoding: args.opt_value_from_str("--output-encoding")?,
+            transport: args.opt_value_from_str("--decode")?,
+            binary: args.contains("--binary"),
+            bom: match (args.contains("--strip-bom"), args.contains("--keep-bom")) {
+                (true, true) => anyhow::bail!("--strip-bom and --keep-bom cannot be combined"),
+                (true, false) => encoder::BomMode::Strip,
+                (false, true) => encoder::BomMode::Keep,
+                (false, false) => encoder::BomMode::Default,
+            },
+            invalid: args.opt_value_from_str("--invalid")?.unwrap_or_default(),
+            replacement_char: args.opt_value_from_str("--replacement-char")?,
+            fd: args.opt_value_from_str("--fd")?,
+            file_name: args.opt_value_from_os_str("--file-name", |s| {
+                Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+            })?,
+            byte_range: args.opt_value_from_str("--byte-range")?,
+            against: args.opt_value_from_str("--against")?,
             show_line_numbers: args.contains(["-n", "--number"]),
-            debug: args.contains("--debug"),
+            number_nonblank: args.contains(["-b", "--number-nonblank"]),
+            show_ends: args.contains(["-E", "--show-ends"]),
+            verbosity: count_verbosity(&mut args),
+            quiet: args.contains(["-q", "--quiet"]),
+            fail_on_replacement: args.contains("--fail-on-replacement") | args.contains("--strict"),
+            prefetch: args.opt_value_from_str("--prefetch")?.unwrap_or(0),
+            input_buffer_size: match args.opt_value_from_str("--input-buffer-size")? {
+                Some(0) => anyhow::bail!("--input-buffer-size must be greater than 0"),
+                Some(size) => size,
+                None => DEFAULT_INPUT_BUFFER_SIZE,
+            },
+            detection_bytes: match args.opt_value_from_str("--detection-bytes")? {
+                Some(0) => anyhow::bail!("--detection-bytes must be greater than 0"),
+                size => size,
+            },
+            min_confidence: args.opt_value_from_str("--min-confidence")?,
+            strict_confidence: args.contains("--strict-confidence"),
+            max_bytes: args.opt_value_from_str("--bytes")?,
+            summary_json: args.contains("--summary-json"),
+            max_files: args.opt_value_from_str("--max-files")?,
+            detect_only: args.contains("--detect-only"),
+            checksum: args.opt_value_from_str("--checksum")?,
+            checksum_raw: args.contains("--checksum-raw"),
+            dump_detection: args.contains("--dump-detection"),
+            osc133: args.contains("--osc133"),
+            server: args.contains("--server"),
+            header_always: args.contains("--header=always"),
+            header: args.contains("--header"),
+            header_color: args.opt_value_from_str("--header-color")?,
+            paging: args.opt_value_from_str("--paging")?.unwrap_or_default(),
+            print_width: args.contains("--print-width"),
+            retab_json: args.contains("--retab-detect=json"),
+            retab_detect: args.contains("--retab-detect"),
+            force_syntax_path: args.contains("--force-syntax-path"),
+            debug_highlight: args.contains("--debug-highlight"),
+            line_index: args.contains("--line-index"),
+            hex: args.contains("--hex"),
+            line_length_histogram: args.contains("--line-length-histogram"),
+            detect_newline: args.contains("--detect-newline"),
+            histogram_unit: args
+                .opt_value_from_str("--histogram-unit")?
+                .unwrap_or_default(),
+
+            // 匯出
+            export: args.contains("--export"),
+            output: args.opt_value_from_os_str(["-o", "--output"], |s| {
+                Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+            })?,
 
             // 語法高亮選項
             no_highlight: args.contains("--no-highlight"),
             theme: args.opt_value_from_str("--theme")?,
+            theme_light: args.opt_value_from_str("--theme-light")?,
+            theme_dark: args.opt_value_from_str("--theme-dark")?,
+            true_color: args.opt_value_from_str("--true-color")?.unwrap_or_default(),
+            color_depth: args.opt_value_from_str("--color-depth")?,
+            flatten_ansi: args.contains("--flatten-ansi-to-16"),
+            invert_theme: args.contains("--invert-theme"),
+            // --dim-scopes 指定清單（隱含 --dim-uninteresting）；無效的 selector 在此回報
+            dim_scopes: {
+                let scopes: Option<String> = args.opt_value_from_str("--dim-scopes")?;
+                let dim = args.contains("--dim-uninteresting");
+                let scopes =
+                    scopes.or_else(|| dim.then(|| highlighter::DEFAULT_DIM_SCOPES.to_string()));
+                if let Some(ref list) = scopes {
+                    highlighter::parse_scope_selectors(list)?;
+                }
+                scopes
+            },
             language: args.opt_value_from_str(["-l", "--language"])?,
 
-            files: args.finish().into_iter().map(PathBuf::from).collect(),
+            // Tab 選項
+            replace_tabs_in_output_only: args.contains("--replace-tabs-in-output-only"),
+            tabs: args.opt_value_from_str("--tabs")?,
+
+            reveal_bidi: args.contains("--reveal-bidi"),
+            reveal_in_strings: args.contains("--reveal-in-strings"),
+            show_nonprinting: args.contains("--show-nonprinting"),
+            normalize: args.opt_value_from_str("--normalize-unicode")?,
+            escapes: match (
+                args.contains("--interpret-escapes"),
+                args.opt_value_from_str("--escapes")?,
+            ) {
+                (_, Some(escapes)) => Some(escapes),
+                (true, None) => Some(printer::Escapes::ALL),
+                (false, None) => None,
+            },
+
+            // --level-colors 隱含 --highlight-levels
+            levels: {
+                let whole_line = args.contains("--highlight-levels=line");
+                let enabled = args.contains("--highlight-levels") || whole_line;
+                match args.opt_value_from_str::<_, String>("--level-colors")? {
+                    Some(map) => Some(
+                        printer::LevelColors::new(Some(&map), whole_line)
+                            .map_err(anyhow::Error::msg)?,
+                    ),
+                    None if enabled => Some(
+                        printer::LevelColors::new(None, whole_line).map_err(anyhow::Error::msg)?,
+                    ),
+                    None => None,
+                }
+            },
+
+            strip_shebang: args.contains("--strip-shebang"),
+
+            // 取樣選項
+            sample: args.opt_value_from_str("--sample")?,
+            sample_ends: args.contains("--sample-ends"),
+            line_ranges: args.values_from_str("--line-range")?,
+            highlight_lines: args.values_from_fn(
+                ["-H", "--highlight-line"],
+                printer::LineRange::parse_line_or_range,
+            )?,
+            highlight_line_color: args.opt_value_from_str("--highlight-line-color")?,
+            zebra: args.contains("--zebra"),
+
+            // 連續空行（--squeeze-blank 等同於 --compact-blank 1）
+            compact_blank: {
+                let compact: Option<usize> = args.opt_value_from_str("--compact-blank")?;
+                let squeeze = args.contains(["-s", "--squeeze-blank"]);
+                compact.or(squeeze.then_some(1))
+            },
+            collapse_repeats: args.contains("--collapse-repeats"),
+
+            locate: args.contains("--locate"),
+            warn_long_lines: args.opt_value_from_str("--warn-long-lines")?,
+            truncate_long_lines: args.contains("--truncate-long-lines"),
+            max_output_lines: args.opt_value_from_str("--max-output-lines")?,
+            no_run_on_empty: args.contains("--no-run-on-empty"),
+
+            // 合併成一行
+            join: args.contains(["-j", "--join"]) | args.contains("--flatten-newlines"),
+            join_separator: args.opt_value_from_str("--join-separator")?,
+            join_trim: args.contains("--join-trim"),
+
+            // 行首/行尾裝飾
+            line_prefix: args.opt_value_from_str("--line-prefix")?,
+            line_suffix: args.opt_value_from_str("--line-suffix")?,
+            prefix_position: args
+                .opt_value_from_str("--line-prefix-position")?
+                .unwrap_or_default(),
+
+            reflow_long_comments: args.contains("--reflow-long-comments"),
+            wrap: args.opt_value_from_str("--wrap")?.unwrap_or_default(),
+
+            preserve_ansi: args.contains("--preserve-ansi-and-highlight"),
+
+            // --timestamp-pattern 隱含 --highlight-timestamps
+            timestamps: match (
+                args.contains("--highlight-timestamps"),
+                args.opt_value_from_str::<_, String>("--timestamp-pattern")?,
+            ) {
+                (_, Some(pattern)) => Some(
+                    regex::Regex::new(&pattern)
+                        .with_context(|| format!("Invalid --timestamp-pattern '{}'", pattern))?,
+                ),
+                (true, None) => Some(regex::Regex::new(printer::DEFAULT_TIMESTAMP_PATTERN)?),
+                (false, None) => None,
+            },
+
+            highlight_urls: args.contains("--highlight-urls"),
+            hyperlinks: args.contains("--hyperlinks"),
+
+            // 預設區分大小寫；--match-case 覆蓋 -i（方便在別名中使用）
+            matches: {
+                let ignore_case = args.contains(["-i", "--ignore-case"]);
+                let match_case = args.contains("--match-case");
+                match args.opt_value_from_str::<_, String>("--match")? {
+                    Some(pattern) => Some(
+                        regex::RegexBuilder::new(&pattern)
+                            .case_insensitive(ignore_case && !match_case)
+                            .build()
+                            .with_context(|| format!("Invalid --match pattern '{}'", pattern))?,
+                    ),
+                    None => None,
+                }
+            },
+
+            // 截斷選項
+            chop: args.opt_value_from_str("--chop")?,
+            truncate_marker: args.opt_value_from_str("--truncate-marker")?,
+            truncate_marker_color: args.opt_value_from_str("--truncate-marker-color")?,
+            pad_to_width: args.opt_value_from_str("--pad-to-width")?,
+
+            files: {
+                let mut files: Vec<PathBuf> =
+                    args.finish().into_iter().map(PathBuf::from).collect();
+                if let Some(list) = files_from {
+                    files.extend(read_files_from(&list, null_separated)?);
+                }
+                files
+            },
         })
     }
 }
 
+/// 讀取 --files-from 指定的檔案清單（`-` 表示 stdin）
+fn read_files_from(list: &Path, null_separated: bool) -> Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .context("Failed to read file list from stdin")?;
+    } else {
+        bytes = std::fs::read(list)
+            .with_context(|| format!("Failed to read file list {}", list.display()))?;
+    }
+
+    Ok(parse_file_list(&bytes, null_separated))
+}
+
+/// 解析以換行或 NUL 分隔的檔案清單（忽略空項目）
+fn parse_file_list(bytes: &[u8], null_separated: bool) -> Vec<PathBuf> {
+    let separator = if null_separated { b'\0' } else { b'\n' };
+
+    bytes
+        .split(|&b| b == separator)
+        .map(|entry| {
+            if null_separated {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(bytes_to_os_string(entry)))
+        .collect()
+}
+
+/// 將原始位元組轉為 OsString（Unix 上保留非 UTF-8 的路徑）
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes.to_vec())
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    OsString::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// 計算詳細程度：每個 -V / --verbose 加一，-VV、-VVV… 依 V 的數量累加，--debug 直接設為 2
+fn count_verbosity(args: &mut Arguments) -> u8 {
+    // pico_args 只認得完整的旗標，先把 -VVV 展開成三個 -V
+    let remaining = std::mem::replace(args, Arguments::from_vec(Vec::new())).finish();
+    *args = Arguments::from_vec(expand_verbosity_flags(remaining));
+
+    let mut verbosity: u8 = 0;
+    while args.contains(["-V", "--verbose"]) {
+        verbosity = verbosity.saturating_add(1);
+    }
+    if args.contains("--debug") {
+        verbosity = verbosity.max(2);
+    }
+    verbosity
+}
+
+/// 把 `-VV`、`-VVV` 等參數展開成等量的 `-V`
+fn expand_verbosity_flags(args: Vec<OsString>) -> Vec<OsString> {
+    let mut expanded = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg.to_str().and_then(|s| s.strip_prefix('-')) {
+            Some(flags) if flags.len() > 1 && flags.bytes().all(|b| b == b'V') => {
+                expanded.extend(std::iter::repeat_n(OsString::from("-V"), flags.len()));
+            }
+            _ => expanded.push(arg),
+        }
+    }
+    expanded
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse()?;
+    let mut args = Args::parse()?;
+
+    args.no_highlight = no_highlight(&args, env_disables_color());
+
+    // 設定診斷訊息的輸出層級（-V 優先於 --quiet）
+    if args.verbosity > 0 {
+        logging::set_level(logging::Level::from_verbosity(args.verbosity));
+    } else if args.quiet {
+        logging::set_level(logging::Level::Quiet);
+    }
+
+    if args.force_syntax_path {
+        debug!("Forcing plain text through the syntax highlighting path");
+    }
+
+    // 診斷：輸出寬度
+    if args.print_width {
+        let (width, source) = terminal::output_width();
+        println!("{}", format_width(width, source));
+        return Ok(());
+    }
+
+    // 限制處理的檔案數量
+    let skipped = limit_files(&mut args.files, args.max_files);
+    if skipped > 0 {
+        debug!("Skipping {} file(s) beyond --max-files", skipped);
+    }
 
     // 解析用戶指定的編碼
     let user_encoding = if let Some(ref enc_str) = args.encoding {
@@ -76,88 +529,1230 @@ fn main() -> Result<()> {
     } else {
         None
     };
+    let output_encoding = match args.output_encoding {
+        Some(ref enc_str) => Some(encoder::parse_encoding(enc_str)?),
+        None => None,
+    };
 
-    // 處理 stdin
-    if args.files.is_empty() {
-        if args.debug {
-            eprintln!("[DEBUG] Reading from stdin");
-        }
-        let (content, detected) = encoder::read_stdin_with_encoding(user_encoding, args.debug)?;
+    // 解碼選項
+    let decode_options = encoder::DecodeOptions {
+        invalid: args.invalid,
+        replacement: args.replacement_char,
+        detection_bytes: args.detection_bytes,
+        max_bytes: args.max_bytes,
+        bom: args.bom,
+        transport: args.transport,
+        confidence: encoder::ConfidenceThreshold {
+            min: args
+                .min_confidence
+                .unwrap_or(encoder::ConfidenceThreshold::default().min),
+            strict: args.strict_confidence,
+        },
+    };
+    if args.transport.is_some() && args.byte_range.is_some() {
+        anyhow::bail!("--decode cannot be combined with --byte-range");
+    }
+
+    // 只偵測編碼：讀取檔案開頭即可，不解碼整個檔案；沒有檔案時偵測 stdin
+    if args.detect_only {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
 
-        if args.debug {
-            eprintln!(
-                "[DEBUG] Final encoding: {} (confidence: {:?})",
+        for file_path in &inputs {
+            let detected = detect_input(file_path, &stdin_cache, user_encoding, &decode_options)?;
+            println!(
+                "{}: {} ({:?})",
+                file_path.display(),
                 detected.encoding.name(),
                 detected.confidence
             );
-            eprintln!("[DEBUG] Content length: {} bytes", content.len());
-            eprintln!("[DEBUG] ---");
         }
+        return Ok(());
+    }
 
-        // 決定是否啟用語法高亮
-        let enable_highlighting = !args.no_highlight;
+    // 內容雜湊：輸出 `雜湊  路徑`（與 sha256sum 相同格式）
+    if let Some(algorithm) = args.checksum {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
 
-        // 使用 Cursor 將字符串轉為 BufRead
-        let reader = std::io::Cursor::new(content);
-        printer::print_content_streaming(
-            reader,
-            args.show_line_numbers,
-            None,
-            enable_highlighting,
+        for path in &inputs {
+            let hash = if args.checksum_raw {
+                let bytes = read_raw_input(path, &stdin_cache, args.max_bytes)?;
+                algorithm.hex_digest(&bytes)
+            } else {
+                let (content, _) = load_input(
+                    path,
+                    &stdin_cache,
+                    args.byte_range,
+                    user_encoding,
+                    &decode_options,
+                )?;
+                algorithm.hex_digest(content.as_bytes())
+            };
+            println!("{}  {}", hash, path.display());
+        }
+        return Ok(());
+    }
+
+    // 偵測過程：每個檔案一段，多個檔案時以空行分隔
+    if args.dump_detection {
+        for (i, file_path) in args.files.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("file: {}", file_path.display());
+            print!(
+                "{}",
+                encoder::dump_file_detection(file_path, user_encoding)?
+            );
+        }
+        return Ok(());
+    }
+
+    // hexdump：直接輸出原始位元組，不解碼；多個輸入時各自一段，以空行分隔
+    if args.hex {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
+
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        for (i, path) in inputs.iter().enumerate() {
+            let bytes = read_raw_input(path, &stdin_cache, args.max_bytes)?;
+            let mut result = Ok(());
+            if inputs.len() > 1 {
+                if i > 0 {
+                    result = writeln!(out);
+                }
+                result = result.and_then(|_| writeln!(out, "file: {}", path.display()));
+            }
+            if let Err(e) =
+                result.and_then(|_| printer::write_hexdump(&mut out, &bytes, !args.no_highlight))
+            {
+                if e.kind() == std::io::ErrorKind::BrokenPipe {
+                    return Ok(());
+                }
+                return Err(e.into());
+            }
+        }
+        return Ok(());
+    }
+
+    // 行索引：輸出 `行號<TAB>位移<TAB>長度`，多個輸入時前面加上路徑
+    if args.line_index {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
+        let show_path = inputs.len() > 1;
+
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        for path in &inputs {
+            let bytes = read_raw_input(path, &stdin_cache, args.max_bytes)?;
+            let spans = encoder::line_index(&bytes, user_encoding);
+            let prefix = if show_path {
+                format!("{}\t", path.display())
+            } else {
+                String::new()
+            };
+            if let Err(e) = write_line_index(&mut out, &prefix, &spans) {
+                if e.kind() == std::io::ErrorKind::BrokenPipe {
+                    return Ok(());
+                }
+                return Err(e.into());
+            }
+        }
+        return Ok(());
+    }
+
+    // 縮排檢查：每個輸入一行報告，有不一致時以 1 結束（可用於 lint）
+    if args.retab_detect || args.retab_json {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
+
+        let mut reports = Vec::new();
+        for path in &inputs {
+            let (content, _) =
+                load_input(path, &stdin_cache, None, user_encoding, &decode_options)?;
+            let report = indent::IndentReport::analyze(&path.display().to_string(), &content);
+            if args.retab_json {
+                println!("{}", report.to_json());
+            } else {
+                println!("{}", report);
+            }
+            reports.push(report);
+        }
+
+        std::process::exit(retab_exit_code(&reports));
+    }
+
+    // 行尾統計：每個輸入一行報告，混用時另外警告（解碼後掃描，UTF-16 檔案也能正確計數）
+    if args.detect_newline {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
+
+        for path in &inputs {
+            let (content, _) =
+                load_input(path, &stdin_cache, None, user_encoding, &decode_options)?;
+            let report =
+                newline::NewlineReport::analyze(&path.display().to_string(), content.as_bytes());
+            println!("{}", report);
+            if report.is_mixed() {
+                warn!("{}: mixed line endings", path.display());
+            }
+        }
+        return Ok(());
+    }
+
+    // 行長度直方圖：逐行統計，不輸出內容；多個輸入時各自一段，以空行分隔
+    if args.line_length_histogram {
+        let stdin_cache = StdinCache::default();
+        let inputs = if args.files.is_empty() {
+            vec![PathBuf::from("-")]
+        } else {
+            args.files.clone()
+        };
+
+        for (i, path) in inputs.iter().enumerate() {
+            if inputs.len() > 1 {
+                if i > 0 {
+                    println!();
+                }
+                println!("file: {}", path.display());
+            }
+            let (content, _) =
+                load_input(path, &stdin_cache, None, user_encoding, &decode_options)?;
+            let histogram = printer::LineLengthHistogram::from_reader(
+                content_reader(content, args.input_buffer_size),
+                args.histogram_unit,
+            )?;
+            print!("{}", histogram);
+        }
+        return Ok(());
+    }
+
+    // 匯出 SVG：只處理第一個輸入
+    if args.export {
+        if let Some(ref output) = args.output {
+            let is_svg = output
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+            if !is_svg {
+                anyhow::bail!(
+                    "Unsupported export format: {} (only .svg)",
+                    output.display()
+                );
+            }
+        }
+
+        let stdin_cache = StdinCache::default();
+        let path = args
+            .files
+            .first()
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from("-"));
+        if args.files.len() > 1 {
+            warn!("--export only renders the first input");
+        }
+        let (content, _) = load_input(&path, &stdin_cache, None, user_encoding, &decode_options)?;
+        let syntax_path = if is_stdin_path(&path) {
+            args.file_name.as_deref()
+        } else {
+            Some(path.as_path())
+        };
+        let rendered = svg::render(
+            &content,
+            syntax_path,
             args.theme.as_deref(),
             args.language.as_deref(),
+            args.invert_theme,
         )?;
 
+        match args.output {
+            Some(ref output) => std::fs::write(output, rendered)
+                .with_context(|| format!("Failed to write {}", output.display()))?,
+            None => print!("{}", rendered),
+        }
         return Ok(());
     }
 
-    // 處理檔案
-    for (i, file_path) in args.files.iter().enumerate() {
-        if args.debug {
-            eprintln!("[DEBUG] Reading file: {:?}", file_path);
+    // 未指定 --theme 時依終端機背景選擇主題
+    let theme = resolve_theme(&args);
+
+    // 主題不存在時停用高亮，提前提示一次
+    if let Some(ref theme) = theme {
+        if !args.no_highlight && highlighter::Highlighter::new(Some(theme), false).is_err() {
+            warn!("theme '{}' not found, syntax highlighting disabled", theme);
+        }
+    }
+
+    // -H 標出行的樣式（未指定時反白）
+    let highlight_line_style = match args.highlight_line_color {
+        Some(ref color) => {
+            ansi::color_sgr(color).ok_or_else(|| anyhow::anyhow!("Unknown color: {}", color))?
+        }
+        None => printer::PrintOptions::default().highlight_line_style,
+    };
+    // 截斷標記的顏色（未指定時在有高亮的輸出中使用灰色）
+    let truncate_marker_style = match args.truncate_marker_color {
+        Some(ref color) => Some(
+            ansi::color_sgr(color).ok_or_else(|| anyhow::anyhow!("Unknown color: {}", color))?,
+        ),
+        None if !args.no_highlight => ansi::color_sgr("gray"),
+        None => None,
+    };
+    // 檔案標題列：只在輸出到終端機時加上（--header=always 除外），顏色規則同截斷標記
+    let show_header = args.header_always || (args.header && std::io::stdout().is_terminal());
+    let header_style = match args.header_color {
+        Some(ref color) => Some(
+            ansi::color_sgr(color).ok_or_else(|| anyhow::anyhow!("Unknown color: {}", color))?,
+        ),
+        None if !args.no_highlight => ansi::color_sgr("gray"),
+        None => None,
+    };
+
+    // 色彩深度：--color-depth 優先；--true-color 為 auto 時才依 TERM 偵測 16 色終端機
+    let color_depth = args.color_depth.or_else(|| {
+        (args.true_color == highlighter::TrueColorMode::Auto)
+            .then(highlighter::ColorDepth::detect)
+            .flatten()
+    });
+
+    // 列印選項
+    let mut print_options = printer::PrintOptions {
+        show_line_numbers: args.show_line_numbers,
+        number_nonblank: args.number_nonblank,
+        show_ends: args.show_ends,
+        // 決定是否啟用語法高亮
+        // ANSI 跳脫序列只在 UTF-8 輸出時保留
+        enable_highlighting: !args.no_highlight
+            && output_encoding.is_none_or(|encoding| encoding == encoding_rs::UTF_8),
+        theme,
+        // 只在需要高亮時才向終端機查詢
+        true_color: match color_depth {
+            _ if args.no_highlight => None,
+            Some(highlighter::ColorDepth::TrueColor) => Some(true),
+            Some(highlighter::ColorDepth::Ansi256 | highlighter::ColorDepth::Basic) => Some(false),
+            _ => args.true_color.resolve(),
+        },
+        basic_colors: color_depth == Some(highlighter::ColorDepth::Basic),
+        invert_theme: args.invert_theme,
+        dim_scopes: args.dim_scopes.clone(),
+        flatten_ansi: args.flatten_ansi,
+        language: args.language.clone(),
+        // 展開一律在高亮之後進行（語法仍看到原本的 tab），欄位從內容開頭起算，不含行號
+        tab_width: args.tabs.unwrap_or(8),
+        expand_tabs_in_output: match args.tabs {
+            Some(width) => width > 0,
+            None => args.replace_tabs_in_output_only,
+        },
+        chop: args.chop,
+        pad_to_width: args.pad_to_width,
+        truncate_marker: args
+            .truncate_marker
+            .clone()
+            .unwrap_or_else(|| printer::PrintOptions::default().truncate_marker),
+        truncate_marker_style,
+        reveal_bidi: args.reveal_bidi,
+        reveal_in_strings: args.reveal_in_strings,
+        show_nonprinting: args.show_nonprinting,
+        escapes: args.escapes,
+        normalize: args.normalize,
+        sample: args.sample,
+        sample_ends: args.sample_ends,
+        line_ranges: args.line_ranges,
+        highlight_lines: args.highlight_lines,
+        highlight_line_style,
+        // 背景色與語法高亮一樣受 --no-highlight / NO_COLOR 控制
+        zebra: args.zebra && !args.no_highlight,
+        zebra_width: terminal::output_width().0,
+        compact_blank: args.compact_blank,
+        collapse_repeats: args.collapse_repeats,
+        force_syntax_path: args.force_syntax_path,
+        debug_highlight: args.debug_highlight,
+        locate: args.locate,
+        // --truncate-long-lines 單獨使用時以 16KB（不再高亮的長度）為界
+        long_line_limit: args
+            .warn_long_lines
+            .or(args.truncate_long_lines.then_some(16 * 1024)),
+        truncate_long_lines: args.truncate_long_lines,
+        output_limit: printer::OutputLimit::new(args.max_output_lines.filter(|&n| n > 0)),
+        join: (args.join || args.join_separator.is_some()).then(|| {
+            args.join_separator
+                .clone()
+                .unwrap_or_else(|| " ".to_string())
+        }),
+        join_trim: args.join_trim,
+        line_prefix: args.line_prefix.clone(),
+        line_suffix: args.line_suffix.clone(),
+        prefix_position: args.prefix_position,
+        reflow_comments: args
+            .reflow_long_comments
+            .then(|| terminal::output_width().0),
+        wrap: match args.wrap {
+            printer::WrapMode::Auto => std::io::stdout()
+                .is_terminal()
+                .then(|| terminal::output_width().0),
+            printer::WrapMode::Never => None,
+            printer::WrapMode::Columns(n) => Some(n),
+        },
+        strip_shebang: args.strip_shebang,
+        preserve_ansi: args.preserve_ansi,
+        // 時間戳記的顏色與語法高亮無關，但同樣只在 UTF-8 輸出時加上
+        timestamps: args
+            .timestamps
+            .clone()
+            .filter(|_| output_encoding.is_none_or(|encoding| encoding == encoding_rs::UTF_8)),
+        matches: args
+            .matches
+            .clone()
+            .filter(|_| output_encoding.is_none_or(|encoding| encoding == encoding_rs::UTF_8)),
+        highlight_urls: (args.highlight_urls || args.hyperlinks)
+            && output_encoding.is_none_or(|encoding| encoding == encoding_rs::UTF_8),
+        hyperlinks: args.hyperlinks,
+        levels: args
+            .levels
+            .clone()
+            .filter(|_| output_encoding.is_none_or(|encoding| encoding == encoding_rs::UTF_8)),
+        changes: None,
+    };
+
+    // --against 的參考檔案（以相同的編碼設定解碼）
+    let reference = match args.against {
+        Some(ref path) => Some(
+            encoder::read_file_with_encoding(path, user_encoding, &decode_options)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .0,
+        ),
+        None => None,
+    };
+
+    // OSC 133 標記只在輸出到終端機時有意義，導向檔案或管線時不輸出
+    let _osc133 = (args.osc133 && std::io::stdout().is_terminal())
+        .then(|| ansi::Osc133::new(std::io::stdout()));
+
+    if args.fd.is_some() && !args.files.is_empty() {
+        anyhow::bail!("--fd cannot be combined with file arguments");
+    }
+
+    // 二進位檔預設不輸出（--binary 強制輸出）；指定 UTF-16 時 NUL 是正常內容，統計模式也不需要
+    let refuse_binary = !args.binary
+        && !args.summary_json
+        && user_encoding.is_none_or(|encoding| {
+            encoding != encoding_rs::UTF_16LE && encoding != encoding_rs::UTF_16BE
+        });
+    let policy = FilePolicy {
+        refuse_binary,
+        fail_on_replacement: args.fail_on_replacement,
+        show_header,
+        header_style: header_style.as_deref(),
+        output_encoding,
+        buffer_size: args.input_buffer_size,
+    };
+
+    // 常駐模式：stdin 是檔名清單，不分頁；語法與主題只載入一次
+    if args.server {
+        return serve(
+            std::io::stdin().lock(),
+            &mut std::io::stdout().lock(),
+            &print_options,
+            &decode_options,
+            user_encoding,
+            &policy,
+        );
+    }
+
+    // 分頁器：內容改寫到分頁器的 stdin（宣告在 `out` 之前，結束時先送出剩餘內容再等待分頁器）；
+    // 從終端機讀取 stdin 時不分頁，避免與分頁器搶鍵盤輸入，JSON 統計也不分頁
+    let reads_terminal =
+        args.files.is_empty() && args.fd.is_none() && std::io::stdin().is_terminal();
+    let mut pager = (!reads_terminal && !args.summary_json)
+        .then(|| pager::Pager::start(args.paging))
+        .flatten();
+
+    // 直接輸出到終端機（沒有分頁器）時預設限制行數，避免意外印出巨大的檔案；管線不限制
+    if args.max_output_lines.is_none() && pager.is_none() && std::io::stdout().is_terminal() {
+        print_options.output_limit = printer::OutputLimit::new(Some(DEFAULT_MAX_OUTPUT_LINES));
+    }
+    let stdout = std::io::stdout();
+    let mut out: Box<dyn Write> = match pager {
+        Some(ref mut pager) => Box::new(std::io::BufWriter::new(pager.input())),
+        None => Box::new(stdout.lock()),
+    };
+
+    // 一般檔案與 --fd 逐塊解碼輸出，不讀進記憶體；需要完整內容的功能仍一次讀入
+    let streaming_input = args.byte_range.is_none()
+        && args.transport.is_none()
+        && args.max_bytes.is_none()
+        && !args.summary_json
+        && reference.is_none()
+        && output_encoding.is_none()
+        && !args.fail_on_replacement;
+
+    // 處理 stdin（或 --fd 指定的檔案描述符）
+    if args.files.is_empty() {
+        // stdin 是終端機時提示使用方式，避免看起來像卡住
+        if let Some(hint) = stdin_hint(args.fd.is_none() && std::io::stdin().is_terminal()) {
+            if logging::enabled(logging::Level::Normal) {
+                logging::emit(format_args!("{}", hint));
+            }
         }
 
-        let (content, detected) =
-            encoder::read_file_with_encoding(file_path, user_encoding, args.debug)?;
+        // --fd 逐塊解碼輸出，只讀取開頭偵測編碼；需要完整內容的功能仍一次讀入
+        if let Some(fd) = args.fd.filter(|_| streaming_input) {
+            debug!("Streaming from fd {}", fd);
+            let mut file = open_fd(fd)?;
+            let mut head = Vec::new();
+            (&mut file)
+                .take(encoder::DETECTION_PREFIX_LEN)
+                .read_to_end(&mut head)
+                .with_context(|| format!("Failed to read from fd {}", fd))?;
+            let binary = encoder::looks_binary(&head);
+            if refuse_binary && binary {
+                report_binary(args.file_name.as_deref().unwrap_or(Path::new("-")));
+                return Ok(());
+            }
 
-        if args.debug {
-            eprintln!(
-                "[DEBUG] Final encoding: {} (confidence: {:?})",
+            let display_path = args.file_name.as_deref();
+            let (stream, detected) =
+                encoder::open_prefixed_stream(head, file, user_encoding, &decode_options)?;
+            info!(
+                "{}: {} (confidence: {:?})",
+                display_path.map_or_else(|| "-".into(), Path::to_string_lossy),
                 detected.encoding.name(),
                 detected.confidence
             );
-            eprintln!("[DEBUG] Content length: {} bytes", content.len());
-            eprintln!("[DEBUG] ---");
+
+            let mut reader = std::io::BufReader::with_capacity(args.input_buffer_size, stream);
+            let head = String::from_utf8_lossy(reader.fill_buf()?).into_owned();
+            debug!("---");
+            debug_syntax(
+                display_path,
+                &head,
+                args.language.as_deref(),
+                args.detection_bytes,
+            );
+            let file_options = file_print_options(&print_options, binary, None);
+            print_content(
+                reader,
+                &mut out,
+                display_path,
+                &file_options,
+                output_encoding,
+            )?;
+            return Ok(());
         }
 
-        // 決定是否啟用語法高亮
-        let enable_highlighting = !args.no_highlight;
+        let bytes = match args.fd {
+            Some(fd) => {
+                debug!("Reading from fd {}", fd);
+                let mut bytes = Vec::new();
+                open_fd(fd)?
+                    .read_to_end(&mut bytes)
+                    .with_context(|| format!("Failed to read from fd {}", fd))?;
+                bytes
+            }
+            None => {
+                debug!("Reading from stdin");
+                encoder::read_stdin_bytes()?
+            }
+        };
+        let binary = encoder::looks_binary(&bytes);
+        if refuse_binary && binary {
+            report_binary(args.file_name.as_deref().unwrap_or(Path::new("-")));
+            return Ok(());
+        }
+        let (content, detected) = match args.byte_range {
+            Some(range) => {
+                encoder::decode_range_with_encoding(&bytes, range, user_encoding, &decode_options)?
+            }
+            None => encoder::decode_with_encoding(&bytes, user_encoding, &decode_options)?,
+        };
+
+        // --file-name 提供語法偵測與報告用的名稱
+        let display_path = args.file_name.as_deref();
+
+        info!(
+            "{}: {} (confidence: {:?})",
+            display_path.map_or_else(|| "-".into(), Path::to_string_lossy),
+            detected.encoding.name(),
+            detected.confidence
+        );
+        if args.fail_on_replacement {
+            if let Some(failure) =
+                replacement_failure(display_path.unwrap_or(Path::new("-")), &detected)
+            {
+                anyhow::bail!("{}", failure);
+            }
+        }
+        debug!("Content length: {} bytes", content.len());
+        debug!("---");
+        debug_syntax(
+            display_path,
+            &content,
+            args.language.as_deref(),
+            args.detection_bytes,
+        );
+
+        if args.summary_json {
+            let file = file_report(display_path, &content, &detected, args.language.as_deref());
+            println!("{}", report::Summary::new(vec![file]).to_json());
+            return Ok(());
+        }
 
-        // 使用 Cursor 將字符串轉為 BufRead
-        let reader = std::io::Cursor::new(content);
-        printer::print_content_streaming(
+        let changes = reference
+            .as_deref()
+            .map(|reference| diff::changed_lines(reference, &content));
+        let file_options = file_print_options(&print_options, binary, changes);
+        let reader = content_reader(content, args.input_buffer_size);
+        print_content(
             reader,
-            args.show_line_numbers,
-            Some(file_path.as_path()),
-            enable_highlighting,
-            args.theme.as_deref(),
+            &mut out,
+            display_path,
+            &file_options,
+            output_encoding,
+        )?;
+
+        return Ok(());
+    }
+
+    // 處理檔案（可選擇在背景預先讀取後續檔案，輸出順序不變）
+    let stdin_cache = Arc::new(StdinCache::default());
+    let sniff_cache = Arc::clone(&stdin_cache);
+    let byte_range = args.byte_range;
+    // 預先讀取時一次讀入
+    let streaming = streaming_input && args.prefetch == 0;
+    let load = move |file_path: PathBuf| {
+        debug!("Reading file: {}", file_path.display());
+        let is_regular_file = || std::fs::metadata(&file_path).is_ok_and(|m| m.is_file());
+        let result = if streaming && !is_stdin_path(&file_path) && is_regular_file() {
+            encoder::open_file_stream(&file_path, user_encoding, &decode_options)
+                .map(|(stream, detected)| (Input::Stream(stream), detected))
+        } else {
+            load_input(
+                &file_path,
+                &stdin_cache,
+                byte_range,
+                user_encoding,
+                &decode_options,
+            )
+            .map(|(content, detected)| (Input::Content(content), detected))
+        };
+        (file_path, result)
+    };
+
+    let loaded: Box<dyn Iterator<Item = _>> = if args.prefetch > 0 {
+        Box::new(prefetch_ordered(args.files.clone(), args.prefetch, load))
+    } else {
+        Box::new(args.files.iter().cloned().map(load))
+    };
+
+    let mut reports = Vec::new();
+    let mut printed_any = false;
+    let mut failures = Vec::new();
+
+    for (file_path, result) in loaded {
+        // --max-output-lines 是所有檔案合計的上限，用完後不再處理後面的檔案
+        if print_options.output_limit.is_exhausted() {
+            break;
+        }
+        let (input, detected) = result?;
+
+        let is_binary = || {
+            if is_stdin_path(&file_path) {
+                sniff_cache.bytes().map(encoder::looks_binary)
+            } else {
+                encoder::file_looks_binary(&file_path)
+            }
+        };
+        let binary = is_binary()?;
+        match check_file(&file_path, binary, &detected, &policy) {
+            FileCheck::Print => {}
+            FileCheck::Binary => continue,
+            // 有無法解碼的位元組時不輸出內容，處理完所有檔案後一起回報
+            FileCheck::Undecodable(failure) => {
+                failures.push(failure);
+                continue;
+            }
+        }
+
+        // `-` 代表 stdin，不以路徑偵測語法
+        let display_path = (!is_stdin_path(&file_path)).then_some(file_path.as_path());
+
+        let content = match input {
+            Input::Content(content) => content,
+            Input::Stream(stream) => {
+                let mut reader = std::io::BufReader::with_capacity(args.input_buffer_size, stream);
+                // 串流時只有緩衝區開頭的內容可用，足夠用於語法偵測的除錯訊息
+                let head = String::from_utf8_lossy(reader.fill_buf()?).into_owned();
+                debug!("---");
+                debug_syntax(
+                    display_path,
+                    &head,
+                    args.language.as_deref(),
+                    args.detection_bytes,
+                );
+
+                if head.is_empty() && args.no_run_on_empty {
+                    debug!("Skipping empty file: {}", file_path.display());
+                    continue;
+                }
+                if !separate_files(&mut out, &mut printed_any)? {
+                    break;
+                }
+                if show_header && !write_header(&mut out, &file_path, header_style.as_deref())? {
+                    break;
+                }
+                let file_options = file_print_options(&print_options, binary, None);
+                print_content(
+                    reader,
+                    &mut out,
+                    display_path,
+                    &file_options,
+                    output_encoding,
+                )?;
+                continue;
+            }
+        };
+        debug!("Content length: {} bytes", content.len());
+        debug!("---");
+
+        debug_syntax(
+            display_path,
+            &content,
             args.language.as_deref(),
+            args.detection_bytes,
+        );
+
+        // 統計模式不輸出內容
+        if args.summary_json {
+            reports.push(file_report(
+                display_path,
+                &content,
+                &detected,
+                args.language.as_deref(),
+            ));
+            continue;
+        }
+
+        // 空檔案不輸出任何內容；--no-run-on-empty 時連分隔也省略
+        if content.is_empty() && args.no_run_on_empty {
+            debug!("Skipping empty file: {}", file_path.display());
+            continue;
+        }
+
+        if !separate_files(&mut out, &mut printed_any)? {
+            break;
+        }
+        if show_header && !write_header(&mut out, &file_path, header_style.as_deref())? {
+            break;
+        }
+
+        let changes = reference
+            .as_deref()
+            .map(|reference| diff::changed_lines(reference, &content));
+        let file_options = file_print_options(&print_options, binary, changes);
+        let reader = content_reader(content, args.input_buffer_size);
+        print_content(
+            reader,
+            &mut out,
+            display_path,
+            &file_options,
+            output_encoding,
         )?;
+    }
 
-        // 多個檔案間加分隔
-        if i < args.files.len() - 1 {
-            // 使用 println! 來檢查並忽略 broken pipe
-            if let Err(e) = writeln!(std::io::stdout()) {
-                if e.kind() == std::io::ErrorKind::BrokenPipe {
-                    break; // 提早退出迴圈
+    if args.summary_json {
+        println!("{}", report::Summary::new(reports).to_json());
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{}", failures.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// 主迴圈與 --server 共用的每個檔案的輸出設定
+struct FilePolicy<'a> {
+    refuse_binary: bool,       // 略過二進位檔（未指定 --binary）
+    fail_on_replacement: bool, // --fail-on-replacement
+    show_header: bool,         // --header
+    header_style: Option<&'a str>,
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+    buffer_size: usize, // --input-buffer-size
+}
+
+/// 檔案載入後、輸出前的檢查結果
+enum FileCheck {
+    Print,
+    Binary,              // 二進位檔，已說明並略過
+    Undecodable(String), // --fail-on-replacement：有無法解碼的位元組，不輸出
+}
+
+/// 輸出前檢查一個已載入的檔案：略過二進位檔，--fail-on-replacement 時擋下有解碼錯誤的檔案
+fn check_file(
+    path: &Path,
+    binary: bool,
+    detected: &encoder::DetectedEncoding,
+    policy: &FilePolicy,
+) -> FileCheck {
+    if policy.refuse_binary && binary {
+        report_binary(path);
+        return FileCheck::Binary;
+    }
+
+    info!(
+        "{}: {} (confidence: {:?})",
+        path.display(),
+        detected.encoding.name(),
+        detected.confidence
+    );
+
+    if policy.fail_on_replacement {
+        if let Some(failure) = replacement_failure(path, detected) {
+            return FileCheck::Undecodable(failure);
+        }
+    }
+    FileCheck::Print
+}
+
+/// --fail-on-replacement：解碼有錯誤時的說明（路徑與前幾個錯誤位置），沒有錯誤時回傳 None
+fn replacement_failure(path: &Path, detected: &encoder::DetectedEncoding) -> Option<String> {
+    if !detected.had_errors {
+        return None;
+    }
+
+    let offsets: Vec<String> = detected
+        .error_offsets
+        .iter()
+        .map(|offset| offset.to_string())
+        .collect();
+    Some(format!(
+        "{}: bytes could not be decoded as {} (byte offsets: {}{})",
+        path.display(),
+        detected.encoding.name(),
+        offsets.join(", "),
+        if offsets.len() == encoder::MAX_ERROR_OFFSETS {
+            ", ..."
+        } else {
+            ""
+        }
+    ))
+}
+
+/// 輸出一個輸入的內容（到 stdout 或分頁器）；有 --output-encoding 時先整段輸出到記憶體，
+/// 轉碼成功後才寫出
+fn print_content<R: BufRead>(
+    reader: R,
+    mut out: &mut dyn Write,
+    display_path: Option<&Path>,
+    options: &printer::PrintOptions,
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+) -> Result<()> {
+    let Some(encoding) = output_encoding else {
+        printer::print_content_streaming(reader, &mut out, display_path, options)?;
+        return Ok(());
+    };
+
+    let mut rendered = Vec::new();
+    printer::write_content(reader, &mut rendered, display_path, options)?;
+    let bytes = encoder::encode_output(&String::from_utf8_lossy(&rendered), encoding)
+        .with_context(|| {
+            format!(
+                "Failed to convert {} to {}",
+                display_path.map_or_else(|| "-".into(), Path::to_string_lossy),
+                encoding.name()
+            )
+        })?;
+
+    match out.write_all(&bytes) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// --server：從 `input` 逐行讀取檔名並輸出每個檔案，直到空行或 EOF
+///
+/// 每個檔案與一般的檔案參數一樣處理（略過二進位檔、--header、--fail-on-replacement）；
+/// 讀不到的檔案只警告並繼續等待下一個檔名；每個檔案輸出後立即 flush，讓呼叫端（編輯器、fzf）可以顯示
+fn serve<R: BufRead>(
+    input: R,
+    out: &mut dyn Write,
+    print_options: &printer::PrintOptions,
+    decode_options: &encoder::DecodeOptions,
+    user_encoding: Option<&'static encoding_rs::Encoding>,
+    policy: &FilePolicy,
+) -> Result<()> {
+    let mut failures = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let name = line.trim_end_matches('\r');
+        if name.is_empty() {
+            break;
+        }
+
+        // --max-output-lines 的計數跨檔案累計，用完後結束
+        if print_options.output_limit.is_exhausted() {
+            break;
+        }
+
+        // 與一般的檔案參數相同：略過二進位檔、--fail-on-replacement、--header
+        let path = Path::new(name);
+        match encoder::read_file_with_encoding(path, user_encoding, decode_options) {
+            Ok((content, detected)) => {
+                let binary = encoder::file_looks_binary(path).unwrap_or(false);
+                match check_file(path, binary, &detected, policy) {
+                    FileCheck::Print => {
+                        if policy.show_header && !write_header(out, path, policy.header_style)? {
+                            return Ok(());
+                        }
+                        let file_options = file_print_options(print_options, binary, None);
+                        let reader = content_reader(content, policy.buffer_size);
+                        print_content(
+                            reader,
+                            out,
+                            Some(path),
+                            &file_options,
+                            policy.output_encoding,
+                        )?;
+                    }
+                    FileCheck::Binary => {}
+                    // 常駐模式不等到結束才回報，立即說明並繼續處理下一個檔名
+                    FileCheck::Undecodable(failure) => {
+                        warn!("{}", failure);
+                        failures.push(failure);
+                    }
                 }
-                return Err(e.into());
             }
+            Err(e) => warn!("{}: {:#}", name, e),
+        }
+
+        match out.flush() {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{} file(s) could not be decoded", failures.len());
+    }
+    Ok(())
+}
+
+/// 載入後的檔案：完整解碼的內容，或逐塊解碼的串流
+enum Input {
+    Content(String),
+    Stream(encoder::DecodingReader<std::fs::File>),
+}
+
+/// 多個檔案間加分隔；輸出已關閉（broken pipe，例如分頁器已離開）時回傳 false，呼叫端應提早結束
+fn separate_files(out: &mut dyn Write, printed_any: &mut bool) -> Result<bool> {
+    if std::mem::replace(printed_any, true) {
+        if let Err(e) = writeln!(out) {
+            if e.kind() == std::io::ErrorKind::BrokenPipe {
+                return Ok(false);
+            }
+            return Err(e.into());
         }
     }
+    Ok(true)
+}
+
+/// --header：檔案內容前的標題列 `──── path (N bytes) ────`；stdin（`-`）沒有標題
+///
+/// 與 separate_files 相同，輸出已關閉時回傳 false
+fn write_header(out: &mut dyn Write, path: &Path, style: Option<&str>) -> Result<bool> {
+    if is_stdin_path(path) {
+        return Ok(true);
+    }
+
+    let title = match std::fs::metadata(path) {
+        Ok(metadata) => format!("──── {} ({} bytes) ────", path.display(), metadata.len()),
+        Err(_) => format!("──── {} ────", path.display()),
+    };
+    let result = match style {
+        Some(sgr) => writeln!(out, "{}", ansi::paint(&title, sgr)),
+        None => writeln!(out, "{}", title),
+    };
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(false),
+        result => Ok(result.map(|_| true)?),
+    }
+}
+
+/// 每個檔案各自的列印選項：二進位檔（`encoder::looks_binary` 對原始位元組的判斷）不做語法高亮，
+/// `changes` 為 --against 的比較結果
+fn file_print_options(
+    options: &printer::PrintOptions,
+    binary: bool,
+    changes: Option<BTreeMap<usize, diff::LineChange>>,
+) -> printer::PrintOptions {
+    printer::PrintOptions {
+        enable_highlighting: options.enable_highlighting && !binary,
+        changes,
+        ..options.clone()
+    }
+}
+
+/// 略過二進位檔時的說明（-q 時不輸出）
+fn report_binary(path: &Path) {
+    if logging::enabled(logging::Level::Normal) {
+        logging::emit(format_args!(
+            "cate: {} is a binary file (use --binary to print it anyway, or --hex for a hex dump)",
+            path.display()
+        ));
+    }
+}
+
+/// 沒有分頁器的終端機輸出預設最多輸出的行數（--max-output-lines）
+const DEFAULT_MAX_OUTPUT_LINES: usize = 10_000;
+
+/// 逐行讀取的預設緩衝區大小
+const DEFAULT_INPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 以指定容量的 BufReader 包裝解碼後的內容，交給 printer 逐行讀取
+///
+/// 較大的緩衝區減少 read_line 的填充次數，但每個檔案都會多佔用這些記憶體
+fn content_reader(content: String, capacity: usize) -> std::io::BufReader<std::io::Cursor<String>> {
+    std::io::BufReader::with_capacity(capacity, std::io::Cursor::new(content))
+}
+
+/// stdin 只能讀取一次：第一個 `-` 讀取並緩存全部內容，之後的 `-` 重播相同內容
+#[derive(Default)]
+struct StdinCache(OnceCell<Vec<u8>>);
+
+impl StdinCache {
+    fn bytes(&self) -> Result<&[u8]> {
+        self.0
+            .get_or_try_init(encoder::read_stdin_bytes)
+            .map(Vec::as_slice)
+    }
+}
 
+/// 輸出行索引（每行一筆 TSV，行號從 1 開始）
+fn write_line_index<W: Write>(
+    out: &mut W,
+    prefix: &str,
+    spans: &[encoder::LineSpan],
+) -> std::io::Result<()> {
+    for (i, span) in spans.iter().enumerate() {
+        writeln!(out, "{}{}\t{}\t{}", prefix, i + 1, span.offset, span.length)?;
+    }
     Ok(())
 }
+
+/// --retab-detect 的結束碼：任何輸入縮排不一致時為 1
+fn retab_exit_code(reports: &[indent::IndentReport]) -> i32 {
+    if reports.iter().all(|report| report.consistent) {
+        0
+    } else {
+        1
+    }
+}
+
+/// --print-width 的輸出格式
+fn format_width(width: usize, source: terminal::WidthSource) -> String {
+    format!("{} ({})", width, source)
+}
+
+/// 接管檔案描述符（fd 由呼叫端開啟，例如 `cate --fd 3 3<file`），結束時關閉
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    // 先確認 fd 已開啟且可讀，避免接管不屬於我們的 fd
+    if fd < 0 {
+        anyhow::bail!("File descriptor {} is not open", fd);
+    }
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        anyhow::bail!("File descriptor {} is not open", fd);
+    }
+    if flags & libc::O_ACCMODE == libc::O_WRONLY {
+        anyhow::bail!("File descriptor {} is not readable", fd);
+    }
+
+    // SAFETY: fd 已確認為開啟中的可讀描述符，且之後不會再以其他方式使用
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<std::fs::File> {
+    anyhow::bail!("--fd is only supported on Unix")
+}
+
+/// 檔案參數是否代表 stdin
+fn is_stdin_path(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// 讀取單一輸入（檔案或 `-`）並解碼
+fn load_input(
+    path: &Path,
+    stdin: &StdinCache,
+    byte_range: Option<encoder::ByteRange>,
+    user_encoding: Option<&'static encoding_rs::Encoding>,
+    options: &encoder::DecodeOptions,
+) -> Result<(String, encoder::DetectedEncoding)> {
+    match (is_stdin_path(path), byte_range) {
+        (true, Some(range)) => {
+            encoder::decode_range_with_encoding(stdin.bytes()?, range, user_encoding, options)
+        }
+        (true, None) => encoder::decode_with_encoding(stdin.bytes()?, user_encoding, options),
+        (false, Some(range)) => {
+            encoder::read_file_range_with_encoding(path, range, user_encoding, options)
+        }
+        (false, None) => encoder::read_file_with_encoding(path, user_encoding, options),
+    }
+}
+
+/// 讀取單一輸入（檔案或 `-`）的原始位元組，不解碼
+///
+/// 與一般讀取相同：遵守 --bytes，沒有上限時不讀取讀不完的裝置檔（例如 `/dev/zero`）
+fn read_raw_input<'a>(
+    path: &Path,
+    stdin: &'a StdinCache,
+    max_bytes: Option<u64>,
+) -> Result<std::borrow::Cow<'a, [u8]>> {
+    if is_stdin_path(path) {
+        return Ok(std::borrow::Cow::Borrowed(stdin.bytes()?));
+    }
+    let bytes = encoder::read_file_bytes(path, max_bytes)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(std::borrow::Cow::Owned(bytes))
+}
+
+/// 偵測單一輸入（檔案或 `-`）的編碼，只讀取開頭的一段
+fn detect_input(
+    path: &Path,
+    stdin: &StdinCache,
+    user_encoding: Option<&'static encoding_rs::Encoding>,
+    options: &encoder::DecodeOptions,
+) -> Result<encoder::DetectedEncoding> {
+    let (limit, threshold) = (options.detection_bytes, options.confidence);
+    if is_stdin_path(path) {
+        encoder::detect_encoding_from_reader(stdin.bytes()?, user_encoding, limit, threshold)
+    } else {
+        encoder::detect_file_encoding(path, user_encoding, limit, threshold)
+            .with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+/// 只保留前 `max` 個檔案，回傳被略過的數量
+fn limit_files(files: &mut Vec<PathBuf>, max: Option<usize>) -> usize {
+    match max {
+        Some(max) if files.len() > max => {
+            let skipped = files.len() - max;
+            files.truncate(max);
+            skipped
+        }
+        _ => 0,
+    }
+}
+
+/// 沒有指定檔案且 stdin 是終端機時的提示訊息
+fn stdin_hint(stdin_is_terminal: bool) -> Option<&'static str> {
+    stdin_is_terminal.then_some(
+        "cate: reading from terminal (press Ctrl+D to finish, or run 'cate --help' for usage)",
+    )
+}
+
+/// 建立單一檔案的統計資料（用於 --summary-json）
+fn file_report(
+    path: Option<&Path>,
+    content: &str,
+    detected: &encoder::DetectedEncoding,
+    language: Option<&str>,
+) -> report::FileReport {
+    let first_line = content.lines().next();
+    let syntax = highlighter::Highlighter::new(None, false)
+        .map(|hl| hl.syntax_name(path, first_line, language).to_string())
+        .unwrap_or_else(|_| "Plain Text".to_string());
+    let name = path.map_or_else(|| "-".to_string(), |p| p.display().to_string());
+
+    report::FileReport::new(&name, content, detected, &syntax)
+}
+
+/// --debug 時說明語法是怎麼選出來的（寫到 stderr，不影響內容），
+/// 若內容看起來像其他語言，再提示可用 `-l` 指定
+fn debug_syntax(
+    path: Option<&Path>,
+    content: &str,
+    language: Option<&str>,
+    detection_bytes: Option<usize>,
+) {
+    if !logging::enabled(logging::Level::Debug) {
+        return;
+    }
+
+    let Ok(hl) = highlighter::Highlighter::new(None, false) else {
+        return;
+    };
+    let (syntax, source) = hl.explain_syntax(path, content.lines().next(), language);
+    debug!("Syntax: {} (chosen by {})", syntax, source);
+
+    if language.is_some() {
+        return;
+    }
+    if let Some((name, language)) =
+        highlighter::language_suggestion(syntax, content, detection_bytes)
+    {
+        debug!(
+            "Detected syntax is {}, but the content looks like {}; try `-l {}`",
+            syntax, name, language
+        );
+    }
+}
+
+/// 依序預先讀取項目：每個項目在獨立執行緒中載入，
+/// 透過有界 channel 限制同時進行的數量，並按輸入順序回傳結果
+fn prefetch_ordered<T, F>(items: Vec<PathBuf>, depth: usize, load: F) -> impl Iterator<Item = T>
+where
+    T: Send + 'static,
+    F: Fn(PathBuf) -> T + Send + Sync + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(depth);
+    let load = Arc::new(load);
+
+    thread::spawn(move || {
+        for item in items {
+            let load = Arc::clone(&load);
+            let handle = thread::spawn(move || load(item));
+            // 接收端已關閉（例如提早結束），停止預讀
+            if sender.send(handle).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+        .into_iter()
+        .map(|handle| handle.join().expect("prefetch worker panicked"))
+}
+
 fn print_help() {
     println!("cate - cat with encoding support and syntax highlighting");
     println!();
@@ -168,16 +1763,172 @@ fn print_help() {
     println!("    -h, --help              Print this help message");
     println!("    -v, --version           Print version information");
     println!("    -e, --encoding <ENC>    Specify input encoding (utf-8, gbk, big5, etc.)");
+    println!("    --output-encoding <ENC> Convert the output to ENC (disables highlighting unless UTF-8)");
+    println!("    --invalid <MODE>        Undecodable bytes: replace (default), skip or error");
+    println!("    --replacement-char <C>  Show undecodable bytes as C instead of U+FFFD");
+    println!("    --strip-bom             Also remove a U+FEFF left at the start after decoding");
+    println!("    --keep-bom              Keep the file's BOM in the output (as U+FEFF)");
+    println!(
+        "    --decode <FORMAT>       Decode base64 or hex input to raw bytes before detection"
+    );
+    println!("    --fd <N>                Read input from file descriptor N (Unix)");
+    println!("    --binary                Print binary files instead of skipping them");
+    println!("    --hex                   Print a hex dump of the raw bytes (like hexdump -C)");
+    println!("    --file-name <NAME>      Name used to detect the syntax of stdin / --fd input");
     println!("    -n, --number            Show line numbers");
-    println!("    --debug                 Show debug information");
+    println!("    -b, --number-nonblank   Number non-empty lines only (overrides -n)");
+    println!("    -E, --show-ends         Display $ at the end of each line");
+    println!("    --locate                Prefix each line with path:line: (grep -Hn style)");
+    println!("    -V, --verbose           Increase verbosity (-V: encoding, -VV: full debug)");
+    println!("    --debug                 Show debug information (same as -VV)");
+    println!("    --debug-highlight       Print each line's highlighter scope stack to stderr");
+    println!("    --reveal-bidi           Show bidi control and zero-width characters as <U+XXXX>");
+    println!("    --reveal-in-strings     Like --reveal-bidi, but only inside string literals");
+    println!("    --show-nonprinting      Show control characters as ^X and M-^X (tabs kept)");
+    println!("    --interpret-escapes     Turn literal \\n, \\t, \\r, \\\\ and \\xHH into real characters");
+    println!("    --escapes <LIST>        Interpret only these escapes (e.g. n,t; implies --interpret-escapes)");
+    println!("    --normalize-unicode <FORM>");
+    println!(
+        "                            Normalize text to nfc, nfd, nfkc or nfkd (changes bytes)"
+    );
+    println!("    -q, --quiet             Suppress warnings (only errors are printed)");
+    println!("    --fail-on-replacement   Skip and report inputs with undecodable bytes, exit 1 (alias: --strict)");
+    println!("    --input-buffer-size <BYTES>");
+    println!("                            Line reader buffer size (default: 65536)");
+    println!(
+        "    --detection-bytes <N>   Examine only the first N bytes to detect encoding/syntax"
+    );
+    println!("    --min-confidence <LEVEL>");
+    println!("                            Trust a statistical encoding guess only at low (default), medium or high confidence");
+    println!("    --strict-confidence     Fail instead of using the system encoding when a guess is below --min-confidence");
+    println!("    --prefetch <N>          Read up to N files ahead in the background (default: 0)");
     println!("    --list-encodings        List all supported encodings");
+    println!("    --files-from <FILE>     Read file names from FILE ('-' for stdin)");
+    println!("    --null                  File names in --files-from are NUL-separated");
+    println!("    --no-run-on-empty       Skip empty files entirely (no separator line)");
+    println!("    --max-files <N>         Process at most N files");
+    println!(
+        "    --detect-only           Print the detected encoding of each file (or stdin) and exit"
+    );
+    println!("    --dump-detection        Print the bytes and steps behind each encoding decision");
+    println!(
+        "    --checksum <ALG>        Print the md5/sha1/sha256 of the decoded content and exit"
+    );
+    println!("    --checksum-raw          With --checksum, hash the raw bytes instead");
+    println!("    --summary-json          Print a JSON summary of all files instead of content");
+    println!("    --line-length-histogram Print a histogram of line lengths instead of content");
+    println!("    --histogram-unit <UNIT> Measure line length in chars (default) or bytes");
+    println!("    --detect-newline        Count LF, CRLF and lone CR line endings; warn if mixed");
+    println!(
+        "    --osc133                Wrap output in OSC 133 shell-integration marks (TTY only)"
+    );
+    println!("    --paging <MODE>         Page output through $PAGER (default: less -RF): auto, always, never");
+    println!("    --header[=always]       Print a ──── path (size) ──── line before each file (TTY only unless =always)");
+    println!("    --header-color <COLOR>  Header color (name or SGR code, default: gray)");
+    println!("    --server                Read file names from stdin, one per line, and print each (blank line ends)");
     println!();
     println!("SYNTAX HIGHLIGHTING:");
     println!("    --no-highlight          Disable syntax highlighting");
-    println!("    --theme <THEME>         Set color theme (default: base16-eighties.dark)");
+    println!("    --theme <THEME>         Set color theme (default: picked from the terminal background)");
+    println!("    --theme-light <THEME>   Theme for light backgrounds (default: InspiredGitHub)");
+    println!(
+        "    --theme-dark <THEME>    Theme for dark backgrounds (default: base16-eighties.dark)"
+    );
+    println!(
+        "    --true-color <MODE>     24-bit color: auto (env), probe (ask terminal), always, never"
+    );
+    println!("    --color-depth <BITS>    1 (no color), 4 (16 colors), 8 (256 colors) or 24 (true color)");
+    println!(
+        "    --invert-theme          Flip the theme's lightness (dark theme on a light terminal)"
+    );
+    println!("    --flatten-ansi-to-16    Downsample 24-bit and 256 colors to the basic 16 colors");
+    println!("    --dim-uninteresting     Dim comments, strings and imports");
+    println!("    --dim-scopes <LIST>     Dim these comma-separated scopes instead (e.g. comment)");
+    println!("    --preserve-ansi-and-highlight");
+    println!(
+        "                            Pass through lines that contain ESC[, highlight the rest"
+    );
+    println!("    --highlight-timestamps  Color ISO 8601 / syslog timestamps at line starts");
+    println!("    --timestamp-pattern <RE>");
+    println!("                            Color line-start matches of RE instead (implies --highlight-timestamps)");
+    println!("    --highlight-urls        Underline http(s)://, file:// and mailto: URLs");
+    println!(
+        "    --hyperlinks            Also make URLs clickable (OSC 8; implies --highlight-urls)"
+    );
+    println!("    --match <RE>            Mark every match of RE in bold reverse video");
+    println!("    -i, --ignore-case       Match --match case-insensitively");
+    println!("    --match-case            Match --match case-sensitively (default; overrides -i)");
+    println!("    --highlight-levels[=line]");
+    println!("                            Color ERROR/WARN/INFO/DEBUG/TRACE keywords (=line: the whole line)");
+    println!("    --level-colors <MAP>    Keyword colors, e.g. error=red,notice=cyan (implies --highlight-levels)");
     println!("    -l, --language <LANG>   Specify syntax language (e.g., rust, python)");
     println!("    --list-themes           List all available themes");
+    println!("    --theme-dir <DIR>       Also load .tmTheme files from DIR (default: $CATE_CONFIG_DIR/themes)");
+    println!("    --syntax-dir <DIR>      Also load .sublime-syntax files from DIR");
     println!("    --list-syntaxes         List all supported languages");
+    println!(
+        "    --list-syntaxes=scopes  List languages with their top-level scope (Name -> scope)"
+    );
+    println!();
+    println!("FILTERING:");
+    println!(
+        "    --line-index            Print line<TAB>byte offset<TAB>byte length for each line"
+    );
+    println!("    --byte-range <A:B>      Print only raw bytes A..B of each input (B exclusive)");
+    println!("    --bytes <N>             Read at most N bytes of each file (needed for devices)");
+    println!(
+        "    --against <FILE>        Print only lines that differ from FILE (+ added, ~ changed)"
+    );
+    println!("    --strip-shebang         Omit a leading #! line (numbering still starts at 2)");
+    println!("    --sample <N>            Print only every Nth line");
+    println!("    --sample-ends           With --sample, also print the first and last line");
+    println!("    --line-range <A:B>      Print only lines A..B (1-based, inclusive; repeatable)");
+    println!("    -H, --highlight-line <N|A:B>");
+    println!("                            Mark line N or lines A..B in reverse video (repeatable)");
+    println!("    --highlight-line-color <COLOR>");
+    println!("                            Style for marked lines (name or SGR code, default: 7)");
+    println!("    --zebra                 Shade every other line with a faint background");
+    println!(
+        "    -s, --squeeze-blank     Collapse runs of blank or whitespace-only lines into one"
+    );
+    println!("    --compact-blank <N>     Collapse runs of blank lines to at most N");
+    println!("    --collapse-repeats      Print runs of identical lines once, followed by (xN)");
+    println!();
+    println!("JOINING:");
+    println!("    -j, --join              Join all lines into one (no highlighting)");
+    println!("    --join-separator <SEP>  Separator between joined lines (default: space)");
+    println!("    --join-trim             Trim each line and drop blank lines before joining");
+    println!();
+    println!("DECORATION:");
+    println!("    --line-prefix <STR>     Write STR at the start of every line (e.g. '> ')");
+    println!("    --line-suffix <STR>     Write STR at the end of every line");
+    println!("    --line-prefix-position <POS>");
+    println!("                            Prefix before (default) or after the line number");
+    println!();
+    println!("LONG LINES:");
+    println!("    --chop <COLS>           Truncate lines longer than COLS columns");
+    println!("    --wrap <MODE>           Wrap long lines: auto (terminal width), never (default) or COLS");
+    println!("    --pad-to-width <COLS>   Pad shorter lines with spaces to COLS columns");
+    println!("    --reflow-long-comments  Word-wrap long // and # comments to the terminal width (needs highlighting)");
+    println!("    --warn-long-lines <N>   Warn when a line is longer than N bytes");
+    println!(
+        "    --truncate-long-lines   Cut lines over the limit before highlighting (default: 16384)"
+    );
+    println!("    --max-output-lines <N>  Stop after N lines in total (default: 10000 on an unpaged terminal, 0 = no limit)");
+    println!("    --truncate-marker <STR> Marker for truncated lines (default: ›)");
+    println!("    --truncate-marker-color <COLOR>");
+    println!("                            Marker color (name or SGR code, default: gray)");
+    println!();
+    println!("EXPORT:");
+    println!("    --export                Render the first input as a highlighted SVG image");
+    println!("    -o, --output <FILE.svg> Write the export to FILE instead of stdout");
+    println!();
+    println!("TABS:");
+    println!(
+        "    --tabs <N>              Expand tabs to the next multiple of N columns (0: keep tabs)"
+    );
+    println!("    --replace-tabs-in-output-only");
+    println!("                            Expand tabs after highlighting (syntax sees raw tabs)");
     println!();
     println!("EXAMPLES:");
     println!("    cate file.rs                    # Display Rust file with syntax highlighting");
@@ -186,6 +1937,7 @@ fn print_help() {
     println!("    cate -e gbk chinese.txt         # Specify GBK encoding");
     println!("    cat file.js | cate              # Read from stdin");
     println!("    cat script | cate -l python     # Specify language for stdin");
+    println!("    cate --export code.rs -o out.svg # Save highlighted code as SVG");
     println!();
     println!("SUPPORTED ENCODINGS:");
     encoder::list_encodings();
@@ -195,6 +1947,48 @@ fn print_version() {
     println!("cate {}", env!("CARGO_PKG_VERSION"));
 }
 
+/// 是否停用高亮：--no-highlight 與 --color-depth 1 一律停用；沒有明確指定 --color-depth 時
+/// 才看環境變數
+fn no_highlight(args: &Args, env_disabled: bool) -> bool {
+    match args.color_depth {
+        _ if args.no_highlight => true,
+        Some(depth) => depth == highlighter::ColorDepth::Mono,
+        None => env_disabled,
+    }
+}
+
+/// 環境變數是否要求停用上色
+fn env_disables_color() -> bool {
+    color_disabled_by_env(
+        std::env::var("NO_COLOR").ok().as_deref(),
+        std::env::var("FORCE_COLOR").ok().as_deref(),
+    )
+}
+
+/// 非空的 NO_COLOR（https://no-color.org）或 FORCE_COLOR 為 `0` / `false` 時停用上色
+///
+/// 輸出不論是否為終端機預設都會上色，所以其他的 FORCE_COLOR 值沒有作用
+fn color_disabled_by_env(no_color: Option<&str>, force_color: Option<&str>) -> bool {
+    no_color.is_some_and(|value| !value.is_empty())
+        || matches!(force_color, Some("0") | Some("false"))
+}
+
+/// 決定要使用的主題：--theme 優先；否則依終端機背景在 --theme-light / --theme-dark 中選擇，
+/// 背景無法判斷時視為深色
+fn resolve_theme(args: &Args) -> Option<String> {
+    if args.theme.is_some() || args.no_highlight {
+        return args.theme.clone();
+    }
+    match terminal::background_is_light() {
+        Some(true) => Some(
+            args.theme_light
+                .clone()
+                .unwrap_or_else(|| highlighter::DEFAULT_LIGHT_THEME.to_string()),
+        ),
+        _ => args.theme_dark.clone(),
+    }
+}
+
 fn list_themes() {
     println!("Available themes:");
     let mut themes = highlighter::Highlighter::available_themes();
@@ -202,6 +1996,16 @@ fn list_themes() {
     for theme in themes {
         println!("  {}", theme);
     }
+
+    // 同名的別名只列出生效的那一個（使用者定義的優先）
+    println!();
+    println!("Aliases:");
+    let mut shown = std::collections::HashSet::new();
+    for (alias, theme) in highlighter::theme_aliases() {
+        if shown.insert(alias.clone()) {
+            println!("  {} -> {}", alias, theme);
+        }
+    }
 }
 
 fn list_syntaxes() {
@@ -212,3 +2016,492 @@ fn list_syntaxes() {
         println!("{}", syntax);
     }
 }
+
+fn list_syntax_scopes() {
+    for (name, scope) in highlighter::Highlighter::available_syntax_scopes() {
+        println!("{} -> {}", name, scope);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn parse_args(args: &[&str]) -> Args {
+        let args = args.iter().map(OsString::from).collect();
+        Args::from_arguments(Arguments::from_vec(args)).unwrap()
+    }
+
+    #[test]
+    fn test_verbosity_levels() {
+        assert_eq!(parse_args(&[]).verbosity, 0);
+        assert_eq!(parse_args(&["-V"]).verbosity, 1);
+        assert_eq!(parse_args(&["-V", "--verbose"]).verbosity, 2);
+        assert_eq!(parse_args(&["-VV"]).verbosity, 2);
+        assert_eq!(parse_args(&["-VVV"]).verbosity, 3);
+        assert_eq!(parse_args(&["-VVV", "-V"]).verbosity, 4);
+        assert_eq!(
+            parse_args(&["-VVV", "file"]).files,
+            vec![PathBuf::from("file")]
+        );
+        assert_eq!(parse_args(&["--debug"]).verbosity, 2);
+        assert_eq!(parse_args(&["-V", "-V", "-V", "file"]).verbosity, 3);
+
+        assert_eq!(logging::Level::from_verbosity(0), logging::Level::Normal);
+        assert_eq!(logging::Level::from_verbosity(1), logging::Level::Info);
+        assert_eq!(logging::Level::from_verbosity(3), logging::Level::Debug);
+    }
+
+    #[test]
+    fn test_squeeze_blank_is_compact_blank_one() {
+        assert_eq!(parse_args(&["-s"]).compact_blank, Some(1));
+        assert_eq!(parse_args(&["--compact-blank", "3"]).compact_blank, Some(3));
+        assert_eq!(parse_args(&[]).compact_blank, None);
+    }
+
+    #[test]
+    fn test_min_confidence_flags() {
+        let args = parse_args(&["--min-confidence", "medium", "--strict-confidence"]);
+        assert_eq!(
+            args.min_confidence,
+            Some(encoder::EncodingConfidence::Medium)
+        );
+        assert!(args.strict_confidence);
+        assert_eq!(parse_args(&[]).min_confidence, None);
+        let args = ["--min-confidence", "certain"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert!(Args::from_arguments(Arguments::from_vec(args)).is_err());
+    }
+
+    #[test]
+    fn test_retab_detect_exit_code() {
+        let consistent = indent::IndentReport::analyze("a.py", "def f():\n    pass\n");
+        let mixed = indent::IndentReport::analyze("b.py", "def f():\n\tx = 1\n    pass\n");
+        assert_eq!(retab_exit_code(std::slice::from_ref(&consistent)), 0);
+        assert_eq!(retab_exit_code(&[consistent, mixed]), 1);
+
+        assert!(parse_args(&["--retab-detect=json"]).retab_json);
+        assert!(!parse_args(&["--retab-detect"]).retab_json);
+    }
+
+    #[test]
+    fn test_separate_files_after_missing_newline() {
+        let options = printer::PrintOptions {
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let concat = |inputs: &[&str]| {
+            let mut out: Vec<u8> = Vec::new();
+            let mut printed_any = false;
+            for input in inputs {
+                assert!(separate_files(&mut out, &mut printed_any).unwrap());
+                printer::write_content(input.as_bytes(), &mut out, None, &options).unwrap();
+            }
+            String::from_utf8(out).unwrap()
+        };
+
+        // 分隔的換行只補完沒有換行的最後一行，不多出空行；最後一個檔案維持原樣
+        assert_eq!(concat(&["a\n", "b\n"]), "a\n\nb\n");
+        assert_eq!(concat(&["no newline", "b\n"]), "no newline\nb\n");
+        assert_eq!(concat(&["a\n", "no newline"]), "a\n\nno newline");
+    }
+
+    #[test]
+    fn test_write_header() {
+        let args = parse_args(&["--header=always"]);
+        assert!(args.header_always);
+        assert!(!args.header);
+        assert!(parse_args(&["--header"]).header);
+
+        let path = std::env::temp_dir().join(format!("cate-header-{}.txt", std::process::id()));
+        std::fs::write(&path, "hello\n").unwrap();
+        let mut out = Vec::new();
+        assert!(write_header(&mut out, &path, None).unwrap());
+        assert!(write_header(&mut out, &path, Some("90")).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let title = format!("──── {} (6 bytes) ────", path.display());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}\n\x1b[90m{}\x1b[0m\n", title, title)
+        );
+
+        // stdin 沒有標題
+        let mut out = Vec::new();
+        assert!(write_header(&mut out, Path::new("-"), None).unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_server_prints_each_file() {
+        assert!(parse_args(&["--server"]).server);
+
+        let dir = std::env::temp_dir();
+        let rust = dir.join(format!("cate-server-{}.rs", std::process::id()));
+        let python = dir.join(format!("cate-server-{}.py", std::process::id()));
+        let binary = dir.join(format!("cate-server-{}.bin", std::process::id()));
+        std::fs::write(&rust, "fn main() {}\n").unwrap();
+        std::fs::write(&python, "def f():\n    pass\n").unwrap();
+        std::fs::write(&binary, b"\x7fELF\x00\x01\x02").unwrap();
+
+        let options = printer::PrintOptions {
+            true_color: Some(true),
+            ..Default::default()
+        };
+        let mut policy = FilePolicy {
+            refuse_binary: true,
+            fail_on_replacement: false,
+            show_header: false,
+            header_style: None,
+            output_encoding: None,
+            buffer_size: 8192,
+        };
+        // 空行結束，之後的檔名不處理；二進位檔與一般檔案參數一樣略過並說明
+        let input = format!(
+            "{}\n{}\n{}\n\n{}\n",
+            rust.display(),
+            binary.display(),
+            python.display(),
+            rust.display()
+        );
+        let serve_input = |input: &str, user_encoding, policy: &FilePolicy| {
+            let mut out = Vec::new();
+            let (result, messages) = logging::capture(logging::Level::Normal, || {
+                serve(
+                    input.as_bytes(),
+                    &mut out,
+                    &options,
+                    &encoder::DecodeOptions::default(),
+                    user_encoding,
+                    policy,
+                )
+            });
+            (result.map(|_| String::from_utf8(out).unwrap()), messages)
+        };
+
+        let (output, messages) = serve_input(&input, None, &policy);
+        let output = output.unwrap();
+        assert_eq!(
+            ansi::strip_escapes(&output),
+            "fn main() {}\ndef f():\n    pass\n"
+        );
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("is a binary file"));
+        // 兩個檔案都有語法高亮（依各自的副檔名）
+        let (first, second) = output.split_at(output.find("def").unwrap());
+        assert!(first.contains("\x1b[38;2;"));
+        assert!(second.contains("\x1b[38;2;"));
+
+        // --header 與 --fail-on-replacement 也套用在每個檔名上
+        policy.refuse_binary = false;
+        policy.show_header = true;
+        let (output, _) = serve_input(&input, None, &policy);
+        let output = ansi::strip_escapes(&output.unwrap());
+        assert!(output.starts_with(&format!(
+            "──── {} (13 bytes) ────\nfn main() {{}}\n",
+            rust.display()
+        )));
+        assert!(output.contains(&format!(
+            "──── {} (7 bytes) ────\n\x7fELF",
+            binary.display()
+        )));
+
+        let lossy = dir.join(format!("cate-server-{}.txt", std::process::id()));
+        std::fs::write(&lossy, b"ok \xff\xfe ok").unwrap();
+        policy.fail_on_replacement = true;
+        let (output, messages) = serve_input(
+            &format!("{}\n{}\n", lossy.display(), rust.display()),
+            Some(encoding_rs::UTF_8),
+            &policy,
+        );
+        assert_eq!(
+            output.unwrap_err().to_string(),
+            "1 file(s) could not be decoded"
+        );
+        assert!(messages[0].contains("could not be decoded as"));
+        std::fs::remove_file(&lossy).unwrap();
+        std::fs::remove_file(&rust).unwrap();
+        std::fs::remove_file(&python).unwrap();
+        std::fs::remove_file(&binary).unwrap();
+    }
+
+    #[test]
+    fn test_fail_on_replacement_reports_lossy_file() {
+        assert!(parse_args(&["--strict"]).fail_on_replacement);
+
+        let path = std::env::temp_dir().join(format!("cate-lossy-{}.txt", std::process::id()));
+        std::fs::write(&path, b"ok \xff\xfe ok").unwrap();
+        let options = encoder::DecodeOptions::default();
+        let (_, detected) = logging::capture(logging::Level::Quiet, || {
+            load_input(
+                &path,
+                &StdinCache::default(),
+                None,
+                Some(encoding_rs::UTF_8),
+                &options,
+            )
+        })
+        .0
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 有錯誤時回報路徑與位置（main 以錯誤結束，結束碼非 0）
+        let failure = replacement_failure(&path, &detected).unwrap();
+        assert!(failure.ends_with("(byte offsets: 3, 4)"), "{}", failure);
+
+        let (_, clean) = encoder::decode_with_encoding(b"ok", None, &options).unwrap();
+        assert_eq!(replacement_failure(&path, &clean), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_from_fd() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_end, write_end] = fds;
+
+        let data = b"print('from fd')\n";
+        assert_eq!(
+            unsafe { libc::write(write_end, data.as_ptr().cast(), data.len()) },
+            data.len() as isize
+        );
+        unsafe { libc::close(write_end) };
+
+        let mut bytes = Vec::new();
+        open_fd(read_end).unwrap().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, data);
+        assert!(open_fd(-1).unwrap_err().to_string().contains("not open"));
+    }
+
+    #[test]
+    fn test_input_buffer_size() {
+        assert_eq!(parse_args(&[]).input_buffer_size, DEFAULT_INPUT_BUFFER_SIZE);
+        assert_eq!(
+            parse_args(&["--input-buffer-size", "4096"]).input_buffer_size,
+            4096
+        );
+        let args = Arguments::from_vec(vec!["--input-buffer-size".into(), "0".into()]);
+        assert!(Args::from_arguments(args).is_err());
+
+        // 緩衝區大小不影響輸出
+        let content: String = (1..=50).map(|i| format!("第 {} 行\tline\n", i)).collect();
+        let options = printer::PrintOptions {
+            show_line_numbers: true,
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let render = |capacity| {
+            let reader = content_reader(content.clone(), capacity);
+            assert_eq!(reader.capacity(), capacity);
+            let mut out = Vec::new();
+            printer::write_content(reader, &mut out, None, &options).unwrap();
+            out
+        };
+        assert_eq!(render(3), render(DEFAULT_INPUT_BUFFER_SIZE));
+    }
+
+    #[test]
+    fn test_no_color_env() {
+        assert!(color_disabled_by_env(Some("1"), None));
+        assert!(!color_disabled_by_env(Some(""), None));
+        assert!(!color_disabled_by_env(None, None));
+    }
+
+    #[test]
+    fn test_color_env_precedence() {
+        assert!(color_disabled_by_env(None, Some("0")));
+        assert!(color_disabled_by_env(None, Some("false")));
+        // 其他的 FORCE_COLOR 值與未設定相同（預設就會上色）
+        assert!(!color_disabled_by_env(None, Some("1")));
+        assert!(!color_disabled_by_env(None, Some("")));
+        // NO_COLOR 優先於 FORCE_COLOR，空的 NO_COLOR 視為未設定
+        assert!(color_disabled_by_env(Some("1"), Some("1")));
+        assert!(!color_disabled_by_env(Some(""), Some("1")));
+
+        // 明確的選項優先於環境變數
+        assert!(no_highlight(&parse_args(&[]), true));
+        assert!(!no_highlight(&parse_args(&[]), false));
+        assert!(!no_highlight(&parse_args(&["--color-depth", "8"]), true));
+        assert!(no_highlight(&parse_args(&["--color-depth", "1"]), false));
+        assert!(no_highlight(&parse_args(&["--no-highlight"]), false));
+    }
+
+    #[test]
+    fn test_print_width_reports_source() {
+        assert_eq!(
+            format_width(132, terminal::WidthSource::Env),
+            "132 (COLUMNS)"
+        );
+        let (width, source) = terminal::resolve_width(Some("132"), Some(100));
+        assert_eq!(format_width(width, source), "132 (COLUMNS)");
+        let (width, source) = terminal::resolve_width(None, None);
+        assert_eq!(format_width(width, source), "80 (fallback)");
+    }
+
+    #[test]
+    fn test_file_list_newline_separated() {
+        let files = parse_file_list(b"a.txt\nsub dir/b.rs\r\n\nc.md\n", false);
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("sub dir/b.rs"),
+                PathBuf::from("c.md")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_list_null_separated() {
+        let files = parse_file_list(b"line\nbreak.txt\0b.rs\0", true);
+        assert_eq!(
+            files,
+            vec![PathBuf::from("line\nbreak.txt"), PathBuf::from("b.rs")]
+        );
+    }
+
+    #[test]
+    fn test_files_from_appends_after_arguments() {
+        let list = std::env::temp_dir().join(format!("cate-list-{}.txt", std::process::id()));
+        std::fs::write(&list, "x.txt\ny.txt\n").unwrap();
+
+        let args = parse_args(&["first.txt", "--files-from", list.to_str().unwrap()]);
+        std::fs::remove_file(&list).unwrap();
+
+        assert_eq!(
+            args.files,
+            vec![
+                PathBuf::from("first.txt"),
+                PathBuf::from("x.txt"),
+                PathBuf::from("y.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_files_limits_inputs() {
+        let inputs: Vec<String> = (1..=10).map(|i| format!("file{}.txt", i)).collect();
+        let mut argv = vec!["--max-files", "3"];
+        argv.extend(inputs.iter().map(String::as_str));
+
+        let mut args = parse_args(&argv);
+        assert_eq!(args.files.len(), 10);
+        assert_eq!(limit_files(&mut args.files, args.max_files), 7);
+        assert_eq!(
+            args.files,
+            vec![
+                PathBuf::from("file1.txt"),
+                PathBuf::from("file2.txt"),
+                PathBuf::from("file3.txt")
+            ]
+        );
+
+        // 未指定上限時不略過任何檔案
+        let mut files = vec![PathBuf::from("a")];
+        assert_eq!(limit_files(&mut files, None), 0);
+    }
+
+    #[test]
+    fn test_highlighting_is_decided_per_file() {
+        let options = printer::PrintOptions {
+            true_color: Some(false),
+            ..Default::default()
+        };
+        let render = |path: &str, content: &str| {
+            let binary = encoder::looks_binary(content.as_bytes());
+            let file_options = file_print_options(&options, binary, None);
+            let mut out = Vec::new();
+            printer::write_content(
+                content.as_bytes(),
+                &mut out,
+                Some(Path::new(path)),
+                &file_options,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // 同一組選項：原始碼照常高亮，二進位檔原樣輸出
+        assert!(render("main.rs", "fn main() {}\n").contains("\x1b["));
+        assert_eq!(render("data.bin", "ELF\0\x01fn\n"), "ELF\0\x01fn\n");
+        assert!(render("main.rs", "fn main() {}\n").contains("\x1b["));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_raw_input_refuses_devices() {
+        // --line-index、--hex 與 --checksum-raw 共用：讀不完的裝置需要 --bytes
+        let stdin = StdinCache::default();
+        let err = read_raw_input(Path::new("/dev/zero"), &stdin, None).unwrap_err();
+        assert!(format!("{:#}", err).contains("--bytes"));
+        assert_eq!(
+            read_raw_input(Path::new("/dev/zero"), &stdin, Some(4)).unwrap(),
+            &b"\0\0\0\0"[..]
+        );
+        assert!(read_raw_input(Path::new("/dev/null"), &stdin, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_detect_only_reads_stdin() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("中文編碼測試，這是一段比較長的文字內容。");
+        let stdin = StdinCache(OnceCell::with_value(gbk.into_owned()));
+
+        let options = encoder::DecodeOptions::default();
+        let detected = detect_input(Path::new("-"), &stdin, None, &options).unwrap();
+        assert_eq!(detected.encoding, encoding_rs::GBK);
+
+        let detected =
+            detect_input(Path::new("-"), &stdin, Some(encoding_rs::BIG5), &options).unwrap();
+        assert_eq!(detected.encoding, encoding_rs::BIG5);
+        assert_eq!(detected.confidence, encoder::EncodingConfidence::Certain);
+    }
+
+    #[test]
+    fn test_stdin_is_replayed_for_each_dash() {
+        let stdin = StdinCache(OnceCell::with_value(b"from stdin\n".to_vec()));
+        let file = std::env::temp_dir().join(format!("cate-dash-{}.txt", std::process::id()));
+        std::fs::write(&file, "from file\n").unwrap();
+
+        let inputs = [PathBuf::from("-"), file.clone(), PathBuf::from("-")];
+        let contents: Vec<String> = inputs
+            .iter()
+            .map(|path| {
+                load_input(path, &stdin, None, None, &encoder::DecodeOptions::default())
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(
+            contents,
+            vec!["from stdin\n", "from file\n", "from stdin\n"]
+        );
+    }
+
+    #[test]
+    fn test_stdin_hint_only_for_terminal() {
+        assert!(stdin_hint(true).unwrap().contains("cate --help"));
+        assert!(stdin_hint(false).is_none());
+    }
+
+    #[test]
+    fn test_prefetch_preserves_order() {
+        let items: Vec<PathBuf> = (0..6).map(|i| PathBuf::from(i.to_string())).collect();
+
+        // 越前面的項目載入越慢，確保完成順序與輸入順序不同
+        let results: Vec<String> = prefetch_ordered(items, 3, |path: PathBuf| {
+            let n: u64 = path.to_str().unwrap().parse().unwrap();
+            thread::sleep(Duration::from_millis((6 - n) * 10));
+            path.display().to_string()
+        })
+        .collect();
+
+        assert_eq!(results, vec!["0", "1", "2", "3", "4", "5"]);
+    }
+}
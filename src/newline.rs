@@ -0,0 +1,97 @@
+// 行尾分析：統計 LF、CRLF 與單獨的 CR，找出混用的情況
+
+use std::fmt;
+
+/// 單一檔案的行尾統計
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewlineReport {
+    pub path: String,
+    pub lf: usize,   // `\n`
+    pub crlf: usize, // `\r\n`
+    pub cr: usize,   // 後面沒有接 `\n` 的 `\r`（舊式 Mac）
+}
+
+impl NewlineReport {
+    /// 掃描位元組中的行尾；`\r` 與 `\n` 在 UTF-8 中不會出現在多位元組字元內
+    pub fn analyze(path: &str, bytes: &[u8]) -> Self {
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+
+        let mut iter = bytes.iter().peekable();
+        while let Some(&b) = iter.next() {
+            match b {
+                b'\n' => lf += 1,
+                b'\r' if iter.peek() == Some(&&b'\n') => {
+                    iter.next();
+                    crlf += 1;
+                }
+                b'\r' => cr += 1,
+                _ => {}
+            }
+        }
+
+        Self {
+            path: path.to_string(),
+            lf,
+            crlf,
+            cr,
+        }
+    }
+
+    /// 是否混用一種以上的行尾
+    pub fn is_mixed(&self) -> bool {
+        [self.lf, self.crlf, self.cr]
+            .iter()
+            .filter(|&&count| count > 0)
+            .count()
+            > 1
+    }
+
+    /// 行尾種類：lf / crlf / cr，混用時為 mixed，沒有換行時為 none
+    fn kind(&self) -> &'static str {
+        match (self.lf, self.crlf, self.cr) {
+            _ if self.is_mixed() => "mixed",
+            (0, 0, 0) => "none",
+            (_, 0, 0) => "lf",
+            (0, _, 0) => "crlf",
+            _ => "cr",
+        }
+    }
+}
+
+impl fmt::Display for NewlineReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: lf={} crlf={} cr={} ({})",
+            self.path,
+            self.lf,
+            self.crlf,
+            self.cr,
+            self.kind()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_newlines() {
+        let report = NewlineReport::analyze("a.txt", b"one\ntwo\r\nthree\nfour\r\nfive\rsix");
+        assert_eq!((report.lf, report.crlf, report.cr), (2, 2, 1));
+        assert!(report.is_mixed());
+        assert_eq!(report.to_string(), "a.txt: lf=2 crlf=2 cr=1 (mixed)");
+    }
+
+    #[test]
+    fn test_consistent_newlines() {
+        let report = NewlineReport::analyze("b.txt", b"one\r\ntwo\r\n");
+        assert!(!report.is_mixed());
+        assert_eq!(report.to_string(), "b.txt: lf=0 crlf=2 cr=0 (crlf)");
+        assert_eq!(
+            NewlineReport::analyze("c", b"no newline").to_string(),
+            "c: lf=0 crlf=0 cr=0 (none)"
+        );
+    }
+}
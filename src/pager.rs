@@ -0,0 +1,137 @@
+// 分頁器（--paging）：輸出到終端機時把內容交給 $PAGER，由分頁器決定是否需要捲動
+
+use std::io::IsTerminal;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+
+/// 沒有設定 CATE_PAGER / PAGER 時使用的分頁器；`-F` 讓不滿一頁的內容直接輸出後結束
+const DEFAULT_PAGER: &str = "less -RF";
+
+/// --paging：何時使用分頁器
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PagingMode {
+    #[default]
+    Auto, // stdout 是終端機時
+    Always, // 即使輸出被導向也使用
+    Never,  // 不使用
+}
+
+impl FromStr for PagingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(PagingMode::Auto),
+            "always" => Ok(PagingMode::Always),
+            "never" => Ok(PagingMode::Never),
+            _ => Err(format!(
+                "Invalid value '{}' for --paging (expected auto, always or never)",
+                s
+            )),
+        }
+    }
+}
+
+/// 執行中的分頁器；結束時關閉它的 stdin 並等待使用者離開
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// 依模式啟動分頁器；不需要分頁或無法啟動時回傳 None，呼叫端改寫到 stdout
+    pub fn start(mode: PagingMode) -> Option<Self> {
+        match mode {
+            PagingMode::Never => return None,
+            PagingMode::Auto if !std::io::stdout().is_terminal() => return None,
+            _ => {}
+        }
+
+        let command = pager_command(
+            std::env::var("CATE_PAGER").ok().as_deref(),
+            std::env::var("PAGER").ok().as_deref(),
+        )?;
+        debug!("Pager: {}", command.join(" "));
+
+        match Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => Some(Pager { child }),
+            // 自動模式下找不到分頁器（例如 Windows 沒有 less）時安靜地直接輸出
+            Err(e) if mode == PagingMode::Auto => {
+                debug!("Failed to start pager '{}': {}", command[0], e);
+                None
+            }
+            Err(e) => {
+                warn!("failed to start pager '{}': {}", command[0], e);
+                None
+            }
+        }
+    }
+
+    /// 分頁器的輸入；使用者提早離開時寫入會得到 broken pipe
+    pub fn input(&mut self) -> &mut ChildStdin {
+        self.child.stdin.as_mut().expect("pager stdin is piped")
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+/// 分頁器命令：CATE_PAGER 優先於 PAGER，都沒有時為 `less -RF`；空字串代表不使用分頁器
+///
+/// 只以空白切分參數（不經過 shell）；沒有參數的 `less` 補上 `-RF`，讓顏色正常顯示
+fn pager_command(cate_pager: Option<&str>, pager: Option<&str>) -> Option<Vec<String>> {
+    let command: Vec<String> = cate_pager
+        .or(pager)
+        .unwrap_or(DEFAULT_PAGER)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let program = command.first()?;
+
+    let is_less = std::path::Path::new(program)
+        .file_stem()
+        .is_some_and(|stem| stem == "less");
+    if is_less && command.len() == 1 {
+        return Some(vec![program.clone(), "-RF".to_string()]);
+    }
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paging_mode_from_str() {
+        assert_eq!("auto".parse(), Ok(PagingMode::Auto));
+        assert_eq!("Always".parse(), Ok(PagingMode::Always));
+        assert_eq!("never".parse(), Ok(PagingMode::Never));
+        assert!("sometimes".parse::<PagingMode>().is_err());
+    }
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None, None).unwrap(), ["less", "-RF"]);
+        assert_eq!(
+            pager_command(None, Some("more -d")).unwrap(),
+            ["more", "-d"]
+        );
+        // CATE_PAGER 優先，沒有參數的 less 補上 -RF
+        assert_eq!(
+            pager_command(Some("/usr/bin/less"), Some("more")).unwrap(),
+            ["/usr/bin/less", "-RF"]
+        );
+        assert_eq!(
+            pager_command(Some("less -S"), None).unwrap(),
+            ["less", "-S"]
+        );
+        assert_eq!(pager_command(Some(""), Some("more")), None);
+    }
+}
@@ -1,129 +1,2824 @@
-use crate::highlighter::{supports_true_color, Highlighter, LineHighlighter};
+use crate::ansi;
+use crate::diff::LineChange;
+use crate::highlighter::{self, strip_line_ending, supports_true_color, Highlighter};
+use regex::Regex;
+use std::collections::BTreeMap;
 use std::io::{self, BufRead, Write};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
 
-/// 列印檔案內容（streaming 模式）
-pub fn print_content_streaming<R: BufRead>(
+/// 列印選項
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub show_line_numbers: bool,
+    pub number_nonblank: bool, // 只為非空行編號（cat -b），優先於 show_line_numbers
+    pub show_ends: bool,       // 在每行結尾加上 `$`（cat -E）
+    pub enable_highlighting: bool,
+    pub theme: Option<String>,
+    pub language: Option<String>,
+    pub true_color: Option<bool>,   // None 表示依環境變數判斷
+    pub basic_colors: bool,         // 只使用 16 色（優先於 true_color）
+    pub invert_theme: bool,         // 反轉主題顏色的亮度
+    pub dim_scopes: Option<String>, // 以低亮度輸出符合這些 scope 的片段
+    pub flatten_ansi: bool,         // 輸出前把真彩色與 256 色降為最接近的 16 色
+
+    // Tab 處理
+    pub tab_width: usize,            // tab 寬度
+    pub expand_tabs_in_output: bool, // 高亮後才在輸出中展開 tab
+
+    // 截斷長行
+    pub chop: Option<usize>,                   // 超過此欄數的行會被截斷
+    pub truncate_marker: String,               // 截斷標記
+    pub truncate_marker_style: Option<String>, // 截斷標記的 SGR 樣式
+    pub pad_to_width: Option<usize>,           // 以空白將每行補到此欄數（不截斷）
+
+    // 隱藏字元
+    pub reveal_bidi: bool,                // 以標記顯示雙向控制字元與零寬字元
+    pub reveal_in_strings: bool,          // 只顯示字串字面值中的隱藏字元（需要語法高亮）
+    pub show_nonprinting: bool,           // 以 `^X` / `M-^X` 顯示控制字元（cat -v）
+    pub escapes: Option<Escapes>,         // 把字面上的 `\n`、`\t` 等轉為實際字元
+    pub normalize: Option<Normalization>, // 輸出前套用 Unicode 正規化（會改變位元組）
+
+    // 取樣
+    pub sample: Option<usize>, // 每 N 行輸出一行
+    pub sample_ends: bool,     // 取樣時也輸出第一行與最後一行
+
+    pub line_ranges: Vec<LineRange>, // 只輸出這些行（多個範圍取聯集；空的代表全部）
+    pub highlight_lines: Vec<LineRange>, // 以 highlight_line_style 標出這些行（-H）
+    pub highlight_line_style: String, // 標出行的 SGR 樣式（預設反白）
+    pub zebra: bool,                 // 偶數行加上淡背景色（--zebra）
+    pub zebra_width: usize,          // 斑馬紋背景補齊到的輸出寬度（含行號欄）
+
+    pub strip_shebang: bool, // 不輸出第一行的 shebang（仍用於語法檢測）
+
+    pub compact_blank: Option<usize>, // 連續空行最多輸出 N 行
+    pub collapse_repeats: bool,       // 連續相同的非空行只輸出一次，並加上 `(xN)`
+
+    pub force_syntax_path: bool, // 除錯用：純文字也經過 highlight_line
+    pub debug_highlight: bool,   // 除錯用：每行結束時的 scope 堆疊輸出到 stderr
+
+    // 超長行
+    pub long_line_limit: Option<usize>, // 超過此位元組數的行會在 stderr 提示
+    pub truncate_long_lines: bool,      // 在高亮前截斷超長行並加上截斷標記
+
+    pub output_limit: OutputLimit, // 所有輸入合計最多輸出的行數，超過時在 stderr 提示並停止
+
+    pub locate: bool, // 每行前加上 `路徑:行號:`（grep -Hn 格式）
+
+    // 合併成一行
+    pub join: Option<String>, // 以此分隔字串取代換行
+    pub join_trim: bool,      // 合併前去掉每行頭尾空白，並略過空行
+
+    // 行首/行尾裝飾（例如引用用的 `> `）
+    pub line_prefix: Option<String>,
+    pub line_suffix: Option<String>,
+    pub prefix_position: PrefixPosition, // 前綴相對於行號的位置
+
+    pub reflow_comments: Option<usize>, // 超過此寬度的單行註解重新換行（需要語法高亮）
+    pub wrap: Option<usize>,            // 超過此寬度（含行號欄）的行折成多列
+
+    pub preserve_ansi: bool, // 已含 ANSI 跳脫序列的行原樣輸出，其餘的行照常高亮
+
+    pub timestamps: Option<Regex>, // 行首符合此樣式的時間戳記另外上色（不受高亮開關影響）
+    pub matches: Option<Regex>,    // 標出每行中所有符合此樣式的文字（--match）
+    pub highlight_urls: bool,      // 為網址加上底線
+    pub hyperlinks: bool,          // 網址同時輸出為 OSC 8 超連結
+    pub levels: Option<LevelColors>, // 依記錄層級關鍵字上色（不受高亮開關影響）
+
+    pub changes: Option<BTreeMap<usize, LineChange>>, // 只輸出這些行，行首加上變更標記（--against）
+}
+
+/// 行首前綴相對於行號（或 `--locate` 位置）的位置
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PrefixPosition {
+    #[default]
+    Before, // 前綴在行號之前：`> 1 code`
+    After, // 前綴在行號之後：`1 > code`
+}
+
+impl FromStr for PrefixPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "before" => Ok(PrefixPosition::Before),
+            "after" => Ok(PrefixPosition::After),
+            _ => Err(format!(
+                "Invalid value '{}' for --line-prefix-position (expected before or after)",
+                s
+            )),
+        }
+    }
+}
+
+/// --normalize-unicode 的正規化形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl Normalization {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+            Normalization::Nfkc => text.nfkc().collect(),
+            Normalization::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nfc" => Ok(Normalization::Nfc),
+            "nfd" => Ok(Normalization::Nfd),
+            "nfkc" => Ok(Normalization::Nfkc),
+            "nfkd" => Ok(Normalization::Nfkd),
+            _ => Err(format!(
+                "Invalid value '{}' for --normalize-unicode (expected nfc, nfd, nfkc or nfkd)",
+                s
+            )),
+        }
+    }
+}
+
+/// --interpret-escapes 要轉換的跳脫序列
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escapes {
+    pub newline: bool,         // `\n`
+    pub tab: bool,             // `\t`
+    pub carriage_return: bool, // `\r`
+    pub backslash: bool,       // `\\`
+    pub hex: bool,             // `\xHH`
+}
+
+impl Escapes {
+    pub const ALL: Escapes = Escapes {
+        newline: true,
+        tab: true,
+        carriage_return: true,
+        backslash: true,
+        hex: true,
+    };
+}
+
+impl FromStr for Escapes {
+    type Err = String;
+
+    /// 以逗號分隔的清單，例如 `n,t` 或 `n,t,r,\\,x`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut escapes = Escapes {
+            newline: false,
+            tab: false,
+            carriage_return: false,
+            backslash: false,
+            hex: false,
+        };
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "n" => escapes.newline = true,
+                "t" => escapes.tab = true,
+                "r" => escapes.carriage_return = true,
+                "\\" | "backslash" => escapes.backslash = true,
+                "x" => escapes.hex = true,
+                _ => {
+                    return Err(format!(
+                        "Invalid escape '{}' for --escapes (expected n, t, r, \\ or x)",
+                        name
+                    ))
+                }
+            }
+        }
+        Ok(escapes)
+    }
+}
+
+/// 預設的記錄層級顏色（關鍵字 → 顏色名稱）
+const DEFAULT_LEVEL_COLORS: &[(&str, &str)] = &[
+    ("error", "red"),
+    ("warn", "yellow"),
+    ("warning", "yellow"),
+    ("info", "blue"),
+    ("debug", "gray"),
+    ("trace", "gray"),
+];
+
+/// --highlight-levels：依記錄層級關鍵字（整個單字、不分大小寫）為關鍵字或整行上色
+#[derive(Debug, Clone)]
+pub struct LevelColors {
+    colors: Vec<(String, String)>, // 小寫關鍵字 → SGR 參數
+    pattern: Regex,
+    whole_line: bool, // 為整行上色，而不只是關鍵字
+}
+
+impl LevelColors {
+    /// `map` 為 `關鍵字=顏色,…`（例如 `error=1;31,notice=cyan`），覆蓋或補充預設的對應
+    pub fn new(map: Option<&str>, whole_line: bool) -> Result<Self, String> {
+        let mut colors: Vec<(String, String)> = DEFAULT_LEVEL_COLORS
+            .iter()
+            .map(|(keyword, color)| (keyword.to_string(), ansi::color_sgr(color).unwrap()))
+            .collect();
+
+        for entry in map.into_iter().flat_map(|map| map.split(',')) {
+            let (keyword, color) = entry
+                .split_once('=')
+                .map(|(keyword, color)| (keyword.trim().to_lowercase(), color.trim()))
+                .filter(|(keyword, _)| !keyword.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid entry '{}' for --level-colors (expected KEYWORD=COLOR)",
+                        entry
+                    )
+                })?;
+            let sgr = ansi::color_sgr(color)
+                .ok_or_else(|| format!("Unknown color '{}' for --level-colors", color))?;
+            match colors.iter_mut().find(|(existing, _)| *existing == keyword) {
+                Some(existing) => existing.1 = sgr,
+                None => colors.push((keyword, sgr)),
+            }
+        }
+
+        let keywords: Vec<String> = colors
+            .iter()
+            .map(|(keyword, _)| regex::escape(keyword))
+            .collect();
+        let pattern = Regex::new(&format!(r"(?i)\b(?:{})\b", keywords.join("|")))
+            .map_err(|e| e.to_string())?;
+
+        Ok(LevelColors {
+            colors,
+            pattern,
+            whole_line,
+        })
+    }
+
+    /// 為一行（可能已高亮）中第一個層級關鍵字上色；沒有關鍵字時回傳 None
+    fn decorate(&self, rendered: &str) -> Option<String> {
+        let plain = ansi::strip_escapes(rendered);
+        let found = self.pattern.find(&plain)?;
+        let keyword = found.as_str().to_lowercase();
+        let (_, sgr) = self.colors.iter().find(|(k, _)| *k == keyword)?;
+        Some(if self.whole_line {
+            ansi::paint(&plain, sgr)
+        } else {
+            paint_visible_range(rendered, found.range(), sgr)
+        })
+    }
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            show_line_numbers: false,
+            number_nonblank: false,
+            show_ends: false,
+            enable_highlighting: true,
+            theme: None,
+            language: None,
+            true_color: None,
+            basic_colors: false,
+            invert_theme: false,
+            dim_scopes: None,
+            flatten_ansi: false,
+            tab_width: 8,
+            expand_tabs_in_output: false,
+            chop: None,
+            truncate_marker: "›".to_string(),
+            truncate_marker_style: None,
+            pad_to_width: None,
+            reveal_bidi: false,
+            reveal_in_strings: false,
+            show_nonprinting: false,
+            escapes: None,
+            normalize: None,
+            sample: None,
+            sample_ends: false,
+            line_ranges: Vec::new(),
+            highlight_lines: Vec::new(),
+            highlight_line_style: "7".to_string(),
+            zebra: false,
+            zebra_width: 80,
+            strip_shebang: false,
+            compact_blank: None,
+            collapse_repeats: false,
+            force_syntax_path: false,
+            debug_highlight: false,
+            long_line_limit: None,
+            truncate_long_lines: false,
+            output_limit: OutputLimit::default(),
+            locate: false,
+            join: None,
+            join_trim: false,
+            line_prefix: None,
+            line_suffix: None,
+            prefix_position: PrefixPosition::Before,
+            reflow_comments: None,
+            wrap: None,
+            preserve_ansi: false,
+            timestamps: None,
+            matches: None,
+            highlight_urls: false,
+            hyperlinks: false,
+            levels: None,
+            changes: None,
+        }
+    }
+}
+
+/// --zebra 偶數行的背景色（256 色的深灰；--flatten-ansi 時降為 16 色）
+const ZEBRA_STYLE: &str = "48;5;236";
+
+/// --hex 每列的位元組數
+const HEX_ROW_LEN: usize = 16;
+
+/// --hex 的 ASCII 欄中可列印字元與以 `.` 表示的其他位元組的顏色
+const HEX_PRINTABLE_STYLE: &str = "32";
+const HEX_NONPRINTABLE_STYLE: &str = "90";
+
+/// 以傳統 hexdump 格式輸出原始位元組（與 `hexdump -C` 相同）：
+/// 十六進位位移、每列 16 個位元組（8 個一組）與 ASCII 欄，最後一列是總長度
+///
+/// `color` 時 ASCII 欄的可列印字元與 `.` 以不同顏色區分
+pub fn write_hexdump<W: Write>(out: &mut W, bytes: &[u8], color: bool) -> io::Result<()> {
+    for (row, chunk) in bytes.chunks(HEX_ROW_LEN).enumerate() {
+        let mut line = format!("{:08x}  ", row * HEX_ROW_LEN);
+        for i in 0..HEX_ROW_LEN {
+            if i == HEX_ROW_LEN / 2 {
+                line.push(' ');
+            }
+            match chunk.get(i) {
+                Some(b) => line.push_str(&format!("{:02x} ", b)),
+                None => line.push_str("   "),
+            }
+        }
+
+        line.push_str(" |");
+        // 相同類別的連續位元組一起上色
+        for run in chunk.chunk_by(|a, b| is_hex_printable(*a) == is_hex_printable(*b)) {
+            let printable = is_hex_printable(run[0]);
+            let text: String = run
+                .iter()
+                .map(|&b| if printable { b as char } else { '.' })
+                .collect();
+            match (color, printable) {
+                (true, true) => line.push_str(&ansi::paint(&text, HEX_PRINTABLE_STYLE)),
+                (true, false) => line.push_str(&ansi::paint(&text, HEX_NONPRINTABLE_STYLE)),
+                (false, _) => line.push_str(&text),
+            }
+        }
+        line.push('|');
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out, "{:08x}", bytes.len())
+}
+
+/// hexdump 的 ASCII 欄中直接顯示的位元組（可列印的 ASCII，含空白）
+fn is_hex_printable(b: u8) -> bool {
+    (0x20..=0x7e).contains(&b)
+}
+
+/// 列印檔案內容（streaming 模式）；輸出端已關閉（broken pipe）時視為正常結束
+pub fn print_content_streaming<R: BufRead, W: Write>(
+    reader: R,
+    out: &mut W,
+    file_path: Option<&Path>,
+    options: &PrintOptions,
+) -> io::Result<()> {
+    match write_content(reader, out, file_path, options) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// 將檔案內容寫入指定的輸出
+pub fn write_content<R: BufRead, W: Write>(
     mut reader: R,
-    show_line_numbers: bool,
+    out: &mut W,
     file_path: Option<&Path>,
-    enable_highlighting: bool,
-    theme: Option<&str>,
-    language: Option<&str>,
+    options: &PrintOptions,
 ) -> io::Result<()> {
-    // 準備高亮器（需要在外層創建以延長生命週期）
-    let highlighter = if enable_highlighting {
-        Highlighter::new(theme, supports_true_color()).ok()
+    // 空檔案：不建立高亮器，也不輸出任何東西
+    if reader.fill_buf()?.is_empty() {
+        return Ok(());
+    }
+
+    // 準備高亮器（需要在外層創建以延長生命週期）；合併成一行時不高亮
+    let highlighter = if options.enable_highlighting && options.join.is_none() {
+        let true_color = options.true_color.unwrap_or_else(supports_true_color);
+        Highlighter::new(options.theme.as_deref(), true_color)
+            .ok()
+            .map(|hl| {
+                if options.basic_colors {
+                    hl.with_basic_colors()
+                } else {
+                    hl
+                }
+            })
+            .map(|hl| {
+                if options.invert_theme {
+                    hl.with_inverted_colors()
+                } else {
+                    hl
+                }
+            })
+            .map(|hl| {
+                // scope 清單已在解析參數時驗證過
+                match options
+                    .dim_scopes
+                    .as_deref()
+                    .and_then(|list| highlighter::parse_scope_selectors(list).ok())
+                {
+                    Some(scopes) => hl.with_dimmed_scopes(scopes),
+                    None => hl,
+                }
+            })
     } else {
         None
     };
 
+    let mut line_buffer = String::new();
+
     let mut line_highlighter = if let Some(ref hl) = highlighter {
-        // 讀取第一行用於語法檢測
-        let mut first_line = String::new();
-        reader.read_line(&mut first_line)?;
+        // 讀取第一行用於語法檢測（讀到的內容留在 buffer 中稍後列印）
+        reader.read_line(&mut line_buffer)?;
 
-        let first_line_opt = if first_line.is_empty() {
+        let first_line_opt = if line_buffer.is_empty() {
             None
         } else {
-            Some(first_line.trim_end())
+            Some(line_buffer.trim_end())
         };
 
-        let mut lh = hl.prepare_for_file(file_path, first_line_opt, language);
-
-        // 處理第一行
-        if !first_line.is_empty() {
-            print_single_line(&mut lh, &first_line, 1, show_line_numbers)?;
+        let mut lh = hl.prepare_for_file(file_path, first_line_opt, options.language.as_deref());
+        if options.force_syntax_path {
+            lh.force_syntax_path();
         }
-
         Some(lh)
     } else {
+        reader.read_line(&mut line_buffer)?;
         None
     };
 
-    // 處理剩餘的行
-    let mut line_buffer = String::new();
-    let mut line_number = 2; // 第一行已經處理過了
+    let mut line_number = 1;
+    // -b：到目前為止的非空行數（依原檔計算，不受取樣影響；被壓縮掉的行不計）
+    let mut nonblank_count = 0;
+    let mut hidden_controls = 0;
+    let mut first_hidden_line = None;
+    // 超長行的數量與第一次出現的行號
+    let mut long_lines = 0;
+    let mut first_long_line = None;
+    // 取樣時最後一個未輸出的行（用於 --sample-ends）
+    let mut last_skipped: Option<(String, String, usize, Option<usize>)> = None;
+    // 目前連續空行的數量，與到目前為止被壓縮掉的空行數
+    let mut blank_run = 0;
+    let mut squeezed = 0;
+    // 合併模式下已輸出的行數（決定是否需要分隔字串）
+    let mut joined = 0;
+    // 這個檔案已輸出的行數（重新換行的續行不另外計算），--zebra 依此交替
+    let mut output_lines: usize = 0;
+    // --locate 的路徑（stdin 為 `-`）
+    let location = options
+        .locate
+        .then(|| file_path.map_or_else(|| "-".to_string(), |p| p.display().to_string()));
+    let location = location.as_deref();
 
-    while reader.read_line(&mut line_buffer)? > 0 {
-        if let Some(ref mut lh) = line_highlighter {
-            print_single_line(lh, &line_buffer, line_number, show_line_numbers)?;
+    while !line_buffer.is_empty() {
+        let is_empty_line = strip_line_ending(&line_buffer).is_empty();
+        // --collapse-repeats 比較的是轉換前的原始內容；空行交給 --compact-blank 處理
+        let raw_line = (options.collapse_repeats && !is_empty_line).then(|| line_buffer.clone());
+
+        // 只有空白字元的行也算空行；與 GNU cat -s 相同，被壓縮掉的行不佔用 -n / -b 的行號
+        if strip_line_ending(&line_buffer).trim_end().is_empty() {
+            blank_run += 1;
         } else {
+            blank_run = 0;
+        }
+        let is_squeezed = options.compact_blank.is_some_and(|n| blank_run > n);
+        if is_squeezed {
+            squeezed += 1;
+        } else if !is_empty_line {
+            nonblank_count += 1;
+        }
+        let number = display_number(
+            line_number,
+            squeezed,
+            (!is_empty_line).then_some(nonblank_count),
+            location,
+            options,
+        );
+
+        // 記錄雙向控制字元與零寬字元（Trojan Source）；檔案開頭保留的 BOM（--keep-bom）不算
+        let scanned = match line_number {
+            1 => line_buffer.trim_start_matches('\u{FEFF}'),
+            _ => &line_buffer,
+        };
+        let hidden = scanned.chars().filter(|&c| is_hidden_control(c)).count();
+        if hidden > 0 {
+            hidden_controls += hidden;
+            first_hidden_line.get_or_insert(line_number);
+        }
+
+        // 超長行（例如壓縮過的單行檔案）：先截斷再高亮，避免高亮與終端機輸出拖慢
+        let mut truncated = false;
+        if let Some(limit) = options.long_line_limit {
+            let body_len = strip_line_ending(&line_buffer).len();
+            if body_len > limit {
+                long_lines += 1;
+                first_long_line.get_or_insert(line_number);
+
+                if options.truncate_long_lines {
+                    let cut = (0..=limit)
+                        .rev()
+                        .find(|&i| line_buffer.is_char_boundary(i))
+                        .unwrap_or(0);
+                    line_buffer.replace_range(cut..body_len, "");
+                    truncated = true;
+                }
+            }
+        }
+
+        // Unicode 正規化（組合字元不會跨行，可以逐行處理）
+        if let Some(form) = options.normalize {
+            line_buffer = form.apply(&line_buffer);
+        }
+
+        // 字面上的跳脫序列轉為實際字元；產生的換行拆成續行，沿用同一個行號
+        let mut unescaped_lines = Vec::new();
+        if let Some(escapes) = options.escapes {
+            let body = strip_line_ending(&line_buffer);
+            let unescaped = interpret_escapes(body, escapes);
+            if unescaped != body {
+                let ending = &line_buffer[body.len()..];
+                let mut pieces = unescaped.split('\n');
+                let first = format!("{}{}", pieces.next().unwrap_or_default(), ending);
+                unescaped_lines = pieces.map(str::to_string).collect();
+                line_buffer = first;
+            }
+        }
+
+        // 控制字元改以可見的表示法交給高亮器；搭配 -E 時 CRLF 的 `\r` 也顯示為 `^M`
+        if options.show_nonprinting {
+            let body = strip_line_ending(&line_buffer);
+            let mut ending = &line_buffer[body.len()..];
+            let mut shown = show_nonprinting(body, options.preserve_ansi);
+            if options.show_ends && ending.starts_with('\r') {
+                shown.push_str("^M");
+                ending = &ending[1..];
+            }
+            if shown.len() != body.len() {
+                line_buffer = format!("{}{}", shown, ending);
+            }
+        }
+
+        // --reveal-in-strings：高亮前先找出落在字串中的隱藏字元（依語法 scope）
+        let hidden_in_strings: Vec<usize> = match line_highlighter {
+            Some(ref lh) if options.reveal_in_strings && hidden > 0 => {
+                let ranges = lh.string_ranges(&line_buffer);
+                strip_line_ending(&line_buffer)
+                    .char_indices()
+                    .filter(|&(i, c)| is_hidden_control(c) && ranges.iter().any(|r| r.contains(&i)))
+                    .map(|(i, _)| i)
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        // 過長的整行註解重新換行：每段各自高亮（行註解在行尾結束，語法狀態不受影響）
+        let mut continuation = Vec::new();
+        let mut rendered = match line_highlighter {
+            // 已自帶顏色的行（例如編譯器輸出）原樣輸出；去掉跳脫序列後仍交給高亮器，維持語法狀態
+            Some(ref mut lh) if options.preserve_ansi && line_buffer.contains("\x1b[") => {
+                let _ = lh.highlight_line(&ansi::strip_escapes(&line_buffer));
+                strip_line_ending(&line_buffer).to_string()
+            }
+            Some(ref mut lh) => {
+                let reflowed = options
+                    .reflow_comments
+                    .filter(|_| !truncated)
+                    .and_then(|width| {
+                        let width = width.saturating_sub(gutter_width(number, location, options));
+                        reflow_comment(strip_line_ending(&line_buffer), width)
+                    })
+                    .filter(|_| lh.is_comment_line(&line_buffer));
+
+                match reflowed {
+                    Some(pieces) => {
+                        let mut pieces = pieces.into_iter().map(|piece| {
+                            lh.highlight_line(&format!("{}\n", piece)).unwrap_or(piece)
+                        });
+                        let first = pieces.next().unwrap_or_default();
+                        continuation = pieces.collect();
+                        first
+                    }
+                    None => lh
+                        .highlight_line(&line_buffer)
+                        .unwrap_or_else(|_| strip_line_ending(&line_buffer).to_string()),
+                }
+            }
             // 無語法高亮
-            print_plain_line(&line_buffer, line_number, show_line_numbers)?;
+            None => strip_line_ending(&line_buffer).to_string(),
+        };
+        if !hidden_in_strings.is_empty() {
+            rendered = reveal_hidden_controls_at(&rendered, &hidden_in_strings);
+        }
+        // 先處理層級（整行模式會重新上色），時間戳記再疊加在上面
+        if let Some(ref levels) = options.levels {
+            if let Some(decorated) = levels.decorate(&rendered) {
+                rendered = decorated;
+            }
+        }
+        if let Some(ref pattern) = options.timestamps {
+            if let Some(decorated) = highlight_timestamp(&rendered, pattern) {
+                rendered = decorated;
+            }
+        }
+        if options.highlight_urls {
+            if let Some(decorated) = highlight_urls(&rendered, options.hyperlinks) {
+                rendered = decorated;
+            }
+        }
+        if let Some(ref pattern) = options.matches {
+            if let Some(decorated) = highlight_matches(&rendered, pattern) {
+                rendered = decorated;
+            }
+        }
+        continuation.extend(
+            unescaped_lines
+                .into_iter()
+                .map(|piece| match line_highlighter {
+                    Some(ref mut lh) => lh.highlight_line(&format!("{}\n", piece)).unwrap_or(piece),
+                    None => piece,
+                }),
+        );
+        if truncated {
+            match options.truncate_marker_style {
+                Some(ref sgr) => rendered.push_str(&ansi::paint(&options.truncate_marker, sgr)),
+                None => rendered.push_str(&options.truncate_marker),
+            }
+        }
+
+        // 向後讀取與這一行相同的行：只輸出這一次並加上次數，讀到的下一個不同行留到下一輪
+        let mut repeats = 0;
+        let mut lookahead = None;
+        if let Some(ref raw) = raw_line {
+            let mut next = String::new();
+            while reader.read_line(&mut next)? > 0
+                && strip_line_ending(&next) == strip_line_ending(raw)
+            {
+                repeats += 1;
+                if let Some(ref mut lh) = line_highlighter {
+                    let _ = lh.highlight_line(&line_buffer);
+                }
+                // 行尾沿用最後一個重複行（檔案結尾沒有換行時也不補上）
+                let body_len = strip_line_ending(&line_buffer).len();
+                line_buffer.truncate(body_len);
+                line_buffer.push_str(&next[strip_line_ending(&next).len()..]);
+                next.clear();
+            }
+            if repeats > 0 {
+                rendered.push_str(&format!(" (x{})", repeats + 1));
+                nonblank_count += repeats;
+            }
+            lookahead = Some(next);
+        }
+
+        // 高亮卡在某個狀態時（例如未結束的字串），可以看出從哪一行開始偏離
+        if options.debug_highlight {
+            if let Some(ref lh) = line_highlighter {
+                crate::logging::emit(format_args!(
+                    "[HIGHLIGHT] line {}: {}",
+                    line_number,
+                    lh.state_summary()
+                ));
+            }
+        }
+
+        // -H：疊加在語法顏色上；重新換行的續行一併標出
+        if options
+            .highlight_lines
+            .iter()
+            .any(|range| range.contains(line_number))
+        {
+            rendered = ansi::overlay(&rendered, &options.highlight_line_style);
+            for piece in &mut continuation {
+                *piece = ansi::overlay(piece, &options.highlight_line_style);
+            }
+        }
+
+        // 被略過的行仍會經過高亮器，保持語法狀態正確
+        let mut line_ending = &line_buffer[strip_line_ending(&line_buffer).len()..];
+        // shebang 已用於語法檢測；行號維持原檔位置（從 2 開始）
+        let is_stripped_shebang =
+            options.strip_shebang && line_number == 1 && line_buffer.starts_with("#!");
+
+        let mut is_compacted_blank = is_squeezed;
+
+        // 合併成一行：換行改為分隔字串（寫在行首），結尾統一補一個換行
+        if let Some(ref separator) = options.join {
+            if options.join_trim {
+                rendered = rendered.trim().to_string();
+                is_compacted_blank |= rendered.is_empty();
+            }
+            if !is_compacted_blank && !is_stripped_shebang && is_selected(line_number, options) {
+                if joined > 0 {
+                    rendered.insert_str(0, separator);
+                }
+                joined += 1;
+            }
+            line_ending = "";
+        }
+
+        if is_stripped_shebang || is_compacted_blank {
+            // 不輸出
+        } else if is_selected(line_number, options) {
+            // --max-output-lines 已達上限時不再輸出（--sample-ends 的最後一行也不輸出）；
+            // 合併模式只有一行，不設上限
+            if options.join.is_none() && !options.output_limit.take_line() {
+                last_skipped = None;
+                break;
+            }
+            output_lines += 1;
+
+            // 重新換行的註解：續行沿用同一個行號
+            let mut pieces = std::iter::once(&rendered).chain(&continuation).peekable();
+            while let Some(piece) = pieces.next() {
+                let ending = match pieces.peek() {
+                    Some(_) if line_ending.is_empty() => "\n",
+                    _ => line_ending,
+                };
+                write!(out, "{}", change_marker(line_number, options))?;
+                let striped = options.zebra && output_lines.is_multiple_of(2);
+                write_line(out, piece, ending, number, location, striped, options)?;
+            }
+            last_skipped = None;
+        } else if options.sample_ends && in_line_ranges(line_number, options) {
+            last_skipped = Some((rendered, line_ending.to_string(), line_number, number));
         }
 
         line_buffer.clear();
-        line_number += 1;
+        match lookahead {
+            Some(next) => line_buffer = next,
+            None => {
+                reader.read_line(&mut line_buffer)?;
+            }
+        }
+        // 被合併的重複行仍佔用原檔的行號
+        line_number += 1 + repeats;
+        if past_line_ranges(line_number, options) {
+            break;
+        }
+    }
+
+    if let Some((mut rendered, line_ending, line_number, number)) = last_skipped {
+        if let Some(ref separator) = options.join {
+            if joined > 0 {
+                rendered.insert_str(0, separator);
+            }
+            joined += 1;
+        }
+        write!(out, "{}", change_marker(line_number, options))?;
+        output_lines += 1;
+        let striped = options.zebra && output_lines.is_multiple_of(2);
+        write_line(
+            out,
+            &rendered,
+            &line_ending,
+            number,
+            location,
+            striped,
+            options,
+        )?;
+    }
+
+    if joined > 0 {
+        writeln!(out)?;
+    }
+
+    if let Some(line) = first_long_line {
+        warn!(
+            "{}: {} line(s) longer than {} bytes, first on line {}{}",
+            file_path.map_or_else(|| "-".to_string(), |p| p.display().to_string()),
+            long_lines,
+            options.long_line_limit.unwrap_or_default(),
+            line,
+            if options.truncate_long_lines {
+                " (truncated)"
+            } else {
+                " (use --truncate-long-lines to cut them)"
+            }
+        );
+    }
+
+    if let Some(line) = first_hidden_line {
+        warn!(
+            "{}: contains {} bidirectional or zero-width control character(s), first on line {}{}",
+            file_path.map_or_else(|| "-".to_string(), |p| p.display().to_string()),
+            hidden_controls,
+            line,
+            if options.reveal_bidi {
+                ""
+            } else {
+                " (use --reveal-bidi to show them)"
+            }
+        );
     }
 
     Ok(())
 }
 
-/// 列印單行（帶語法高亮）
-fn print_single_line(
-    highlighter: &mut LineHighlighter,
-    line: &str,
-    line_number: usize,
-    show_line_numbers: bool,
-) -> io::Result<()> {
-    let mut stdout = io::stdout().lock();
+/// --wrap：長行的折行方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WrapMode {
+    Auto, // 輸出到終端機時依終端機寬度折行
+    #[default]
+    Never, // 不折行（超出終端機的部分由終端機處理）
+    Columns(usize), // 固定欄數
+}
 
-    let highlighted = highlighter
-        .highlight_line(line)
-        .unwrap_or_else(|_| line.to_string());
+impl FromStr for WrapMode {
+    type Err = String;
 
-    if show_line_numbers {
-        match write!(stdout, "{} {}", line_number, highlighted) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
-            Err(e) => Err(e),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(WrapMode::Auto),
+            "never" => Ok(WrapMode::Never),
+            columns => match columns.parse() {
+                Ok(n) if n > 0 => Ok(WrapMode::Columns(n)),
+                _ => Err(format!(
+                    "Invalid value '{}' for --wrap (expected auto, never or a column count)",
+                    s
+                )),
+            },
         }
-    } else {
-        match write!(stdout, "{}", highlighted) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
-            Err(e) => Err(e),
+    }
+}
+
+/// --max-output-lines：所有輸入合計的輸出行數上限；複製的 PrintOptions 共用同一個計數
+#[derive(Debug, Clone, Default)]
+pub struct OutputLimit {
+    max: Option<usize>,
+    printed: Arc<AtomicUsize>,
+    reported: Arc<AtomicBool>,
+}
+
+impl OutputLimit {
+    /// `None` 代表不限制
+    pub fn new(max: Option<usize>) -> Self {
+        OutputLimit {
+            max,
+            ..Default::default()
+        }
+    }
+
+    /// 預留一行輸出；已達上限時在 stderr 提示（只提示一次）並回傳 false
+    pub fn take_line(&self) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+        self.printed.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// 是否已達上限（後面的輸入不再輸出）；第一次發現時在 stderr 提示
+    pub fn is_exhausted(&self) -> bool {
+        let Some(max) = self.max else {
+            return false;
+        };
+        if self.printed.load(Ordering::Relaxed) < max {
+            return false;
+        }
+        if !self.reported.swap(true, Ordering::Relaxed)
+            && crate::logging::enabled(crate::logging::Level::Normal)
+        {
+            crate::logging::emit(format_args!(
+                "... output truncated at {} lines, use --max-output-lines 0 to disable",
+                max
+            ));
         }
+        true
     }
 }
 
-/// 列印單行（無語法高亮）
-fn print_plain_line(line: &str, line_number: usize, show_line_numbers: bool) -> io::Result<()> {
-    let mut stdout = io::stdout().lock();
+/// --line-length-histogram 計算行長度的單位
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LengthUnit {
+    #[default]
+    Chars, // 字元數
+    Bytes, // UTF-8 位元組數（解碼後）
+}
+
+impl FromStr for LengthUnit {
+    type Err = String;
 
-    if show_line_numbers {
-        match writeln!(stdout, "{} {}", line_number, line.trim_end()) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
-            Err(e) => Err(e),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chars" => Ok(LengthUnit::Chars),
+            "bytes" => Ok(LengthUnit::Bytes),
+            _ => Err(format!(
+                "Invalid value '{}' for --histogram-unit (expected chars or bytes)",
+                s
+            )),
         }
-    } else {
-        match write!(stdout, "{}", line) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
-            Err(e) => Err(e),
+    }
+}
+
+/// 行長度（不含行尾）的直方圖，以 2 的次方分組：0、1、2-3、4-7、8-15…
+#[derive(Debug, Default)]
+pub struct LineLengthHistogram {
+    buckets: Vec<usize>,
+    lines: usize,
+    min: usize,
+    max: usize,
+    total: usize,
+}
+
+impl LineLengthHistogram {
+    /// 逐行讀取並累計，不保留內容
+    pub fn from_reader<R: BufRead>(mut reader: R, unit: LengthUnit) -> io::Result<Self> {
+        let mut histogram = Self::default();
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let body = strip_line_ending(&line);
+            histogram.add(match unit {
+                LengthUnit::Chars => body.chars().count(),
+                LengthUnit::Bytes => body.len(),
+            });
+            line.clear();
+        }
+        Ok(histogram)
+    }
+
+    pub fn add(&mut self, length: usize) {
+        let bucket = Self::bucket(length);
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+
+        self.min = if self.lines == 0 {
+            length
+        } else {
+            self.min.min(length)
+        };
+        self.max = self.max.max(length);
+        self.total += length;
+        self.lines += 1;
+    }
+
+    /// 長度所屬的分組：0 為空行，k 為 [2^(k-1), 2^k)
+    fn bucket(length: usize) -> usize {
+        (usize::BITS - length.leading_zeros()) as usize
+    }
+
+    /// 分組的範圍標籤
+    fn bucket_label(bucket: usize) -> String {
+        match bucket {
+            0 => "0".to_string(),
+            1 => "1".to_string(),
+            k => format!("{}-{}", 1usize << (k - 1), (1usize << k) - 1),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+impl std::fmt::Display for LineLengthHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const BAR_WIDTH: usize = 40;
 
-    #[test]
-    fn test_print_streaming() {
-        let content = "line 1\nline 2\nline 3\n";
-        let reader = Cursor::new(content);
-        // 這個測試主要確保函數不會 panic
-        let _ = print_content_streaming(reader, false, None, false, None, None);
+        if self.lines == 0 {
+            return writeln!(f, "lines: 0");
+        }
+        writeln!(
+            f,
+            "lines: {}, min: {}, max: {}, mean: {:.1}",
+            self.lines,
+            self.min,
+            self.max,
+            self.total as f64 / self.lines as f64
+        )?;
+
+        // 只列出最短與最長之間的分組，長條依最大的分組縮放
+        let largest = self.buckets.iter().copied().max().unwrap_or(0);
+        let first = Self::bucket(self.min);
+        for (bucket, &count) in self.buckets.iter().enumerate().skip(first) {
+            let bar = (count * BAR_WIDTH).div_ceil(largest);
+            writeln!(
+                f,
+                "{:>11} | {:>7} {}",
+                Self::bucket_label(bucket),
+                count,
+                "#".repeat(bar)
+            )?;
+        }
+        Ok(())
     }
+}
 
-    #[test]
-    fn test_print_streaming_with_line_numbers() {
-        let content = "line 1\nline 2\nline 3\n";
-        let reader = Cursor::new(content);
-        let _ = print_content_streaming(reader, true, None, false, None, None);
+/// 行號範圍 `START:END`（從 1 開始，包含 END，兩端皆可省略）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl LineRange {
+    /// `N` 代表單獨一行，其餘同 `START:END`（-H 使用）
+    pub fn parse_line_or_range(s: &str) -> Result<Self, String> {
+        if s.contains(':') {
+            return s.parse();
+        }
+        match s.parse() {
+            Ok(n) if n > 0 => Ok(LineRange {
+                start: n,
+                end: Some(n),
+            }),
+            _ => Err(format!("Invalid line number '{}'", s)),
+        }
+    }
+
+    fn contains(&self, line_number: usize) -> bool {
+        line_number >= self.start && self.end.is_none_or(|end| line_number <= end)
+    }
+}
+
+impl FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid line range '{}' (expected START:END)", s);
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+
+        let start = if start.is_empty() {
+            1
+        } else {
+            start.parse().map_err(|_| invalid())?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse().map_err(|_| invalid())?)
+        };
+
+        if start == 0 || end.is_some_and(|end| end < start) {
+            return Err(invalid());
+        }
+
+        Ok(LineRange { start, end })
+    }
+}
+
+/// 此行是否落在 --line-range 指定的範圍內
+fn in_line_ranges(line_number: usize, options: &PrintOptions) -> bool {
+    options.line_ranges.is_empty()
+        || options
+            .line_ranges
+            .iter()
+            .any(|range| range.contains(line_number))
+}
+
+/// 已超過所有 --line-range 的結尾：後面的行都不會輸出，可以停止讀取
+fn past_line_ranges(line_number: usize, options: &PrintOptions) -> bool {
+    !options.line_ranges.is_empty()
+        && options
+            .line_ranges
+            .iter()
+            .all(|range| range.end.is_some_and(|end| line_number > end))
+}
+
+/// 取樣模式下此行是否輸出
+fn is_sampled(line_number: usize, options: &PrintOptions) -> bool {
+    match options.sample {
+        Some(n) if n > 1 => {
+            line_number.is_multiple_of(n) || (options.sample_ends && line_number == 1)
+        }
+        _ => true,
+    }
+}
+
+/// 此行是否輸出：在 --line-range 內且符合取樣，有 --against 時只輸出有變更的行
+fn is_selected(line_number: usize, options: &PrintOptions) -> bool {
+    in_line_ranges(line_number, options)
+        && is_sampled(line_number, options)
+        && options
+            .changes
+            .as_ref()
+            .is_none_or(|changes| changes.contains_key(&line_number))
+}
+
+/// --against 的行首變更標記（例如 `+ `）；合併成一行時不加
+fn change_marker(line_number: usize, options: &PrintOptions) -> String {
+    match options.changes {
+        Some(ref changes) if options.join.is_none() => changes.get(&line_number).map_or_else(
+            || "  ".to_string(),
+            |change| format!("{} ", change.marker()),
+        ),
+        _ => String::new(),
+    }
+}
+
+/// 是否為會影響顯示的隱藏字元（雙向控制字元或零寬字元）
+pub fn is_hidden_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{202A}'..='\u{202E}' // LRE, RLE, PDF, LRO, RLO
+            | '\u{2066}'..='\u{2069}' // LRI, RLI, FSI, PDI
+            | '\u{200B}'..='\u{200F}' // 零寬空格、ZWNJ、ZWJ、LRM、RLM
+            | '\u{2060}' // 零寬不換行空格
+            | '\u{FEFF}' // 行中的 BOM
+    )
+}
+
+/// 轉換字面上的跳脫序列（類似 `echo -e`）；未啟用或無法辨識的序列原樣保留
+///
+/// `\\` 永遠視為一組，未啟用時也不會讓後面的字元被當成跳脫序列；`\xHH` 轉為 U+00HH
+fn interpret_escapes(content: &str, escapes: Escapes) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(pos) = rest.find('\\') {
+        output.push_str(&rest[..pos]);
+        let sequence = &rest[pos..];
+        let hex = sequence
+            .get(2..4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+        let (replacement, len) = match sequence.as_bytes().get(1) {
+            Some(b'n') if escapes.newline => ("\n".to_string(), 2),
+            Some(b't') if escapes.tab => ("\t".to_string(), 2),
+            Some(b'r') if escapes.carriage_return => ("\r".to_string(), 2),
+            Some(b'\\') if escapes.backslash => ("\\".to_string(), 2),
+            Some(b'\\') => ("\\\\".to_string(), 2),
+            Some(b'x') if escapes.hex && hex.is_some() => {
+                (char::from(hex.unwrap_or_default()).to_string(), 4)
+            }
+            _ => ("\\".to_string(), 1),
+        };
+        output.push_str(&replacement);
+        rest = &sequence[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// --highlight-timestamps 的預設樣式：行首的 ISO 8601（`2024-05-01T12:00:00.123Z`、
+/// `2024-05-01 12:00:00,123`）或 syslog（`May  1 12:00:00`）時間戳記，可以放在 `[...]` 中
+pub const DEFAULT_TIMESTAMP_PATTERN: &str = concat!(
+    r"^\[?(?:",
+    r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?",
+    r"|(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) [ \d]\d \d{2}:\d{2}:\d{2}",
+    r")\]?"
+);
+
+/// 時間戳記的顏色（青色）
+const TIMESTAMP_STYLE: &str = "36";
+
+/// 為行首的時間戳記上色（疊加在已高亮的內容上）；行首不符合樣式時回傳 None
+fn highlight_timestamp(rendered: &str, pattern: &Regex) -> Option<String> {
+    let plain = ansi::strip_escapes(rendered);
+    let found = pattern
+        .find(&plain)
+        .filter(|m| m.start() == 0 && !m.is_empty())?;
+    Some(paint_visible_range(
+        rendered,
+        found.range(),
+        TIMESTAMP_STYLE,
+    ))
+}
+
+/// --match 標出文字的樣式（粗體反白）
+const MATCH_STYLE: &str = "1;7";
+
+/// 標出所有符合樣式的文字；比對去掉跳脫序列後的內容，原本的顏色保留在反白之下
+///
+/// 沒有符合時回傳 None
+fn highlight_matches(rendered: &str, pattern: &Regex) -> Option<String> {
+    let plain = ansi::strip_escapes(rendered);
+    let ranges: Vec<_> = pattern
+        .find_iter(&plain)
+        .filter(|m| !m.is_empty())
+        .map(|m| (m.range(), None))
+        .collect();
+    if ranges.is_empty() {
+        return None;
+    }
+    Some(overlay_ranges(rendered, &ranges, MATCH_STYLE))
+}
+
+/// --highlight-urls 辨識的網址：http(s)、file 與 mailto；結尾的標點不算在網址內
+const URL_PATTERN: &str = r#"\b(?:https?://|file://|mailto:)[^\s<>"'`]*[^\s<>"'`.,;:!?)\]}]"#;
+
+/// 網址的樣式（底線）
+const URL_STYLE: &str = "4";
+
+static URL_REGEX: once_cell::sync::Lazy<Regex> =
+    once_cell::sync::Lazy::new(|| Regex::new(URL_PATTERN).unwrap());
+
+/// 為網址加上底線；`hyperlinks` 時再包上 OSC 8，讓支援的終端機可以點擊。沒有網址時回傳 None
+fn highlight_urls(rendered: &str, hyperlinks: bool) -> Option<String> {
+    let plain = ansi::strip_escapes(rendered);
+    let ranges: Vec<_> = URL_REGEX
+        .find_iter(&plain)
+        .map(|m| (m.range(), hyperlinks.then(|| m.as_str())))
+        .collect();
+    if ranges.is_empty() {
+        return None;
+    }
+    Some(overlay_ranges(rendered, &ranges, URL_STYLE))
+}
+
+/// 在已上色的內容上為幾段文字疊加樣式（範圍是去掉跳脫序列後的位元組位置，依序且不重疊）
+///
+/// 範圍內每個 SGR 序列之後重新套用樣式，結束時恢復最後一個顏色；
+/// 附有連結目標的範圍另外以 OSC 8 超連結包住
+fn overlay_ranges(
+    rendered: &str,
+    ranges: &[(std::ops::Range<usize>, Option<&str>)],
+    sgr: &str,
+) -> String {
+    let style = format!("\x1b[{}m", sgr);
+    let mut ranges = ranges.iter().peekable();
+    let mut output = String::with_capacity(rendered.len() + 32);
+    let mut i = 0;
+    let mut visible = 0;
+    let mut last_sgr = "";
+    let mut current: Option<&(std::ops::Range<usize>, Option<&str>)> = None;
+
+    let close =
+        |output: &mut String, current: &(std::ops::Range<usize>, Option<&str>), last_sgr| {
+            output.push_str(ansi::RESET);
+            output.push_str(last_sgr);
+            if current.1.is_some() {
+                output.push_str(ansi::OSC8_CLOSE);
+            }
+        };
+
+    while i < rendered.len() {
+        if let Some(range) = current.filter(|(range, _)| visible == range.end) {
+            close(&mut output, range, last_sgr);
+            current = None;
+        }
+        if current.is_none()
+            && ranges
+                .peek()
+                .is_some_and(|(range, _)| visible == range.start)
+        {
+            current = ranges.next();
+            if let Some((_, Some(target))) = current {
+                output.push_str(&ansi::osc8_open(target));
+            }
+            output.push_str(&style);
+        }
+
+        if let Some(len) = ansi::escape_len(rendered, i) {
+            let sequence = &rendered[i..i + len];
+            output.push_str(sequence);
+            if sequence.starts_with("\x1b[") && sequence.ends_with('m') {
+                last_sgr = sequence;
+                if current.is_some() {
+                    output.push_str(&style);
+                }
+            }
+            i += len;
+            continue;
+        }
+        let c = rendered[i..].chars().next().unwrap();
+        output.push(c);
+        i += c.len_utf8();
+        visible += c.len_utf8();
+    }
+    if let Some(range) = current {
+        close(&mut output, range, "");
+    }
+    output
+}
+
+/// 以 `sgr` 重新為已上色內容中的一段文字上色（`range` 是去掉跳脫序列後的位元組範圍）
+///
+/// 這段文字之後重新套用被蓋掉的最後一個顏色，其餘內容不變
+fn paint_visible_range(rendered: &str, range: std::ops::Range<usize>, sgr: &str) -> String {
+    let mut output = String::with_capacity(rendered.len() + 16);
+    let mut i = 0;
+    let mut visible = 0;
+    let mut last_sgr = "";
+
+    // 逐一走過可見字元直到 `until`；`copy` 為真時原樣複製，否則只記下途中的 SGR
+    let mut advance = |output: &mut String, until: usize, copy: bool| {
+        while i < rendered.len() && visible < until {
+            if let Some(len) = ansi::escape_len(rendered, i) {
+                let sequence = &rendered[i..i + len];
+                if sequence.starts_with("\x1b[") && sequence.ends_with('m') {
+                    last_sgr = sequence;
+                }
+                if copy {
+                    output.push_str(sequence);
+                }
+                i += len;
+                continue;
+            }
+            let c = rendered[i..].chars().next().unwrap();
+            if copy {
+                output.push(c);
+            }
+            i += c.len_utf8();
+            visible += c.len_utf8();
+        }
+        (i, last_sgr)
+    };
+
+    let (start, _) = advance(&mut output, range.start, true);
+    let (end, last_sgr) = advance(&mut output, range.end, false);
+    output.push_str(&ansi::paint(
+        &ansi::strip_escapes(&rendered[start..end]),
+        sgr,
+    ));
+    output.push_str(last_sgr);
+    output.push_str(&rendered[end..]);
+    output
+}
+
+/// 以 cat -v 的表示法顯示控制字元：`^A`、`^?`，C1 控制字元為 `M-^X`；tab 保持原樣
+///
+/// `preserve_ansi`（--preserve-ansi）時 ANSI 跳脫序列原樣保留，不把 ESC 顯示為 `^[`
+fn show_nonprinting(content: &str, preserve_ansi: bool) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut i = 0;
+    while i < content.len() {
+        if let Some(len) = ansi::escape_len(content, i).filter(|_| preserve_ansi) {
+            output.push_str(&content[i..i + len]);
+            i += len;
+            continue;
+        }
+
+        let c = content[i..].chars().next().unwrap();
+        i += c.len_utf8();
+        match c {
+            '\t' => output.push(c),
+            '\x00'..='\x1f' => {
+                output.push('^');
+                output.push((c as u8 + 64) as char);
+            }
+            '\x7f' => output.push_str("^?"),
+            '\u{80}'..='\u{9f}' => {
+                output.push_str("M-^");
+                output.push((c as u8 - 0x80 + 64) as char);
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// 將隱藏字元替換為可見的反白標記（例如 `<U+202E>`）
+fn reveal_hidden_controls(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    for c in content.chars() {
+        if is_hidden_control(c) {
+            output.push_str(&hidden_control_marker(c));
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// 只替換指定位置（去掉跳脫序列後的位元組位置）的隱藏字元
+fn reveal_hidden_controls_at(rendered: &str, positions: &[usize]) -> String {
+    let mut output = String::with_capacity(rendered.len() + positions.len() * 16);
+    let mut i = 0;
+    let mut visible = 0;
+    while i < rendered.len() {
+        if let Some(len) = ansi::escape_len(rendered, i) {
+            output.push_str(&rendered[i..i + len]);
+            i += len;
+            continue;
+        }
+        let c = rendered[i..].chars().next().unwrap();
+        if is_hidden_control(c) && positions.contains(&visible) {
+            output.push_str(&hidden_control_marker(c));
+        } else {
+            output.push(c);
+        }
+        i += c.len_utf8();
+        visible += c.len_utf8();
+    }
+    output
+}
+
+/// 隱藏字元的反白標記
+fn hidden_control_marker(c: char) -> String {
+    format!("\x1b[7m<U+{:04X}>\x1b[27m", c as u32)
+}
+
+/// 將超過 `width` 欄的 `//` 或 `#` 單行註解依單字換行，續行重複縮排與註解符號
+///
+/// 不是這類註解、或不需要換行時回傳 None；是否真的是註解由呼叫端以語法 scope 確認
+fn reflow_comment(body: &str, width: usize) -> Option<Vec<String>> {
+    if ansi::visible_width(body) <= width {
+        return None;
+    }
+
+    let rest = body.trim_start();
+    let indent = &body[..body.len() - rest.len()];
+    let marker_len = if rest.starts_with("//") {
+        rest.find(|c| c != '/' && c != '!').unwrap_or(rest.len())
+    } else if rest.starts_with('#') && !rest.starts_with("#!") {
+        rest.find(|c| c != '#').unwrap_or(rest.len())
+    } else {
+        return None;
+    };
+    let (marker, text) = rest.split_at(marker_len);
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    let prefix = format!("{}{} ", indent, marker);
+    let available = width.saturating_sub(ansi::visible_width(&prefix)).max(1);
+    Some(
+        ansi::word_wrap(text, available)
+            .into_iter()
+            .map(|line| format!("{}{}", prefix, line))
+            .collect(),
+    )
+}
+
+/// 行首要顯示的號碼：--locate 使用原檔行號；-n 使用原檔行號扣掉被壓縮掉的空行（`squeezed`），
+/// 與 GNU cat -sn 相同；-b 只為非空行編號，並優先於 -n
+fn display_number(
+    line_number: usize,
+    squeezed: usize,
+    nonblank_number: Option<usize>,
+    location: Option<&str>,
+    options: &PrintOptions,
+) -> Option<usize> {
+    if location.is_some() {
+        Some(line_number)
+    } else if options.number_nonblank {
+        nonblank_number
+    } else {
+        options.show_line_numbers.then_some(line_number - squeezed)
+    }
+}
+
+/// 行號欄的最小寬度；超過時隨行號位數增加（串流輸出無法預知總行數）
+const MIN_NUMBER_WIDTH: usize = 4;
+/// 行號與內容之間的分隔
+const NUMBER_SEPARATOR: &str = " │ ";
+
+/// 行號靠右對齊的寬度
+fn number_width(number: usize) -> usize {
+    number.to_string().len().max(MIN_NUMBER_WIDTH)
+}
+
+/// 行號、位置與行首/行尾裝飾佔用的欄數
+fn gutter_width(number: Option<usize>, location: Option<&str>, options: &PrintOptions) -> usize {
+    let gutter = match (location, number) {
+        (Some(path), Some(n)) => ansi::visible_width(path) + n.to_string().len() + 2,
+        (None, Some(n)) => number_width(n) + ansi::visible_width(NUMBER_SEPARATOR),
+        _ => 0,
+    };
+    let marker = if options.changes.is_some() { 2 } else { 0 };
+    let decoration = [&options.line_prefix, &options.line_suffix]
+        .into_iter()
+        .flatten()
+        .map(|s| ansi::visible_width(s))
+        .sum::<usize>();
+    gutter + marker + decoration
+}
+
+/// 寫出單行（內容已完成高亮，不含行尾）；`striped` 的行加上斑馬紋背景
+fn write_line<W: Write>(
+    out: &mut W,
+    content: &str,
+    line_ending: &str,
+    number: Option<usize>,
+    location: Option<&str>,
+    striped: bool,
+    options: &PrintOptions,
+) -> io::Result<()> {
+    let mut content = if options.reveal_bidi {
+        reveal_hidden_controls(content)
+    } else {
+        content.to_string()
+    };
+
+    // 在高亮後展開 tab，避免影響語法檢測
+    if options.expand_tabs_in_output {
+        content = ansi::expand_tabs(&content, options.tab_width);
+    }
+
+    // 截斷過長的行，並為截斷標記預留寬度
+    if let Some(max_width) = options.chop {
+        let marker = &options.truncate_marker;
+        if let Some(mut chopped) = ansi::truncate(&content, max_width, ansi::visible_width(marker))
+        {
+            match options.truncate_marker_style {
+                Some(ref sgr) => chopped.push_str(&ansi::paint(marker, sgr)),
+                None => chopped.push_str(marker),
+            }
+            content = chopped;
+        }
+    }
+
+    // 合併模式只有一行輸出，不加行首/行尾裝飾
+    let decorate = options.join.is_none();
+    let striped = striped && decorate;
+
+    // 補齊到固定寬度：空白加在重設序列之後，不帶顏色；較長的行由 --chop 決定是否截斷
+    // 斑馬紋的行沒有指定寬度時補到輸出寬度（扣掉行號欄），讓背景延伸到行尾
+    let pad_width = options.pad_to_width.or_else(|| {
+        striped.then(|| {
+            options
+                .zebra_width
+                .saturating_sub(gutter_width(number, location, options))
+        })
+    });
+    if let Some(width) = pad_width.filter(|_| decorate) {
+        let visible = ansi::visible_width(&content);
+        if visible < width {
+            content.push_str(&" ".repeat(width - visible));
+        }
+    }
+
+    // 背景疊加在語法顏色上，補齊的空白一併上色；行號欄不上色
+    if striped {
+        content = ansi::overlay(&content, ZEBRA_STYLE);
+    }
+
+    // 最後才降色，截斷標記、斑馬紋與直通內容中的顏色也一併處理
+    if options.flatten_ansi {
+        content = ansi::flatten_to_16(&content);
+    }
+
+    let prefix = options.line_prefix.as_deref().filter(|_| decorate);
+    let suffix = options.line_suffix.as_deref().filter(|_| decorate);
+
+    // 折行：寬度扣掉行號欄與裝飾，續列以空白縮排到內容的第一欄
+    let mut rows = Vec::new();
+    let mut indent = 0;
+    if let Some(width) = options.wrap.filter(|_| decorate) {
+        let gutter = gutter_width(number, location, options);
+        rows = ansi::wrap(&content, width.saturating_sub(gutter).max(1));
+        content = rows.remove(0);
+        indent = gutter - suffix.map_or(0, ansi::visible_width);
+    }
+
+    if options.prefix_position == PrefixPosition::Before {
+        write!(out, "{}", prefix.unwrap_or_default())?;
+    }
+
+    // 號碼由 display_number 決定；-b 模式下的空行只輸出空白的欄位，保持對齊
+    match (location, number) {
+        (Some(path), Some(n)) => write!(out, "{}:{}:", path, n)?,
+        (None, Some(n)) => write!(
+            out,
+            "{:>width$}{}",
+            n,
+            NUMBER_SEPARATOR,
+            width = number_width(n)
+        )?,
+        (None, None) if options.number_nonblank => write!(
+            out,
+            "{:width$}{}",
+            "",
+            NUMBER_SEPARATOR,
+            width = MIN_NUMBER_WIDTH
+        )?,
+        _ => {}
+    }
+
+    if options.prefix_position == PrefixPosition::After {
+        write!(out, "{}", prefix.unwrap_or_default())?;
+    }
+
+    // 高亮內容以重設序列結尾，`$` 與後綴不會沾到顏色；
+    // `$` 寫在整個行尾（包含 CRLF 的 `\r`）之前，沒有換行的最後一行也會加上
+    let end_marker = if options.show_ends && decorate {
+        "$"
+    } else {
+        ""
+    };
+    write!(out, "{}", content)?;
+    for row in rows {
+        write!(out, "\n{:indent$}{}", "", row, indent = indent)?;
+    }
+    write!(
+        out,
+        "{}{}{}",
+        end_marker,
+        suffix.unwrap_or_default(),
+        line_ending
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi::strip_escapes;
+    use crate::logging::{self, Level};
+    use std::io::Cursor;
+
+    fn render(content: &str, options: &PrintOptions) -> String {
+        let mut out = Vec::new();
+        write_content(Cursor::new(content), &mut out, None, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let bytes = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR ok";
+        let mut out = Vec::new();
+        write_hexdump(&mut out, bytes, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+             00000010  20 6f 6b                                          | ok|\n\
+             00000013\n"
+        );
+
+        // 上色時可列印字元與 `.` 的顏色不同，十六進位欄不變
+        let mut out = Vec::new();
+        write_hexdump(&mut out, b"A\x00", true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "00000000  41 00{}  |\x1b[32mA\x1b[0m\x1b[90m.\x1b[0m|\n00000002\n",
+                " ".repeat(43)
+            )
+        );
+
+        let mut out = Vec::new();
+        write_hexdump(&mut out, b"", false).unwrap();
+        assert_eq!(out, b"00000000\n");
+    }
+
+    #[test]
+    fn test_final_newline_preserved_in_all_modes() {
+        let modes = [
+            PrintOptions {
+                enable_highlighting: false,
+                ..Default::default()
+            },
+            PrintOptions {
+                language: Some("rust".to_string()),
+                true_color: Some(true),
+                ..Default::default()
+            },
+            PrintOptions {
+                show_line_numbers: true,
+                ..Default::default()
+            },
+            PrintOptions {
+                number_nonblank: true,
+                show_ends: true,
+                ..Default::default()
+            },
+            PrintOptions {
+                locate: true,
+                show_nonprinting: true,
+                ..Default::default()
+            },
+            PrintOptions {
+                chop: Some(4),
+                ..Default::default()
+            },
+            PrintOptions {
+                wrap: Some(4),
+                pad_to_width: Some(12),
+                line_suffix: Some(" <".to_string()),
+                ..Default::default()
+            },
+            PrintOptions {
+                collapse_repeats: true,
+                ..Default::default()
+            },
+            PrintOptions {
+                sample: Some(2),
+                sample_ends: true,
+                ..Default::default()
+            },
+            PrintOptions {
+                line_ranges: vec!["1:3".parse().unwrap()],
+                highlight_lines: vec!["1:".parse().unwrap()],
+                ..Default::default()
+            },
+            PrintOptions {
+                escapes: Some(Escapes::ALL),
+                ..Default::default()
+            },
+        ];
+
+        for options in &modes {
+            for input in ["no newline", "no newline\nno newline", "a\\nb"] {
+                let output = render(input, options);
+                assert!(!output.ends_with('\n'), "{:?} with {:?}", output, options);
+
+                let output = render(&format!("{}\n", input), options);
+                assert!(output.ends_with('\n'), "{:?} with {:?}", output, options);
+            }
+        }
+
+        // 重複的最後一行沒有換行：合併後同樣沒有
+        let collapse = &modes[7];
+        assert_eq!(render("a\na", collapse), "a (x2)");
+        assert_eq!(render("a\na\r\n", collapse), "a (x2)\r\n");
+    }
+
+    #[test]
+    fn test_print_streaming() {
+        let content = "line 1\nline 2\nline 3\n";
+        let reader = Cursor::new(content);
+        // 這個測試主要確保函數不會 panic
+        let options = PrintOptions {
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        print_content_streaming(reader, &mut out, None, &options).unwrap();
+        assert_eq!(out, content.as_bytes());
+    }
+
+    #[test]
+    fn test_line_number_padding_grows() {
+        let options = PrintOptions {
+            show_line_numbers: true,
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let content: String = (1..=10_001).map(|i| format!("{}\n", i)).collect();
+        let output = render(&content, &options);
+        let lines: Vec<&str> = output.lines().collect();
+
+        // 最小寬度 4，之後隨位數增加
+        assert_eq!(lines[0], "   1 │ 1");
+        assert_eq!(lines[9_998], "9999 │ 9999");
+        assert_eq!(lines[10_000], "10001 │ 10001");
+    }
+
+    #[test]
+    fn test_print_streaming_with_line_numbers() {
+        let options = PrintOptions {
+            show_line_numbers: true,
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            render("line 1\nline 2\nline 3\n", &options),
+            "   1 │ line 1\n   2 │ line 2\n   3 │ line 3\n"
+        );
+    }
+
+    #[test]
+    fn test_expand_tabs_after_highlighting() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            tab_width: 4,
+            expand_tabs_in_output: true,
+            ..Default::default()
+        };
+        let output = render("\tlet x = 1;\nab\tc\n", &options);
+
+        // 輸出已上色，但 tab 已依欄位展開
+        assert!(output.contains("\x1b["));
+        assert!(!output.contains('\t'));
+        let lines: Vec<String> = output.lines().map(strip_escapes).collect();
+        assert_eq!(lines, vec!["    let x = 1;", "ab  c"]);
+
+        // 行號不佔用 tab 的欄位：tab stop 從內容開頭起算
+        let numbered = PrintOptions {
+            show_line_numbers: true,
+            ..options
+        };
+        let output = render("a\tb\n\tc\n", &numbered);
+        let lines: Vec<String> = output.lines().map(strip_escapes).collect();
+        assert_eq!(lines, vec!["   1 │ a   b", "   2 │     c"]);
+    }
+
+    #[test]
+    fn test_tabs_width() {
+        // --tabs 4
+        let options = PrintOptions {
+            enable_highlighting: false,
+            tab_width: 4,
+            expand_tabs_in_output: true,
+            ..Default::default()
+        };
+        assert_eq!(render("ab\tc\n", &options), "ab  c\n");
+        assert_eq!(
+            render("abcd\te\n\t\tf\n", &options),
+            "abcd    e\n        f\n"
+        );
+
+        // 加上 -n 時 tab stop 不因行號前綴而位移
+        let numbered = PrintOptions {
+            show_line_numbers: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            render("ab\tc\nabcd\te\n", &numbered),
+            "   1 │ ab  c\n   2 │ abcd    e\n"
+        );
+
+        // --tabs 0：tab 原樣輸出（main 會關閉展開；寬度 0 本身也不展開）
+        let passthrough = PrintOptions {
+            tab_width: 0,
+            expand_tabs_in_output: false,
+            ..options.clone()
+        };
+        assert_eq!(render("ab\tc\n", &passthrough), "ab\tc\n");
+        let zero_width = PrintOptions {
+            tab_width: 0,
+            ..options
+        };
+        assert_eq!(render("ab\tc\n", &zero_width), "ab\tc\n");
+    }
+
+    #[test]
+    fn test_chop_with_custom_marker() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            chop: Some(10),
+            truncate_marker: "[...]".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("0123456789abcdef\nshort\n", &options),
+            "01234[...]\nshort\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_mode_from_str() {
+        assert_eq!("auto".parse(), Ok(WrapMode::Auto));
+        assert_eq!("Never".parse(), Ok(WrapMode::Never));
+        assert_eq!("40".parse(), Ok(WrapMode::Columns(40)));
+        assert!("0".parse::<WrapMode>().is_err());
+        assert!("wide".parse::<WrapMode>().is_err());
+    }
+
+    #[test]
+    fn test_wrap_long_lines() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            wrap: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("abcdefghijklmnopqrstuvwxyz\nshort\n", &options),
+            "abcdefghij\nklmnopqrst\nuvwxyz\nshort\n"
+        );
+
+        // 有行號時續列對齊內容的第一欄，`$` 只在最後一列
+        let numbered = PrintOptions {
+            wrap: Some(16),
+            show_line_numbers: true,
+            show_ends: true,
+            ..options
+        };
+        assert_eq!(
+            render("abcdefghijklmnopqrstuvwxyz\n", &numbered),
+            "   1 │ abcdefghi\n       jklmnopqr\n       stuvwxyz$\n"
+        );
+
+        // 高亮的顏色跨列延續
+        let highlighted = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            wrap: Some(12),
+            ..Default::default()
+        };
+        let output = render("let s = \"a long string literal\";\n", &highlighted);
+        let rows: Vec<&str> = output.lines().collect();
+        assert!(rows.len() > 1);
+        assert!(rows.iter().all(|row| ansi::visible_width(row) <= 12));
+        assert!(rows[1].starts_with("\x1b["));
+    }
+
+    #[test]
+    fn test_pad_to_width() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            pad_to_width: Some(20),
+            line_suffix: Some("|".to_string()),
+            ..Default::default()
+        };
+        // 寬字元佔兩欄；後綴加在補齊的空白之後
+        assert_eq!(
+            render(
+                "short
+中文
+",
+                &options
+            ),
+            format!("short{}|\n中文{}|\n", " ".repeat(15), " ".repeat(16))
+        );
+
+        // 較長的行不截斷，搭配 --chop 才會被切到固定寬度
+        let long = "x".repeat(25) + "\n";
+        assert_eq!(render(&long, &options), long.replace('\n', "|\n"));
+        let chopped = PrintOptions {
+            chop: Some(20),
+            ..options
+        };
+        assert_eq!(render(&long, &chopped), format!("{}›|\n", "x".repeat(19)));
+    }
+
+    #[test]
+    fn test_reveal_bidi_override() {
+        let line = "let access = \"user\u{202E} \u{2066}// admin\u{2069}\";\n";
+        let options = PrintOptions {
+            enable_highlighting: false,
+            reveal_bidi: true,
+            ..Default::default()
+        };
+
+        let (output, messages) = logging::capture(Level::Normal, || render(line, &options));
+        assert!(!output.contains('\u{202E}'));
+        assert_eq!(
+            strip_escapes(&output),
+            "let access = \"user<U+202E> <U+2066>// admin<U+2069>\";\n"
+        );
+        assert!(output.contains("\x1b[7m<U+202E>\x1b[27m"));
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("3 bidirectional or zero-width"));
+
+        // 未開啟時內容不變，但仍會警告
+        let plain = PrintOptions {
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let (output, messages) = logging::capture(Level::Normal, || render(line, &plain));
+        assert_eq!(output, line);
+        assert!(messages[0].contains("--reveal-bidi"));
+    }
+
+    #[test]
+    fn test_changes_print_only_differing_lines() {
+        let reference = "fn a() {}\nfn b() {}\nfn c() {}\n";
+        let content = "fn a() {}\nfn b() { 1 }\nfn c() {}\nfn d() {}\n";
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            changes: Some(crate::diff::changed_lines(reference, content)),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(content, &options),
+            "~    2 │ fn b() { 1 }\n+    4 │ fn d() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_unicode() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            normalize: Some(Normalization::Nfc),
+            ..Default::default()
+        };
+        // e + U+0301（組合重音）→ U+00E9
+        assert_eq!(render("caf\u{65}\u{301}\n", &options), "caf\u{e9}\n");
+
+        let options = PrintOptions {
+            normalize: Some(Normalization::Nfkd),
+            ..options
+        };
+        assert_eq!(render("\u{e9}\u{ff21}", &options), "\u{65}\u{301}A");
+    }
+
+    #[test]
+    fn test_interpret_escapes() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            escapes: Some(Escapes::ALL),
+            ..Default::default()
+        };
+        assert_eq!(render("a\\tb\n", &options), "a\tb\n");
+
+        // 已是實際字元的部分不受影響，`\\n` 是反斜線加上 n
+        assert_eq!(
+            interpret_escapes("\t\\x41\\\\n\\q\\x4", Escapes::ALL),
+            "\tA\\n\\q\\x4"
+        );
+
+        // 只啟用部分序列；未啟用的 `\\` 整組保留
+        let escapes: Escapes = "t".parse().unwrap();
+        assert_eq!(interpret_escapes("\\t\\n\\\\t", escapes), "\t\\n\\\\t");
+    }
+
+    #[test]
+    fn test_escaped_newline_continues_line() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            escapes: Some(Escapes::ALL),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("a\\nb\nc\n", &options),
+            "   1 │ a\n   1 │ b\n   2 │ c\n"
+        );
+    }
+
+    #[test]
+    fn test_leading_bom_is_not_hidden_control() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            ..Default::default()
+        };
+        let (output, messages) =
+            logging::capture(Level::Normal, || render("\u{FEFF}a\nb\u{FEFF}\n", &options));
+        assert_eq!(output, "\u{FEFF}a\nb\u{FEFF}\n");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("1 bidirectional or zero-width"));
+        assert!(messages[0].contains("first on line 2"));
+    }
+
+    #[test]
+    fn test_show_nonprinting() {
+        assert_eq!(
+            show_nonprinting("a\x01b\x7f\tc\u{85}", false),
+            "a^Ab^?\tcM-^E"
+        );
+        assert_eq!(show_nonprinting("\x1b[31mx\x1b[0m", false), "^[[31mx^[[0m");
+
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_nonprinting: true,
+            ..Default::default()
+        };
+        assert_eq!(render("\x01\x7f\r\n", &options), "^A^?\r\n");
+
+        // 搭配 -E 時 CRLF 顯示為 `^M$`
+        let options = PrintOptions {
+            show_ends: true,
+            ..options
+        };
+        assert_eq!(render("\x01\r\nx", &options), "^A^M$\nx$");
+
+        // 搭配 --preserve-ansi：跳脫序列保留，其餘控制字元照樣顯示
+        assert_eq!(
+            show_nonprinting("\x1b[1;31merr\x1b[0m\x01\x7f", true),
+            "\x1b[1;31merr\x1b[0m^A^?"
+        );
+        let preserved = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            enable_highlighting: true,
+            show_ends: false,
+            preserve_ansi: true,
+            ..options
+        };
+        assert_eq!(
+            render("\x1b[31merror\x1b[0m\x07: x\n", &preserved),
+            "\x1b[31merror\x1b[0m^G: x\n"
+        );
+    }
+
+    #[test]
+    fn test_sample_every_nth_line() {
+        let content: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            sample: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&content, &options),
+            "   3 │ line 3\n   6 │ line 6\n   9 │ line 9\n"
+        );
+
+        let with_ends = PrintOptions {
+            sample_ends: true,
+            ..options
+        };
+        assert_eq!(
+            render(&content, &with_ends),
+            "   1 │ line 1\n   3 │ line 3\n   6 │ line 6\n   9 │ line 9\n  10 │ line 10\n"
+        );
+    }
+
+    #[test]
+    fn test_line_range_from_str() {
+        assert_eq!(
+            "3:5".parse(),
+            Ok(LineRange {
+                start: 3,
+                end: Some(5)
+            })
+        );
+        assert_eq!(
+            ":50".parse(),
+            Ok(LineRange {
+                start: 1,
+                end: Some(50)
+            })
+        );
+        assert_eq!(
+            "100:".parse(),
+            Ok(LineRange {
+                start: 100,
+                end: None
+            })
+        );
+        assert!("5".parse::<LineRange>().is_err());
+        assert!("0:3".parse::<LineRange>().is_err());
+        assert!("5:3".parse::<LineRange>().is_err());
+    }
+
+    #[test]
+    fn test_line_ranges_union() {
+        let content: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            line_ranges: vec!["2:3".parse().unwrap(), "9:".parse().unwrap()],
+            ..Default::default()
+        };
+        // 行號維持原檔位置
+        assert_eq!(
+            render(&content, &options),
+            "   2 │ line 2\n   3 │ line 3\n   9 │ line 9\n  10 │ line 10\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight_lines() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            highlight_lines: vec![
+                LineRange::parse_line_or_range("2").unwrap(),
+                LineRange::parse_line_or_range("4:").unwrap(),
+            ],
+            ..Default::default()
+        };
+        // 行號不反白，只有內容
+        assert_eq!(
+            render("a\nb\nc\nd\n", &options),
+            "   1 │ a\n   2 │ \x1b[7mb\x1b[0m\n   3 │ c\n   4 │ \x1b[7md\x1b[0m\n"
+        );
+        assert!(LineRange::parse_line_or_range("0").is_err());
+
+        // 語法高亮的重設序列之後重新套用反白
+        let highlighted = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            highlight_lines: vec![LineRange::parse_line_or_range("1").unwrap()],
+            ..Default::default()
+        };
+        let output = render("let x = 1;\n", &highlighted);
+        assert!(output.starts_with("\x1b[7m"));
+        assert_eq!(
+            output.matches("\x1b[0m").count(),
+            output.matches("\x1b[0m\x1b[7m").count() + 1
+        );
+        assert_eq!(strip_escapes(&output), "let x = 1;\n");
+    }
+
+    #[test]
+    fn test_max_output_lines() {
+        let content: String = (1..=8).map(|n| format!("line {}\n", n)).collect();
+        let limited = || PrintOptions {
+            enable_highlighting: false,
+            output_limit: OutputLimit::new(Some(5)),
+            ..Default::default()
+        };
+        // 提示寫到 stderr，不混進輸出
+        let (output, messages) = logging::capture(Level::Normal, || render(&content, &limited()));
+        assert_eq!(output, "line 1\nline 2\nline 3\nline 4\nline 5\n");
+        assert_eq!(
+            messages,
+            ["... output truncated at 5 lines, use --max-output-lines 0 to disable"]
+        );
+
+        // 剛好 5 行時沒有提示；上限只計算實際輸出的行
+        let (output, messages) =
+            logging::capture(Level::Normal, || render(&content[..35], &limited()));
+        assert_eq!(output, "line 1\nline 2\nline 3\nline 4\nline 5\n");
+        assert!(messages.is_empty());
+        let ranged = PrintOptions {
+            line_ranges: vec!["4:".parse().unwrap()],
+            ..limited()
+        };
+        assert_eq!(
+            render(&content, &ranged),
+            "line 4\nline 5\nline 6\nline 7\nline 8\n"
+        );
+
+        // 上限是所有檔案合計：複製的選項共用計數，第二個檔案只輸出剩下的行數，只提示一次
+        let first = limited();
+        let second = first.clone();
+        let (output, messages) = logging::capture(Level::Normal, || {
+            render("a\nb\nc\n", &first) + &render("d\ne\nf\n", &second)
+        });
+        assert_eq!(output, "a\nb\nc\nd\ne\n");
+        assert_eq!(messages.len(), 1);
+        assert!(second.output_limit.is_exhausted());
+    }
+
+    #[test]
+    fn test_zebra() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            zebra: true,
+            zebra_width: 12,
+            ..Default::default()
+        };
+        // 偶數行加上背景並補齊到輸出寬度（扣掉 7 欄的行號欄），行號不上色
+        assert_eq!(
+            render("a\nb\nc\nd", &options),
+            format!(
+                "   1 │ a\n   2 │ {z}b    \x1b[0m\n   3 │ c\n   4 │ {z}d    \x1b[0m",
+                z = "\x1b[48;5;236m"
+            )
+        );
+
+        // 降色時背景也降為 16 色
+        let flattened = PrintOptions {
+            enable_highlighting: false,
+            zebra: true,
+            zebra_width: 3,
+            flatten_ansi: true,
+            ..Default::default()
+        };
+        let output = render("a\nb\n", &flattened);
+        assert!(
+            output.starts_with("a\n\x1b[40mb  \x1b[0m\n"),
+            "{:?}",
+            output
+        );
+
+        // 依輸出的行交替，而不是原檔的行號：取樣只輸出偶數行時仍一行有一行沒有
+        let sampled = PrintOptions {
+            enable_highlighting: false,
+            zebra: true,
+            zebra_width: 1,
+            sample: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("1\n2\n3\n4\n5\n6\n7\n", &sampled),
+            "2\n\x1b[48;5;236m4\x1b[0m\n6\n"
+        );
+    }
+
+    #[test]
+    fn test_line_range_keeps_highlight_state() {
+        // 範圍從多行字串中間開始：前面的行仍經過高亮器，可見的行維持字串顏色
+        let content = "let s = \"first\nsecond\";\nlet t = 1;\n";
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            ..Default::default()
+        };
+        let full = render(content, &options);
+        let sliced = render(
+            content,
+            &PrintOptions {
+                line_ranges: vec!["2:2".parse().unwrap()],
+                ..options
+            },
+        );
+        assert_eq!(sliced, full.lines().nth(1).unwrap().to_string() + "\n");
+    }
+
+    #[test]
+    fn test_compact_blank_runs() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            compact_blank: Some(2),
+            ..Default::default()
+        };
+        // 被壓縮掉的行不佔用行號（GNU cat -sn）
+        assert_eq!(
+            render("a\n\n\n\n\n\nb\n\nc\n", &options),
+            "   1 │ a\n   2 │ \n   3 │ \n   4 │ b\n   5 │ \n   6 │ c\n"
+        );
+
+        // --squeeze-blank 等同於 N = 1
+        let squeeze = PrintOptions {
+            show_line_numbers: false,
+            compact_blank: Some(1),
+            ..options
+        };
+        assert_eq!(render("a\n\r\n\nb\n", &squeeze), "a\n\r\nb\n");
+        assert_eq!(render("a\n  \n\t\n\nb\n", &squeeze), "a\n  \nb\n");
+
+        // -s：只有空白字元的行也算空行，整段只留第一行
+        assert_eq!(render("a\n \n\t\n\nb\n", &squeeze), "a\n \nb\n");
+        // -s -n：被壓縮掉的行不編號，之後的行號接續
+        let numbered = PrintOptions {
+            show_line_numbers: true,
+            ..squeeze.clone()
+        };
+        assert_eq!(
+            render("a\n \n\t\n\nb\n", &numbered),
+            "   1 │ a\n   2 │  \n   3 │ b\n"
+        );
+        // -s -b：被壓縮掉的空白行同樣不計入非空行的編號
+        let nonblank = PrintOptions {
+            number_nonblank: true,
+            ..squeeze.clone()
+        };
+        assert_eq!(
+            render("a\n \n\t\n\nb\n", &nonblank),
+            "   1 │ a\n   2 │  \n   3 │ b\n"
+        );
+        // --locate 仍指向原檔位置
+        let located = PrintOptions {
+            locate: true,
+            ..squeeze.clone()
+        };
+        assert_eq!(render("a\n \n\t\n\nb\n", &located), "-:1:a\n-:2: \n-:5:b\n");
+
+        // 被壓縮的行仍經過高亮器，之後的語法狀態與未壓縮時相同
+        let rust = PrintOptions {
+            language: Some("rust".to_string()),
+            compact_blank: Some(1),
+            ..Default::default()
+        };
+        let source = "/*\n\n   \n\n*/\nfn main() {}\n";
+        let squeezed = render(source, &rust);
+        let full = render(
+            source,
+            &PrintOptions {
+                compact_blank: None,
+                ..rust
+            },
+        );
+        assert_eq!(strip_escapes(&squeezed), "/*\n\n*/\nfn main() {}\n");
+        assert_eq!(squeezed.lines().last(), full.lines().last());
+    }
+
+    #[test]
+    fn test_force_syntax_path_keeps_plain_text() {
+        let content = "a < b && c\t\"d\" \\ 中文\n\nlast\n";
+        let options = PrintOptions {
+            force_syntax_path: true,
+            ..Default::default()
+        };
+        let output = render(content, &options);
+
+        // 純文字也產生了跳脫序列，但內容不變
+        assert!(output.contains("\x1b["));
+        assert_eq!(strip_escapes(&output), content);
+
+        let shortcut = PrintOptions::default();
+        assert_eq!(render(content, &shortcut), content);
+    }
+
+    #[test]
+    fn test_join_lines_with_separator() {
+        let options = PrintOptions {
+            join: Some(",".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(render("a\nb\nc\n", &options), "a,b,c\n");
+
+        // 去掉頭尾空白並略過空行
+        let trimmed = PrintOptions {
+            join: Some(" ".to_string()),
+            join_trim: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render("{\n  \"a\": 1,\n\n  \"b\": 2\n}\n", &trimmed),
+            "{ \"a\": 1, \"b\": 2 }\n"
+        );
+    }
+
+    #[test]
+    fn test_locate_prefix() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            locate: true,
+            ..Default::default()
+        };
+        assert_eq!(render("a\nb\n", &options), "-:1:a\n-:2:b\n");
+
+        let mut out = Vec::new();
+        let path = Path::new("src/lib.rs");
+        write_content(Cursor::new("x\n"), &mut out, Some(path), &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "src/lib.rs:1:x\n");
+    }
+
+    #[test]
+    fn test_line_prefix_and_suffix() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            line_prefix: Some("> ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(render("a\nb\nc\n", &options), "> a\n> b\n> c\n");
+
+        // 前綴相對於行號的位置
+        let numbered = PrintOptions {
+            show_line_numbers: true,
+            line_suffix: Some(" <".to_string()),
+            ..options.clone()
+        };
+        assert_eq!(render("a\nb", &numbered), ">    1 │ a <\n>    2 │ b <");
+        let after = PrintOptions {
+            prefix_position: PrefixPosition::After,
+            ..numbered
+        };
+        assert_eq!(render("a\n", &after), "   1 │ > a <\n");
+    }
+
+    #[test]
+    fn test_reflow_long_comment() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            reflow_comments: Some(24),
+            ..Default::default()
+        };
+        let source = "    // the quick brown fox jumps over the lazy dog\n\
+                      let s = \"// not a comment but quite a long string\";\n";
+        let output = strip_escapes(&render(source, &options));
+        assert_eq!(
+            output,
+            concat!(
+                "    // the quick brown\n",
+                "    // fox jumps over\n",
+                "    // the lazy dog\n",
+                "let s = \"// not a comment but quite a long string\";\n"
+            )
+        );
+
+        // 行號佔用的寬度也計入
+        let numbered = PrintOptions {
+            show_line_numbers: true,
+            ..options
+        };
+        let python = PrintOptions {
+            language: Some("python".to_string()),
+            ..numbered
+        };
+        assert_eq!(
+            strip_escapes(&render("# a b c d e f g h i j k", &python)),
+            "   1 │ # a b c d e f g h\n   1 │ # i j k"
+        );
+    }
+
+    #[test]
+    fn test_highlight_urls() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            highlight_urls: true,
+            ..Default::default()
+        };
+        // 句尾的句點不算在網址內
+        assert_eq!(
+            render("see https://example.com/a?b=1. done\n", &options),
+            "see \x1b[4mhttps://example.com/a?b=1\x1b[0m. done\n"
+        );
+        assert_eq!(render("no links here\n", &options), "no links here\n");
+
+        let linked = PrintOptions {
+            hyperlinks: true,
+            ..options
+        };
+        assert_eq!(
+            render("(mailto:me@example.com)\n", &linked),
+            "(\x1b]8;;mailto:me@example.com\x1b\\\x1b[4mmailto:me@example.com\x1b[0m\x1b]8;;\x1b\\)\n"
+        );
+
+        // 疊加在語法高亮上，前後的顏色保留
+        let highlighted = "\x1b[38;5;2m\"http://a.b/c\"\x1b[0m";
+        assert_eq!(
+            highlight_urls(highlighted, false).unwrap(),
+            "\x1b[38;5;2m\"\x1b[4mhttp://a.b/c\x1b[0m\x1b[38;5;2m\"\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let pattern = Regex::new("fo+").unwrap();
+        let options = PrintOptions {
+            enable_highlighting: false,
+            matches: Some(pattern.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("foo bar fooo\nnone\n", &options),
+            "\x1b[1;7mfoo\x1b[0m bar \x1b[1;7mfooo\x1b[0m\nnone\n"
+        );
+
+        // 跨越原本的顏色：重設之後重新套用樣式，結束後恢復最後一個顏色
+        let highlighted = "\x1b[38;5;1mxf\x1b[0m\x1b[38;5;2mooy\x1b[0m";
+        assert_eq!(
+            highlight_matches(highlighted, &pattern).unwrap(),
+            "\x1b[38;5;1mx\x1b[1;7mf\x1b[0m\x1b[1;7m\x1b[38;5;2m\x1b[1;7moo\x1b[0m\x1b[38;5;2my\x1b[0m"
+        );
+        assert_eq!(highlight_matches("bar", &pattern), None);
+
+        // 不分大小寫由呼叫端建立樣式時決定
+        let ignore_case = regex::RegexBuilder::new("foo")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            strip_escapes(&highlight_matches("FOO", &ignore_case).unwrap()),
+            "FOO"
+        );
+    }
+
+    #[test]
+    fn test_highlight_timestamps() {
+        let pattern = Regex::new(DEFAULT_TIMESTAMP_PATTERN).unwrap();
+        let options = PrintOptions {
+            enable_highlighting: false,
+            timestamps: Some(pattern.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "2024-05-01T12:00:00.123Z INFO started\nno timestamp\n",
+                &options
+            ),
+            "\x1b[36m2024-05-01T12:00:00.123Z\x1b[0m INFO started\nno timestamp\n"
+        );
+
+        // 疊加在高亮結果上：時間戳記之後恢復原本的顏色
+        let highlighted = "\x1b[38;5;1m2024-05-01 12:00:00 x\x1b[0m";
+        assert_eq!(
+            highlight_timestamp(highlighted, &pattern).unwrap(),
+            "\x1b[36m2024-05-01 12:00:00\x1b[0m\x1b[38;5;1m x\x1b[0m"
+        );
+        assert_eq!(
+            highlight_timestamp("May  1 09:15:02 host sshd[1]: ok", &pattern).unwrap(),
+            "\x1b[36mMay  1 09:15:02\x1b[0m host sshd[1]: ok"
+        );
+        assert_eq!(
+            highlight_timestamp("[2024-05-01 12:00] x", &pattern).unwrap(),
+            "\x1b[36m[2024-05-01 12:00]\x1b[0m x"
+        );
+        // 只認行首
+        assert_eq!(highlight_timestamp("at 2024-05-01 12:00", &pattern), None);
+    }
+
+    #[test]
+    fn test_highlight_levels() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            levels: Some(LevelColors::new(None, false).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("x ERROR boom\nerrors are fine\n", &options),
+            "x \x1b[31mERROR\x1b[0m boom\nerrors are fine\n"
+        );
+
+        // 整行上色，並與時間戳記組合
+        let options = PrintOptions {
+            levels: Some(LevelColors::new(None, true).unwrap()),
+            timestamps: Some(Regex::new(DEFAULT_TIMESTAMP_PATTERN).unwrap()),
+            ..options
+        };
+        assert_eq!(
+            render("2024-05-01 12:00 error: boom\n", &options),
+            "\x1b[36m2024-05-01 12:00\x1b[0m\x1b[31m error: boom\x1b[0m\n"
+        );
+
+        // 自訂對應：覆蓋預設並加入新的關鍵字
+        let levels = LevelColors::new(Some("error=1;35, Notice=cyan"), false).unwrap();
+        assert_eq!(
+            levels.decorate("\x1b[38;5;1mnotice: Error\x1b[0m").unwrap(),
+            "\x1b[36mnotice\x1b[0m\x1b[38;5;1m: Error\x1b[0m"
+        );
+        assert_eq!(levels.decorate("Error").unwrap(), "\x1b[1;35mError\x1b[0m");
+        assert!(LevelColors::new(Some("error"), false).is_err());
+        assert!(LevelColors::new(Some("error=nope"), false).is_err());
+    }
+
+    #[test]
+    fn test_reveal_in_strings() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            reveal_in_strings: true,
+            ..Default::default()
+        };
+        let line = "let a\u{200B} = \"x\u{200B}y\";\n";
+        let (output, _) = logging::capture(Level::Quiet, || render(line, &options));
+
+        // 字串中的零寬空格被標出，程式碼中的保持原樣
+        assert_eq!(strip_escapes(&output), "let a\u{200B} = \"x<U+200B>y\";\n");
+        assert!(output.contains("\x1b[7m<U+200B>\x1b[27m"));
+
+        // 沒有語法高亮時沒有 scope 可用，不標出
+        let plain = PrintOptions {
+            enable_highlighting: false,
+            ..options
+        };
+        let (output, _) = logging::capture(Level::Quiet, || render(line, &plain));
+        assert_eq!(output, line);
+    }
+
+    #[test]
+    fn test_debug_highlight_state() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            debug_highlight: true,
+            ..Default::default()
+        };
+        let content = "let a = 1;\nlet s = \"open;\nlet b = 2;\n";
+        let (output, messages) = logging::capture(Level::Normal, || render(content, &options));
+
+        // 輸出不受影響，每行一筆狀態
+        assert_eq!(strip_escapes(&output), content);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], "[HIGHLIGHT] line 1: depth 1 [source.rust]");
+        // 未結束的字串從第 2 行起一直留在堆疊中
+        for message in &messages[1..] {
+            assert!(message.contains("string.quoted.double"), "{}", message);
+        }
+        assert!(messages[2].starts_with("[HIGHLIGHT] line 3: depth 2"));
+    }
+
+    #[test]
+    fn test_collapse_repeats() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            show_line_numbers: true,
+            collapse_repeats: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "start\nretry\nretry\nretry\nretry\n\n\ndone\ndone",
+                &options
+            ),
+            "   1 │ start\n   2 │ retry (x4)\n   6 │ \n   7 │ \n   8 │ done (x2)"
+        );
+
+        // 高亮只套用在輸出的那一行，次數接在高亮內容之後
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            collapse_repeats: true,
+            ..Default::default()
+        };
+        let output = render("let a = 1;\nlet a = 1;\nlet a = 1;\nlet a = 1;\n", &options);
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with("\x1b[38;2;"), "{:?}", output);
+        assert_eq!(strip_escapes(&output), "let a = 1; (x4)\n");
+    }
+
+    #[test]
+    fn test_preserve_ansi_lines() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            true_color: Some(true),
+            preserve_ansi: true,
+            ..Default::default()
+        };
+        let colored = "\x1b[1;31merror\x1b[0m: fn main\n";
+        let output = render(&format!("{}fn main() {{}}\n", colored), &options);
+        let lines: Vec<&str> = output.split_inclusive('\n').collect();
+
+        // 自帶顏色的行原樣輸出，純程式碼的行被高亮
+        assert_eq!(lines[0], colored);
+        assert!(lines[1].starts_with("\x1b[38;2;"), "{:?}", lines[1]);
+        assert_eq!(strip_escapes(lines[1]), "fn main() {}\n");
+    }
+
+    #[test]
+    fn test_number_nonblank() {
+        let options = PrintOptions {
+            enable_highlighting: false,
+            number_nonblank: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render("a\n\n  \nb\n", &options),
+            "   1 │ a\n     │ \n   2 │   \n   3 │ b\n"
+        );
+
+        // -b 優先於 -n
+        let both = PrintOptions {
+            show_line_numbers: true,
+            ..options
+        };
+        assert_eq!(render("a\n\nb", &both), "   1 │ a\n     │ \n   2 │ b");
+    }
+
+    #[test]
+    fn test_line_length_histogram() {
+        let content = "\na\nabc\nabcd\n中文\r\nabcdefgh";
+
+        let chars =
+            LineLengthHistogram::from_reader(Cursor::new(content), LengthUnit::Chars).unwrap();
+        // 0 | 1 | 2-3 (abc, 中文) | 4-7 (abcd) | 8-15 (abcdefgh)
+        assert_eq!(chars.buckets, vec![1, 1, 2, 1, 1]);
+        assert_eq!((chars.lines, chars.min, chars.max), (6, 0, 8));
+
+        // 以位元組計算時，中文佔 6 位元組
+        let bytes =
+            LineLengthHistogram::from_reader(Cursor::new(content), LengthUnit::Bytes).unwrap();
+        assert_eq!(bytes.buckets, vec![1, 1, 1, 2, 1]);
+
+        let report = chars.to_string();
+        assert!(report.starts_with("lines: 6, min: 0, max: 8, mean: 3.0\n"));
+        assert!(report.contains("        2-3 |       2 ########################################\n"));
+        assert!(report.contains("       8-15 |       1 ####################\n"));
+    }
+
+    #[test]
+    fn test_show_ends() {
+        let options = PrintOptions {
+            language: Some("rust".to_string()),
+            show_ends: true,
+            ..Default::default()
+        };
+        let output = render("let a = 1;\r\n\nlet b = 2;", &options);
+        let lines: Vec<&str> = output.split_inclusive('\n').collect();
+
+        // `$` 在高亮的重設序列之後、行尾之前
+        assert!(lines[0].ends_with("\x1b[0m$\r\n"), "{:?}", lines[0]);
+        assert_eq!(lines[1], "$\n");
+        assert!(lines[2].ends_with("\x1b[0m$"));
+        assert_eq!(strip_escapes(&output), "let a = 1;$\r\n$\nlet b = 2;$");
+    }
+
+    #[test]
+    fn test_empty_file_prints_nothing() {
+        assert_eq!(render("", &PrintOptions::default()), "");
+
+        let decorated = PrintOptions {
+            show_line_numbers: true,
+            locate: true,
+            join: Some(",".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(render("", &decorated), "");
+        // 只有換行的檔案不算空檔案
+        assert_eq!(render("\n", &decorated), "-:1:\n");
+    }
+
+    #[test]
+    fn test_warn_long_lines() {
+        let content = format!("short\n{}\nend\n", "x".repeat(2 * 1024 * 1024));
+        let options = PrintOptions {
+            enable_highlighting: false,
+            long_line_limit: Some(64 * 1024),
+            ..Default::default()
+        };
+
+        let (output, messages) = logging::capture(Level::Normal, || render(&content, &options));
+        assert_eq!(output, content);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("1 line(s) longer than 65536 bytes, first on line 2"));
+
+        let truncate = PrintOptions {
+            long_line_limit: Some(8),
+            truncate_long_lines: true,
+            language: Some("rust".to_string()),
+            enable_highlighting: true,
+            ..options
+        };
+        let (output, messages) = logging::capture(Level::Normal, || {
+            render("let s = \"中文中文\";\nok\n", &truncate)
+        });
+        assert_eq!(strip_escapes(&output), "let s = ›\nok\n");
+        assert!(messages[0].contains("(truncated)"));
+    }
+
+    #[test]
+    fn test_strip_shebang_keeps_detection() {
+        let script = "#!/usr/bin/env python3\ndef main():\n    pass\n";
+        let options = PrintOptions {
+            strip_shebang: true,
+            show_line_numbers: true,
+            ..Default::default()
+        };
+        let output = render(script, &options);
+
+        assert!(!output.contains("#!"));
+        // 仍以 shebang 偵測為 Python 並上色
+        assert!(output.contains("\x1b["));
+        assert_eq!(
+            strip_escapes(&output),
+            "   2 │ def main():\n   3 │     pass\n"
+        );
+
+        // 沒有 shebang 時第一行照常輸出
+        let plain = PrintOptions {
+            enable_highlighting: false,
+            strip_shebang: true,
+            ..Default::default()
+        };
+        assert_eq!(render("echo hi\n", &plain), "echo hi\n");
     }
 }
@@ -0,0 +1,101 @@
+use crate::encoder::DetectedEncoding;
+use serde::Serialize;
+
+/// 單一檔案的統計資料
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub encoding: String,
+    pub confidence: String,
+    pub lines: usize,
+    pub bytes: usize, // 解碼後的 UTF-8 位元組數
+    pub had_errors: bool,
+    pub syntax: String,
+}
+
+impl FileReport {
+    /// 從解碼後的內容建立統計資料
+    pub fn new(path: &str, content: &str, detected: &DetectedEncoding, syntax: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            encoding: detected.encoding.name().to_string(),
+            confidence: format!("{:?}", detected.confidence),
+            lines: content.lines().count(),
+            bytes: content.len(),
+            had_errors: detected.had_errors,
+            syntax: syntax.to_string(),
+        }
+    }
+}
+
+/// 所有檔案的合計
+#[derive(Debug, Default, Serialize)]
+pub struct Totals {
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: usize,
+    pub files_with_errors: usize,
+}
+
+/// --summary-json 輸出的完整文件
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub files: Vec<FileReport>,
+    pub totals: Totals,
+}
+
+impl Summary {
+    pub fn new(files: Vec<FileReport>) -> Self {
+        let totals = files.iter().fold(Totals::default(), |mut totals, file| {
+            totals.files += 1;
+            totals.lines += file.lines;
+            totals.bytes += file.bytes;
+            totals.files_with_errors += usize::from(file.had_errors);
+            totals
+        });
+
+        Self { files, totals }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("summary is always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncodingConfidence;
+
+    fn detected(had_errors: bool) -> DetectedEncoding {
+        DetectedEncoding {
+            encoding: encoding_rs::UTF_8,
+            confidence: EncodingConfidence::High,
+            had_errors,
+            error_offsets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_totals_are_sum_of_files() {
+        let files = vec![
+            FileReport::new("a.rs", "fn a() {}\n", &detected(false), "Rust"),
+            FileReport::new("b.txt", "one\ntwo\nthree\n", &detected(true), "Plain Text"),
+            FileReport::new("c.txt", "", &detected(false), "Plain Text"),
+        ];
+        let expected_lines: usize = files.iter().map(|f| f.lines).sum();
+        let expected_bytes: usize = files.iter().map(|f| f.bytes).sum();
+
+        let summary = Summary::new(files);
+
+        assert_eq!(summary.totals.files, 3);
+        assert_eq!(summary.totals.lines, expected_lines);
+        assert_eq!(summary.totals.lines, 4);
+        assert_eq!(summary.totals.bytes, expected_bytes);
+        assert_eq!(summary.totals.files_with_errors, 1);
+
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json()).unwrap();
+        assert_eq!(json["totals"]["lines"], 4);
+        assert_eq!(json["files"][0]["syntax"], "Rust");
+    }
+}
@@ -0,0 +1,136 @@
+// 將高亮後的內容匯出為 SVG 圖片（使用主題的顏色）
+
+use crate::ansi;
+use crate::highlighter::Highlighter;
+use anyhow::Result;
+use std::fmt::Write;
+use std::path::Path;
+use syntect::highlighting::{Color, FontStyle, Style};
+
+const FONT_SIZE: f64 = 14.0;
+const LINE_HEIGHT: f64 = 20.0;
+const CHAR_WIDTH: f64 = FONT_SIZE * 0.6; // 等寬字型的半形字元寬度
+const PADDING: f64 = 16.0;
+
+/// 將內容高亮後輸出為 SVG：每個樣式片段對應一個 `<tspan>`
+pub fn render(
+    content: &str,
+    file_path: Option<&Path>,
+    theme: Option<&str>,
+    language: Option<&str>,
+    invert_theme: bool,
+) -> Result<String> {
+    let mut highlighter = Highlighter::new(theme, true)?;
+    if invert_theme {
+        highlighter = highlighter.with_inverted_colors();
+    }
+    let lines = highlighter.styled_lines(content, file_path, language)?;
+    let (foreground, background) = highlighter.theme_colors();
+
+    // 畫布大小依行數與最寬的一行（CJK 全形字元佔兩欄）
+    let columns = lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|(_, text)| ansi::visible_width(text))
+                .sum::<usize>()
+        })
+        .max()
+        .unwrap_or(0);
+    let width = PADDING * 2.0 + columns as f64 * CHAR_WIDTH;
+    let height = PADDING * 2.0 + lines.len() as f64 * LINE_HEIGHT;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(background)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<g font-family="monospace" font-size="{}" fill="{}" xml:space="preserve">"#,
+        FONT_SIZE,
+        hex(foreground)
+    );
+
+    for (i, line) in lines.iter().enumerate() {
+        let y = PADDING + (i as f64 + 0.75) * LINE_HEIGHT;
+        let _ = write!(svg, r#"<text x="{}" y="{}">"#, PADDING, y);
+        for (style, text) in line {
+            let _ = write!(svg, "<tspan{}>{}</tspan>", attributes(style), escape(text));
+        }
+        svg.push_str("</text>\n");
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    Ok(svg)
+}
+
+/// 片段的填色與字型樣式
+fn attributes(style: &Style) -> String {
+    let mut attrs = format!(r#" fill="{}""#, hex(style.foreground));
+    if style.font_style.contains(FontStyle::BOLD) {
+        attrs.push_str(r#" font-weight="bold""#);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        attrs.push_str(r#" font-style="italic""#);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        attrs.push_str(r#" text-decoration="underline""#);
+    }
+    attrs
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// XML 跳脫（tab 轉為空白，避免在 SVG 中被壓縮）
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push_str("    "),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg_colors_rust_keyword() {
+        let svg = render(
+            "fn main() {\n    let s = \"<&>\";\n}\n",
+            Some(Path::new("main.rs")),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<text ").count(), 3);
+        // base16-eighties 的關鍵字顏色
+        assert!(
+            svg.contains(r##"<tspan fill="#cc99cc">fn</tspan>"##),
+            "{}",
+            svg
+        );
+        assert!(svg.contains("&lt;&amp;&gt;"));
+    }
+}
@@ -0,0 +1,301 @@
+// 終端機相關資訊（輸出寬度、色彩能力等）
+
+use once_cell::sync::OnceCell;
+use std::fmt;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+/// 無法偵測時使用的寬度
+pub const FALLBACK_WIDTH: usize = 80;
+
+/// 輸出寬度的來源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidthSource {
+    Env,      // COLUMNS 環境變數
+    Terminal, // 從 stdout 的終端機查詢
+    Fallback, // 預設值
+}
+
+impl fmt::Display for WidthSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            WidthSource::Env => "COLUMNS",
+            WidthSource::Terminal => "terminal",
+            WidthSource::Fallback => "fallback",
+        })
+    }
+}
+
+/// 取得輸出寬度與其來源（COLUMNS 優先，其次是終端機，最後是 80）
+pub fn output_width() -> (usize, WidthSource) {
+    let terminal = std::io::stdout()
+        .is_terminal()
+        .then(|| terminal_size::terminal_size().map(|(width, _)| width.0 as usize))
+        .flatten();
+
+    resolve_width(std::env::var("COLUMNS").ok().as_deref(), terminal)
+}
+
+/// 依 COLUMNS 的值與終端機寬度決定輸出寬度（不讀取環境，方便測試）
+pub fn resolve_width(columns: Option<&str>, terminal: Option<usize>) -> (usize, WidthSource) {
+    if let Some(width) = columns
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|&width| width > 0)
+    {
+        return (width, WidthSource::Env);
+    }
+
+    match terminal {
+        Some(width) if width > 0 => (width, WidthSource::Terminal),
+        _ => (FALLBACK_WIDTH, WidthSource::Fallback),
+    }
+}
+
+/// 探測終端機回應的等待上限（不支援查詢的終端機可能完全不回應）
+const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// 以 DECRQSS 查詢剛設定的 24-bit 背景色，並以 DA1 作為結尾：
+/// 所有終端機都會回應 DA1，收到它就代表不會再有其他回應
+const TRUE_COLOR_QUERY: &[u8] = b"\x1b[48;2;1;2;3m\x1bP$qm\x1b\\\x1b[0m\x1b[c";
+
+/// 向終端機查詢是否支援 24-bit 真彩色（結果會快取，整個程式只查詢一次）
+///
+/// 無法查詢（沒有終端機、逾時、非 Unix）時回傳 None，由呼叫端回退到環境變數判斷
+pub fn probe_true_color() -> Option<bool> {
+    static PROBED: OnceCell<Option<bool>> = OnceCell::new();
+    *PROBED.get_or_init(|| {
+        let reply = query_tty(TRUE_COLOR_QUERY, PROBE_TIMEOUT)?;
+        debug!(
+            "Terminal reply to true color probe: {:?}",
+            String::from_utf8_lossy(&reply)
+        );
+        parse_true_color_reply(&reply)
+    })
+}
+
+/// 解析探測回應：DECRQSS 回報的 SGR 保留了 RGB 值代表支援真彩色
+///
+/// 只收到 DA1 代表終端機不支援 DECRQSS（視為不支援），兩者都沒有則無法判斷
+fn parse_true_color_reply(reply: &[u8]) -> Option<bool> {
+    let reply = String::from_utf8_lossy(reply);
+
+    // DECRQSS 回應：ESC P 1 $ r <SGR> m ESC \\
+    if let Some((_, sgr)) = reply.split_once("\x1bP1$r") {
+        let sgr = &sgr[..sgr.find('m').unwrap_or(sgr.len())];
+        if sgr.contains(":1:2:3") || sgr.contains(";1;2;3") {
+            return Some(true);
+        }
+    }
+
+    has_da1(&reply).then_some(false)
+}
+
+/// 是否已收到 DA1 回應（ESC [ ? ... c）
+fn has_da1(reply: &str) -> bool {
+    reply
+        .find("\x1b[?")
+        .is_some_and(|start| reply[start..].contains('c'))
+}
+
+/// 以 OSC 11 查詢終端機背景色，同樣以 DA1 作為結尾
+const BACKGROUND_QUERY: &[u8] = b"\x1b]11;?\x1b\\\x1b[c";
+
+/// 終端機背景是否為淺色（結果會快取）：先看 COLORFGBG，再以 OSC 11 查詢
+///
+/// 兩者都無法判斷時回傳 None，由呼叫端使用深色主題
+pub fn background_is_light() -> Option<bool> {
+    static DETECTED: OnceCell<Option<bool>> = OnceCell::new();
+    *DETECTED.get_or_init(|| {
+        if let Some(light) = std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| parse_colorfgbg(&value))
+        {
+            debug!("Background from COLORFGBG: light = {}", light);
+            return Some(light);
+        }
+        let reply = query_tty(BACKGROUND_QUERY, PROBE_TIMEOUT)?;
+        debug!(
+            "Terminal reply to background query: {:?}",
+            String::from_utf8_lossy(&reply)
+        );
+        parse_background_reply(&reply)
+    })
+}
+
+/// 解析 COLORFGBG（`前景;背景` 或 `前景;default;背景`）：背景為 0–6 或 8 是深色
+fn parse_colorfgbg(value: &str) -> Option<bool> {
+    let background = value.rsplit(';').next()?.trim().parse::<u8>().ok()?;
+    Some(!matches!(background, 0..=6 | 8))
+}
+
+/// 解析 OSC 11 回應 `ESC ] 11 ; rgb:RRRR/GGGG/BBBB`：相對亮度超過一半視為淺色
+fn parse_background_reply(reply: &[u8]) -> Option<bool> {
+    let reply = String::from_utf8_lossy(reply);
+    let (_, rgb) = reply.split_once("\x1b]11;rgb:")?;
+    let rgb = &rgb[..rgb.find(['\x07', '\x1b']).unwrap_or(rgb.len())];
+
+    // 每個分量為 1–4 位十六進位數字，換算成 0.0–1.0
+    let channels = rgb
+        .split('/')
+        .map(|hex| {
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            let max = 16u32.checked_pow(u32::try_from(hex.len()).ok()?)? - 1;
+            (max > 0).then(|| f64::from(value) / f64::from(max))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    Some(0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5)
+}
+
+/// 將查詢寫到 /dev/tty 並讀取回應，直到收到 DA1 或逾時
+#[cfg(unix)]
+fn query_tty(query: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return None;
+    }
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let fd = tty.as_raw_fd();
+
+    // 關閉回顯與行緩衝，讓回應不會顯示在畫面上且可以立即讀到
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return None;
+    }
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    let mut reply = Vec::new();
+    let deadline = Instant::now() + timeout;
+    if tty.write_all(query).and_then(|_| tty.flush()).is_ok() {
+        let mut buf = [0u8; 256];
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut pollfd, 1, remaining.as_millis() as libc::c_int) };
+            if ready <= 0 {
+                break;
+            }
+            match tty.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => reply.extend_from_slice(&buf[..n]),
+            }
+            // DA1 是最後一個回應
+            if has_da1(&String::from_utf8_lossy(&reply)) && reply.ends_with(b"c") {
+                break;
+            }
+        }
+    }
+
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    (!reply.is_empty()).then_some(reply)
+}
+
+#[cfg(not(unix))]
+fn query_tty(_query: &[u8], _timeout: Duration) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_true_color_reply() {
+        // 支援：DECRQSS 回報完整的 RGB，接著是 DA1
+        assert_eq!(
+            parse_true_color_reply(b"\x1bP1$r0;48:2::1:2:3m\x1b\\\x1b[?62;22c"),
+            Some(true)
+        );
+        assert_eq!(
+            parse_true_color_reply(b"\x1bP1$r48;2;1;2;3m\x1b\\\x1b[?1;2c"),
+            Some(true)
+        );
+        // 終端機把顏色降為 256 色
+        assert_eq!(
+            parse_true_color_reply(b"\x1bP1$r48;5;16m\x1b\\\x1b[?62c"),
+            Some(false)
+        );
+        // 不支援 DECRQSS，只回應 DA1
+        assert_eq!(parse_true_color_reply(b"\x1b[?6c"), Some(false));
+        // 沒有回應或回應不完整
+        assert_eq!(parse_true_color_reply(b""), None);
+        assert_eq!(parse_true_color_reply(b"\x1bP1$r48:2"), None);
+        assert_eq!(parse_true_color_reply(b"\x1bP1$r"), None);
+        assert_eq!(parse_true_color_reply(b"\x1bP1$r\xff"), None);
+        // SGR 的第一個位元組也要保留
+        assert_eq!(parse_true_color_reply(b"\x1bP1$r;1;2;3m\x1b\\"), Some(true));
+    }
+
+    #[test]
+    fn test_parse_colorfgbg() {
+        assert_eq!(parse_colorfgbg("15;0"), Some(false));
+        assert_eq!(parse_colorfgbg("0;15"), Some(true));
+        assert_eq!(parse_colorfgbg("0;default;7"), Some(true));
+        assert_eq!(parse_colorfgbg("7;8"), Some(false));
+        assert_eq!(parse_colorfgbg("default;default"), None);
+        assert_eq!(parse_colorfgbg(""), None);
+    }
+
+    #[test]
+    fn test_parse_background_reply() {
+        // 白底（以 ST 結尾），接著是 DA1
+        assert_eq!(
+            parse_background_reply(b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\\x1b[?62c"),
+            Some(true)
+        );
+        // 深色背景（以 BEL 結尾，兩位數分量）
+        assert_eq!(
+            parse_background_reply(b"\x1b]11;rgb:2d/2d/2d\x07\x1b[?1;2c"),
+            Some(false)
+        );
+        // 不支援 OSC 11，只回應 DA1
+        assert_eq!(parse_background_reply(b"\x1b[?6c"), None);
+        assert_eq!(parse_background_reply(b"\x1b]11;rgb:ffff/ffff"), None);
+        // 紅色分量的第一位數字也要計入：只有藍色拉高亮度時仍超過一半
+        assert_eq!(
+            parse_background_reply(b"\x1b]11;rgb:8000/8000/ffff\x07"),
+            Some(true)
+        );
+        assert_eq!(parse_background_reply(b"\x1b]11;rgb:f/f/f\x07"), Some(true));
+        // 逾時的不完整回應：在前綴後立即結束，或接著無效的 UTF-8
+        assert_eq!(parse_background_reply(b"\x1b]11;rgb:"), None);
+        assert_eq!(parse_background_reply(b"\x1b]11;rgb:\xff"), None);
+    }
+
+    #[test]
+    fn test_resolve_width_sources() {
+        assert_eq!(
+            resolve_width(Some("120"), Some(100)),
+            (120, WidthSource::Env)
+        );
+        assert_eq!(resolve_width(None, Some(100)), (100, WidthSource::Terminal));
+        // 無效的 COLUMNS 會被忽略
+        assert_eq!(
+            resolve_width(Some("abc"), None),
+            (80, WidthSource::Fallback)
+        );
+        assert_eq!(
+            resolve_width(Some("0"), Some(90)),
+            (90, WidthSource::Terminal)
+        );
+    }
+}